@@ -0,0 +1,89 @@
+use crate::types::CaptureDef;
+use anyhow::{Context, Result, bail};
+use regex::Regex;
+
+/// Extract the value declared by a step's `capture` from the step's stdout.
+///
+/// Without `regex` or `json` the whole stdout (trimmed) is captured.
+pub fn extract(capture: &CaptureDef, stdout: &str) -> Result<String> {
+    match (&capture.regex, &capture.json) {
+        (Some(_), Some(_)) => bail!(
+            "capture for '{}' sets both 'regex' and 'json'; use one",
+            capture.var
+        ),
+        (Some(pattern), None) => extract_regex(pattern, capture.group.unwrap_or(1), stdout),
+        (None, Some(path)) => extract_json(path, stdout),
+        (None, None) => Ok(stdout.trim().to_string()),
+    }
+}
+
+fn extract_regex(pattern: &str, group: usize, stdout: &str) -> Result<String> {
+    let re = Regex::new(pattern).with_context(|| format!("invalid capture regex '{pattern}'"))?;
+    let caps = re
+        .captures(stdout)
+        .ok_or_else(|| anyhow::anyhow!("capture regex '{pattern}' did not match output"))?;
+    let group = if re.captures_len() > 1 { group } else { 0 };
+    caps.get(group)
+        .map(|m| m.as_str().to_string())
+        .ok_or_else(|| anyhow::anyhow!("capture regex '{pattern}' has no group {group}"))
+}
+
+fn extract_json(path: &str, stdout: &str) -> Result<String> {
+    let root: serde_json::Value =
+        serde_json::from_str(stdout).context("capture expected JSON output")?;
+    let mut current = &root;
+    for segment in parse_json_path(path)? {
+        let next = match &segment {
+            PathSegment::Key(key) => current.get(key.as_str()),
+            PathSegment::Index(idx) => current.get(*idx),
+        };
+        current = next.ok_or_else(|| {
+            anyhow::anyhow!("JSON path '{path}' not found in output (missing {segment})")
+        })?;
+    }
+    Ok(match current {
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    })
+}
+
+enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+impl std::fmt::Display for PathSegment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PathSegment::Key(key) => write!(f, "key '{key}'"),
+            PathSegment::Index(idx) => write!(f, "index {idx}"),
+        }
+    }
+}
+
+/// Parse a dotted JSON path with optional indexes: `items[0].name`, `[1]`, `$.a.b`.
+fn parse_json_path(path: &str) -> Result<Vec<PathSegment>> {
+    let trimmed = path.strip_prefix('$').unwrap_or(path);
+    let mut segments = Vec::new();
+    for part in trimmed.split('.').filter(|p| !p.is_empty()) {
+        let (key, mut rest) = match part.find('[') {
+            Some(pos) => (&part[..pos], &part[pos..]),
+            None => (part, ""),
+        };
+        if !key.is_empty() {
+            segments.push(PathSegment::Key(key.to_string()));
+        }
+        while !rest.is_empty() {
+            let end = rest
+                .find(']')
+                .filter(|_| rest.starts_with('['))
+                .ok_or_else(|| anyhow::anyhow!("invalid JSON path '{path}'"))?;
+            let idx = rest[1..end]
+                .parse::<usize>()
+                .with_context(|| format!("invalid index in JSON path '{path}'"))?;
+            segments.push(PathSegment::Index(idx));
+            rest = &rest[end + 1..];
+        }
+    }
+    Ok(segments)
+}
@@ -1,4 +1,5 @@
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
 
 #[derive(Parser)]
 #[command(
@@ -17,11 +18,22 @@ EXAMPLES:
   tforge new my-app                  Create a project interactively
   tforge new my-app --ai \"flutter app with firebase\"
                                      Create with LLM assistance
+  tforge new my-app -t axum-server --set port=8080 --yes
+                                     Create without prompts (CI)
   tforge list                        Show all available templates
   tforge search firebase             Search templates by keyword
   tforge add https://github.com/user/template.git
                                      Add a community template
+  tforge install gcp-cloudrun        Install a template from a registry index
+  tforge add-template firebase-flutter
+                                     Add a template to the current project
+  tforge new my-app -t axum-server --dry-run
+                                     Show the commands without running them
+  tforge plan                        Show what `tforge resume` would run
   tforge resume                      Retry from last failed step
+  tforge apply                       Converge the project to an edited tforge.toml
+  tforge log gcp-cloudsql            Show recorded output of a template's steps
+  tforge destroy --dry-run           Show the teardown commands
   tforge config llm                  Configure LLM provider"
 )]
 pub struct Cli {
@@ -37,38 +49,155 @@ pub enum Commands {
 Create a new project directory and scaffold it from one or more templates.\n\n\
 In interactive mode (default), tforge walks you through template selection \
 and parameter configuration. With --ai, a natural language description is \
-interpreted by an LLM to select templates automatically.",
+interpreted by an LLM to select templates automatically.\n\n\
+With --template, template selection is skipped. Values from --var-file and \
+--set are used as-is; with --yes, remaining parameters take their manifest \
+defaults, a required parameter without a value is an error, and the \
+execution confirmation is skipped.\n\n\
+With --dry-run, the fully rendered execution plan is printed and nothing is \
+executed or written.\n\n\
+With --jobs N, templates that do not depend on each other (through \
+requires_templates or captured variables) run concurrently, up to N at a time.",
         after_help = "\
 EXAMPLES:
   tforge new my-app
-  tforge new my-app --ai \"flutter app with firebase auth and GCP backend\""
+  tforge new my-app --ai \"flutter app with firebase auth and GCP backend\"
+  tforge new my-app -t gcp-project --set gcp_project_id=my-app-dev --yes
+  tforge new my-app -t flutter-app -t axum-server --var-file vars.toml --yes
+  tforge new my-app -t gcp-cloudsql --var-file vars.toml --dry-run --json
+  tforge new my-app -t flutter-app -t axum-server -t gcp-project --jobs 3"
     )]
-    New {
-        /// Project name (used as directory name)
-        name: String,
-        /// Natural language project description for LLM-assisted setup
-        #[arg(long, value_name = "DESCRIPTION")]
-        ai: Option<String>,
+    New(NewArgs),
+    /// Show the rendered execution plan for the current project
+    #[command(
+        long_about = "\
+Resolve the template order from tforge.toml, evaluate every step condition \
+and print the fully rendered commands in execution order. Steps that would \
+be skipped are listed with the reason. Steps already completed in \
+.tforge-state.json are skipped, as `tforge resume` would. Nothing is executed.",
+        after_help = "\
+EXAMPLES:
+  tforge plan
+  tforge plan --json"
+    )]
+    Plan {
+        /// Print the plan as JSON
+        #[arg(long)]
+        json: bool,
     },
+    /// Converge the current project to its tforge.toml
+    #[command(
+        long_about = "\
+Read tforge.toml, which may have been edited to add templates or change \
+parameters, and bring the project in line with it. Templates required by the \
+listed ones are added, only parameters without a value are prompted for, and \
+only steps that never ran, failed, were added or changed by a template \
+update, or whose rendered inputs changed are executed.\n\n\
+The plan is shown and confirmed before anything runs. tforge.toml is \
+updated with added templates and newly answered parameters, and tforge.lock \
+with the templates' current versions. Templates \
+removed from tforge.toml are not torn down; use `tforge destroy -t <name>` \
+before removing them.",
+        after_help = "\
+EXAMPLES:
+  tforge apply
+  tforge apply --dry-run
+  tforge apply --yes --jobs 4"
+    )]
+    Apply(ApplyArgs),
+    /// Add templates to the current project and run only their steps
+    #[command(
+        name = "add-template",
+        long_about = "\
+Add one or more templates to the project in the current directory. The \
+parameters in tforge.toml are reused, templates required by the new ones are \
+added too, and only parameters without a value are prompted for. Only the \
+steps of the added templates run; tforge.toml and .tforge-state.json are \
+updated.\n\n\
+Not to be confused with `tforge add`, which caches a template from git.",
+        after_help = "\
+EXAMPLES:
+  tforge add-template firebase-flutter
+  tforge add-template gcp-cloudsql --set db_tier=db-f1-micro --yes
+  tforge add-template gcp-appengine --dry-run"
+    )]
+    AddTemplate(AddTemplateArgs),
     /// Resume execution from the last failed step
     #[command(
         long_about = "\
 Resume a previously interrupted or failed project setup. Reads \
-.tforge-state.json and retries from the first failure.",
+.tforge-state.json and retries from the first failure.\n\n\
+Completed steps whose rendered command, working directory, check or \
+variables changed since they ran (for example after editing parameters in \
+tforge.toml) are listed and run again, unless --ignore-drift is given.\n\n\
+Resume refuses to run when a template's version or files no longer match \
+tforge.lock (for example after `tforge update`). Review the changes with \
+`tforge apply`, or pass --ignore-lock to continue with the current templates \
+and update tforge.lock.",
+        after_help = "\
+EXAMPLES:
+  tforge resume
+  tforge resume --jobs 4
+  tforge resume --ignore-drift
+  tforge resume --ignore-lock"
+    )]
+    Resume {
+        /// Run up to N independent templates at the same time
+        #[arg(long, short = 'j', value_name = "N", default_value_t = 1)]
+        jobs: usize,
+        /// Skip completed steps even if their rendered inputs changed
+        #[arg(long)]
+        ignore_drift: bool,
+        /// Continue even if templates no longer match tforge.lock, and update it
+        #[arg(long)]
+        ignore_lock: bool,
+    },
+    /// Tear down provisioned resources using the templates' destroy steps
+    #[command(
+        long_about = "\
+Run the [[destroy]] steps of every template that has completed steps in \
+.tforge-state.json, in reverse dependency order. Templates without destroy \
+steps are left untouched. After a template's destroy steps succeed, its \
+step state is cleared so it would be provisioned again by `tforge resume`.\n\n\
+Asks for confirmation unless --yes is given. --dry-run prints the rendered \
+destroy commands without running them.",
         after_help = "\
 EXAMPLES:
-  tforge resume"
+  tforge destroy --dry-run
+  tforge destroy -t gcp-cloudsql
+  tforge destroy --yes"
     )]
-    Resume,
+    Destroy(DestroyArgs),
     /// Show the current project's execution state
     #[command(long_about = "\
 Display which templates and steps have been executed, failed, or are \
 pending. Reads from .tforge-state.json in the current directory.")]
     Status,
+    /// Show the recorded runs of a template's steps
+    #[command(
+        long_about = "\
+Print what .tforge-state.json recorded about the last run of each step of a \
+template: start and end time, duration, attempts, exit code, the rendered \
+command and working directory, the last lines of stdout/stderr, the error and \
+the tforge version that ran it. Nothing is executed.",
+        after_help = "\
+EXAMPLES:
+  tforge log gcp-cloudsql            Every step of gcp-cloudsql
+  tforge log gcp-cloudsql 2          Only step 2
+  tforge log gcp-cloudsql create-instance
+                                     The step with id = \"create-instance\""
+    )]
+    Log {
+        /// Template name
+        template: String,
+        /// Step number (1-based) or step id; all steps when omitted
+        step: Option<String>,
+    },
     /// List all available templates (bundled and installed)
     #[command(long_about = "\
 Show all templates including bundled templates shipped with the binary \
-and community templates added via `tforge add`.")]
+and community templates added via `tforge add` or `tforge install`, followed \
+by the templates of the configured registry indexes that are not installed.")]
     List,
     /// Search the template registry by keyword
     #[command(
@@ -87,18 +216,47 @@ EXAMPLES:
     #[command(
         long_about = "\
 Clone a template repository into the local cache. The repository must \
-contain a template.toml at its root.",
+contain a template.toml at its root, or in the directory given with --path, \
+so one repository can host many templates.\n\n\
+With --ref, the template is checked out at a tag, branch or commit. \
+`tforge update` pulls templates that follow a branch (the default branch \
+when --ref is omitted) and leaves templates pinned to a tag or commit alone.",
         after_help = "\
 EXAMPLES:
-  tforge add https://github.com/user/my-template.git"
+  tforge add https://github.com/user/my-template.git
+  tforge add https://github.com/team/templates.git --path gcp/cloudrun --ref v1.4.0
+  tforge add https://github.com/team/templates.git --path flutter/app --ref main"
     )]
     Add {
         /// Git URL of the template repository
         url: String,
+        /// Tag, branch or commit to check out
+        #[arg(long = "ref", value_name = "REF")]
+        git_ref: Option<String>,
+        /// Directory of the template inside the repository
+        #[arg(long, value_name = "SUBDIR")]
+        path: Option<String>,
+    },
+    /// Install a template listed in a registry index
+    #[command(
+        long_about = "\
+Download a template listed in one of the registry indexes configured under \
+`registries` in ~/.config/tforge/config.toml into the local cache. Git \
+entries are cloned at their ref; tarball entries are verified against their \
+sha256 before they are unpacked. `tforge list` and `tforge search` show the \
+index templates that are not installed yet.",
+        after_help = "\
+EXAMPLES:
+  tforge install gcp-cloudrun"
+    )]
+    Install {
+        /// Template name from a registry index
+        name: String,
     },
     /// Update cached community templates
     #[command(long_about = "\
-Fetch the latest version of cached community templates via git pull.")]
+Fetch the latest version of cached community templates via git pull. \
+Templates added with --ref pointing at a tag or commit stay pinned.")]
     Update,
     /// Configure tforge settings
     #[command(
@@ -120,3 +278,93 @@ EXAMPLES:
         show: bool,
     },
 }
+
+#[derive(Args)]
+pub struct NewArgs {
+    /// Project name (used as directory name)
+    pub name: String,
+    /// Natural language project description for LLM-assisted setup
+    #[arg(long, value_name = "DESCRIPTION")]
+    pub ai: Option<String>,
+    /// Template to include (repeatable); skips interactive template selection
+    #[arg(long = "template", short = 't', value_name = "NAME")]
+    pub templates: Vec<String>,
+    /// Set a parameter value (repeatable)
+    #[arg(long = "set", value_name = "KEY=VALUE")]
+    pub set: Vec<String>,
+    /// Load parameter values from a TOML or JSON file
+    #[arg(long, value_name = "PATH")]
+    pub var_file: Option<PathBuf>,
+    /// Never prompt: use parameter defaults and skip the confirmation
+    #[arg(long, short = 'y')]
+    pub yes: bool,
+    /// Print the rendered execution plan instead of running it
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Print the dry-run plan as JSON
+    #[arg(long, requires = "dry_run")]
+    pub json: bool,
+    /// Run up to N independent templates at the same time
+    #[arg(long, short = 'j', value_name = "N", default_value_t = 1)]
+    pub jobs: usize,
+}
+
+#[derive(Args)]
+pub struct AddTemplateArgs {
+    /// Templates to add
+    #[arg(required = true, value_name = "NAME")]
+    pub templates: Vec<String>,
+    /// Set a parameter value (repeatable)
+    #[arg(long = "set", value_name = "KEY=VALUE")]
+    pub set: Vec<String>,
+    /// Load parameter values from a TOML or JSON file
+    #[arg(long, value_name = "PATH")]
+    pub var_file: Option<PathBuf>,
+    /// Never prompt: use parameter defaults and skip the confirmation
+    #[arg(long, short = 'y')]
+    pub yes: bool,
+    /// Print the plan instead of running it
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Print the dry-run plan as JSON
+    #[arg(long, requires = "dry_run")]
+    pub json: bool,
+    /// Run up to N independent templates at the same time
+    #[arg(long, short = 'j', value_name = "N", default_value_t = 1)]
+    pub jobs: usize,
+}
+
+#[derive(Args)]
+pub struct ApplyArgs {
+    /// Never prompt: use parameter defaults and skip the confirmation
+    #[arg(long, short = 'y')]
+    pub yes: bool,
+    /// Print the plan instead of running it
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Print the dry-run plan as JSON
+    #[arg(long, requires = "dry_run")]
+    pub json: bool,
+    /// Run up to N independent templates at the same time
+    #[arg(long, short = 'j', value_name = "N", default_value_t = 1)]
+    pub jobs: usize,
+    /// Skip completed steps even if their rendered inputs changed
+    #[arg(long)]
+    pub ignore_drift: bool,
+}
+
+#[derive(Args)]
+pub struct DestroyArgs {
+    /// Only destroy this template (repeatable)
+    #[arg(long = "template", short = 't', value_name = "NAME")]
+    pub templates: Vec<String>,
+    /// Print the rendered destroy plan instead of running it
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Print the dry-run plan as JSON
+    #[arg(long, requires = "dry_run")]
+    pub json: bool,
+    /// Skip the confirmation prompt
+    #[arg(long, short = 'y')]
+    pub yes: bool,
+}
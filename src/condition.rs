@@ -1,56 +1,532 @@
 use anyhow::{Result, bail};
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap};
 
-pub fn evaluate_condition(condition: &str, vars: &HashMap<String, String>) -> Result<bool> {
-    let condition = condition.trim();
+use crate::vars::Value;
 
-    if let Some((var, value)) = parse_contains(condition) {
-        let var_value = vars
-            .get(var)
-            .ok_or_else(|| anyhow::anyhow!("variable '{var}' not found"))?;
-        Ok(var_value.split(',').any(|v| v.trim() == value))
-    } else if let Some((var, value)) = parse_equals(condition) {
-        let var_value = vars
-            .get(var)
-            .ok_or_else(|| anyhow::anyhow!("variable '{var}' not found"))?;
-        Ok(var_value == value)
-    } else if let Some((var, value)) = parse_not_equals(condition) {
-        let var_value = vars
-            .get(var)
-            .ok_or_else(|| anyhow::anyhow!("variable '{var}' not found"))?;
-        Ok(var_value != value)
-    } else {
-        bail!("unsupported condition syntax: '{condition}'")
+/// Evaluate a `condition`/`when` expression against the current variables.
+///
+/// Grammar (lowest to highest precedence):
+///
+/// ```text
+/// expr       := and ("or" and)*
+/// and        := unary ("and" unary)*
+/// unary      := "not" unary | "(" expr ")" | comparison
+/// comparison := operand (("==" | "!=" | "contains") operand | "not"? "in" (list | operand))?
+/// list       := "[" (operand ("," operand)* ","?)? "]"
+/// operand    := word | 'string' | "string" | true | false
+/// ```
+///
+/// A bare word is a run of letters, digits, `_`, `-` and `.`; it is a number when the whole
+/// word is one (`-1`, `8.0`) and an identifier otherwise (`flutter-app`, `mysql-8.0`).
+///
+/// Values are compared in their string form (`deploy_now == 'true'` matches a bool
+/// parameter). A bare operand is truthy unless it is empty or `false`; an empty list is
+/// empty. An unquoted identifier on the right of `==`, `!=` or `contains`, or inside a
+/// list, that is not a known variable is compared as a literal (`db == postgres`,
+/// `services in [auth, billing]`), as conditions were before the expression grammar.
+pub fn evaluate_condition(condition: &str, vars: &HashMap<String, Value>) -> Result<bool> {
+    parse_condition(condition)?.evaluate(vars)
+}
+
+/// Parse a condition expression without evaluating it.
+pub fn parse_condition(condition: &str) -> Result<Condition> {
+    let tokens = tokenize(condition)?;
+    let mut parser = Parser {
+        source: condition,
+        tokens,
+        pos: 0,
+    };
+    let expr = parser.parse_or()?;
+    if let Some(token) = parser.peek() {
+        bail!(parser.error_at(token, &format!("unexpected {}", token.kind)));
     }
+    Ok(Condition { expr })
 }
 
-fn parse_contains(s: &str) -> Option<(&str, &str)> {
-    let parts: Vec<&str> = s.splitn(2, " contains ").collect();
-    if parts.len() == 2 {
-        Some((parts[0].trim(), strip_quotes(parts[1].trim())))
-    } else {
-        None
+/// A parsed condition expression.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    expr: Expr,
+}
+
+impl Condition {
+    pub fn evaluate(&self, vars: &HashMap<String, Value>) -> Result<bool> {
+        self.expr.evaluate(vars)
     }
+
+    /// Names of all variables referenced by the expression.
+    pub fn variables(&self) -> BTreeSet<String> {
+        let mut names = BTreeSet::new();
+        self.expr.collect_variables(&mut names);
+        names
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Or(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare(Operand, CompareOp, Operand),
+    In(Operand, Vec<Operand>),
+    InVar(Operand, Operand),
+    Truthy(Operand),
 }
 
-fn parse_equals(s: &str) -> Option<(&str, &str)> {
-    let parts: Vec<&str> = s.splitn(2, " == ").collect();
-    if parts.len() == 2 {
-        Some((parts[0].trim(), strip_quotes(parts[1].trim())))
-    } else {
-        None
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CompareOp {
+    Equals,
+    NotEquals,
+    Contains,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    Var(String),
+    Literal(String),
+    /// Unquoted identifier on the right of a comparison: the variable if it is set,
+    /// otherwise the identifier itself.
+    VarOrLiteral(String),
+}
+
+impl Operand {
+    fn resolve<'a>(&'a self, vars: &'a HashMap<String, Value>) -> Result<Cow<'a, str>> {
+        match self {
+            Operand::Var(name) => Operand::resolve_var(name, vars),
+            Operand::Literal(value) => Ok(Cow::Borrowed(value)),
+            Operand::VarOrLiteral(name) => match vars.get(name) {
+                Some(_) => Operand::resolve_var(name, vars),
+                None => Ok(Cow::Borrowed(name)),
+            },
+        }
+    }
+
+    fn resolve_var<'a>(name: &str, vars: &'a HashMap<String, Value>) -> Result<Cow<'a, str>> {
+        match lookup(vars, name)? {
+            Value::String(value) => Ok(Cow::Borrowed(value)),
+            value => Ok(Cow::Owned(value.to_string())),
+        }
+    }
+
+    /// Items of a list operand: a list variable's items, or a comma-separated string.
+    fn resolve_items<'a>(&'a self, vars: &'a HashMap<String, Value>) -> Result<Vec<Cow<'a, str>>> {
+        match self {
+            Operand::Var(name) => Ok(lookup(vars, name)?.items()),
+            Operand::VarOrLiteral(name) if vars.contains_key(name) => {
+                Ok(lookup(vars, name)?.items())
+            }
+            Operand::Literal(value) | Operand::VarOrLiteral(value) => Ok(value
+                .split(',')
+                .map(|item| Cow::Borrowed(item.trim()))
+                .collect()),
+        }
     }
 }
 
-fn parse_not_equals(s: &str) -> Option<(&str, &str)> {
-    let parts: Vec<&str> = s.splitn(2, " != ").collect();
-    if parts.len() == 2 {
-        Some((parts[0].trim(), strip_quotes(parts[1].trim())))
-    } else {
-        None
+fn lookup<'a>(vars: &'a HashMap<String, Value>, name: &str) -> Result<&'a Value> {
+    vars.get(name)
+        .ok_or_else(|| anyhow::anyhow!("variable '{name}' not found"))
+}
+
+impl Expr {
+    fn evaluate(&self, vars: &HashMap<String, Value>) -> Result<bool> {
+        match self {
+            Expr::Or(lhs, rhs) => Ok(lhs.evaluate(vars)? || rhs.evaluate(vars)?),
+            Expr::And(lhs, rhs) => Ok(lhs.evaluate(vars)? && rhs.evaluate(vars)?),
+            Expr::Not(inner) => Ok(!inner.evaluate(vars)?),
+            Expr::Compare(lhs, op, rhs) => {
+                let rhs = rhs.resolve(vars)?;
+                Ok(match op {
+                    CompareOp::Equals => lhs.resolve(vars)? == rhs,
+                    CompareOp::NotEquals => lhs.resolve(vars)? != rhs,
+                    CompareOp::Contains => lhs.resolve_items(vars)?.contains(&rhs),
+                })
+            }
+            Expr::In(needle, items) => {
+                let needle = needle.resolve(vars)?;
+                for item in items {
+                    if item.resolve(vars)? == needle {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            }
+            Expr::InVar(needle, haystack) => {
+                let needle = needle.resolve(vars)?;
+                Ok(haystack.resolve_items(vars)?.contains(&needle))
+            }
+            Expr::Truthy(operand) => {
+                let value = operand.resolve(vars)?;
+                Ok(!(value.is_empty() || value == "false"))
+            }
+        }
+    }
+
+    fn collect_variables(&self, names: &mut BTreeSet<String>) {
+        let mut add = |operand: &Operand| {
+            if let Operand::Var(name) | Operand::VarOrLiteral(name) = operand {
+                names.insert(name.clone());
+            }
+        };
+        match self {
+            Expr::Or(lhs, rhs) | Expr::And(lhs, rhs) => {
+                lhs.collect_variables(names);
+                rhs.collect_variables(names);
+            }
+            Expr::Not(inner) => inner.collect_variables(names),
+            Expr::Compare(lhs, _, rhs) | Expr::InVar(lhs, rhs) => {
+                add(lhs);
+                add(rhs);
+            }
+            Expr::In(needle, items) => {
+                add(needle);
+                items.iter().for_each(add);
+            }
+            Expr::Truthy(operand) => add(operand),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    Ident(String),
+    Str(String),
+    Number(String),
+    Eq,
+    NotEq,
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    Comma,
+}
+
+impl std::fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenKind::Ident(name) => write!(f, "'{name}'"),
+            TokenKind::Str(value) => write!(f, "string '{value}'"),
+            TokenKind::Number(value) => write!(f, "number {value}"),
+            TokenKind::Eq => write!(f, "'=='"),
+            TokenKind::NotEq => write!(f, "'!='"),
+            TokenKind::LParen => write!(f, "'('"),
+            TokenKind::RParen => write!(f, "')'"),
+            TokenKind::LBracket => write!(f, "'['"),
+            TokenKind::RBracket => write!(f, "']'"),
+            TokenKind::Comma => write!(f, "','"),
+        }
     }
 }
 
-fn strip_quotes(s: &str) -> &str {
-    s.trim_matches('\'').trim_matches('"')
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    /// Byte offset of the token start in the source expression.
+    offset: usize,
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+
+    while let Some(&(offset, ch)) = chars.peek() {
+        let kind = match ch {
+            c if c.is_whitespace() => {
+                chars.next();
+                continue;
+            }
+            '(' => TokenKind::LParen,
+            ')' => TokenKind::RParen,
+            '[' => TokenKind::LBracket,
+            ']' => TokenKind::RBracket,
+            ',' => TokenKind::Comma,
+            '=' | '!' => {
+                chars.next();
+                if chars.peek().map(|&(_, c)| c) != Some('=') {
+                    bail!(
+                        "expected '=' after '{ch}' at column {} in condition '{source}'",
+                        column(source, offset)
+                    );
+                }
+                chars.next();
+                tokens.push(Token {
+                    kind: if ch == '=' {
+                        TokenKind::Eq
+                    } else {
+                        TokenKind::NotEq
+                    },
+                    offset,
+                });
+                continue;
+            }
+            '\'' | '"' => {
+                chars.next();
+                let mut value = String::new();
+                let mut closed = false;
+                for (_, c) in chars.by_ref() {
+                    if c == ch {
+                        closed = true;
+                        break;
+                    }
+                    value.push(c);
+                }
+                if !closed {
+                    bail!(
+                        "unterminated string starting at column {} in condition '{source}'",
+                        column(source, offset)
+                    );
+                }
+                tokens.push(Token {
+                    kind: TokenKind::Str(value),
+                    offset,
+                });
+                continue;
+            }
+            c if is_word_char(c) => {
+                let mut value = String::new();
+                while let Some(&(_, c)) = chars.peek() {
+                    if is_word_char(c) {
+                        value.push(c);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                let kind = if is_number(&value) {
+                    TokenKind::Number(value)
+                } else {
+                    TokenKind::Ident(value)
+                };
+                tokens.push(Token { kind, offset });
+                continue;
+            }
+            other => bail!(
+                "unexpected character '{other}' at column {} in condition '{source}'",
+                column(source, offset)
+            ),
+        };
+        chars.next();
+        tokens.push(Token { kind, offset });
+    }
+
+    Ok(tokens)
+}
+
+/// Characters of a bare word: identifiers and unquoted values such as `flutter-app` or
+/// `mysql-8.0`.
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '-' | '.')
+}
+
+/// Whether a bare word is a number as a whole (`-1`, `8.0`), not just starts like one.
+fn is_number(word: &str) -> bool {
+    let digits = word.strip_prefix('-').unwrap_or(word);
+    let mut parts = digits.splitn(2, '.');
+    let whole = parts.next().unwrap_or_default();
+    let fraction = parts.next();
+    !whole.is_empty()
+        && whole.chars().all(|c| c.is_ascii_digit())
+        && fraction.is_none_or(|f| !f.is_empty() && f.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// 1-based character column of a byte offset.
+fn column(source: &str, offset: usize) -> usize {
+    source[..offset].chars().count() + 1
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token { kind: TokenKind::Ident(name), .. }) if name == keyword)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn error_at(&self, token: &Token, message: &str) -> String {
+        format!(
+            "{message} at column {} in condition '{}'",
+            column(self.source, token.offset),
+            self.source
+        )
+    }
+
+    fn error_at_end(&self, message: &str) -> String {
+        format!("{message} at end of condition '{}'", self.source)
+    }
+
+    fn expect(&mut self, kind: TokenKind) -> Result<()> {
+        match self.next() {
+            Some(token) if token.kind == kind => Ok(()),
+            Some(token) => {
+                bail!(self.error_at(&token, &format!("expected {kind}, found {}", token.kind)))
+            }
+            None => bail!(self.error_at_end(&format!("expected {kind}"))),
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Expr> {
+        let mut lhs = self.parse_and()?;
+        while self.peek_keyword("or") {
+            self.next();
+            let rhs = self.parse_and()?;
+            lhs = Expr::Or(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr> {
+        let mut lhs = self.parse_unary()?;
+        while self.peek_keyword("and") {
+            self.next();
+            let rhs = self.parse_unary()?;
+            lhs = Expr::And(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr> {
+        if self.peek_keyword("not") {
+            self.next();
+            return Ok(Expr::Not(Box::new(self.parse_unary()?)));
+        }
+        if matches!(
+            self.peek(),
+            Some(Token {
+                kind: TokenKind::LParen,
+                ..
+            })
+        ) {
+            self.next();
+            let expr = self.parse_or()?;
+            self.expect(TokenKind::RParen)?;
+            return Ok(expr);
+        }
+        self.parse_comparison()
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expr> {
+        let lhs = self.parse_operand()?;
+
+        let op = match self.peek().map(|t| &t.kind) {
+            Some(TokenKind::Eq) => Some(CompareOp::Equals),
+            Some(TokenKind::NotEq) => Some(CompareOp::NotEquals),
+            Some(TokenKind::Ident(name)) if name == "contains" => Some(CompareOp::Contains),
+            _ => None,
+        };
+        if let Some(op) = op {
+            self.next();
+            let rhs = match self.parse_operand()? {
+                Operand::Var(name) => Operand::VarOrLiteral(name),
+                rhs => rhs,
+            };
+            return Ok(Expr::Compare(lhs, op, rhs));
+        }
+
+        let negated = self.peek_keyword("not")
+            && matches!(
+                self.tokens.get(self.pos + 1),
+                Some(Token { kind: TokenKind::Ident(name), .. }) if name == "in"
+            );
+        if negated {
+            self.next();
+        }
+        if self.peek_keyword("in") {
+            self.next();
+            let expr = if matches!(
+                self.peek(),
+                Some(Token {
+                    kind: TokenKind::LBracket,
+                    ..
+                })
+            ) {
+                Expr::In(lhs, self.parse_list()?)
+            } else {
+                Expr::InVar(lhs, self.parse_operand()?)
+            };
+            return Ok(if negated {
+                Expr::Not(Box::new(expr))
+            } else {
+                expr
+            });
+        }
+
+        Ok(Expr::Truthy(lhs))
+    }
+
+    fn parse_list(&mut self) -> Result<Vec<Operand>> {
+        self.expect(TokenKind::LBracket)?;
+        let mut items = Vec::new();
+        loop {
+            if matches!(
+                self.peek(),
+                Some(Token {
+                    kind: TokenKind::RBracket,
+                    ..
+                })
+            ) {
+                self.next();
+                return Ok(items);
+            }
+            items.push(match self.parse_operand()? {
+                Operand::Var(name) => Operand::VarOrLiteral(name),
+                item => item,
+            });
+            match self.next() {
+                Some(Token {
+                    kind: TokenKind::Comma,
+                    ..
+                }) => continue,
+                Some(Token {
+                    kind: TokenKind::RBracket,
+                    ..
+                }) => return Ok(items),
+                Some(token) => bail!(self.error_at(
+                    &token,
+                    &format!("expected ',' or ']' in list, found {}", token.kind)
+                )),
+                None => bail!(self.error_at_end("unterminated list, expected ']'")),
+            }
+        }
+    }
+
+    fn parse_operand(&mut self) -> Result<Operand> {
+        match self.next() {
+            Some(Token {
+                kind: TokenKind::Str(value) | TokenKind::Number(value),
+                ..
+            }) => Ok(Operand::Literal(value)),
+            Some(Token {
+                kind: TokenKind::Ident(name),
+                offset,
+            }) => match name.as_str() {
+                "true" | "false" => Ok(Operand::Literal(name)),
+                "and" | "or" | "not" | "in" | "contains" => bail!(self.error_at(
+                    &Token {
+                        kind: TokenKind::Ident(name.clone()),
+                        offset
+                    },
+                    &format!("expected a value, found keyword '{name}'")
+                )),
+                _ => Ok(Operand::Var(name)),
+            },
+            Some(token) => {
+                bail!(self.error_at(&token, &format!("expected a value, found {}", token.kind)))
+            }
+            None => bail!(self.error_at_end("expected a value")),
+        }
+    }
 }
@@ -5,6 +5,9 @@ use std::path::{Path, PathBuf};
 #[derive(Debug, Serialize, Deserialize, Default)]
 pub struct TforgeConfig {
     pub llm: Option<LlmConfig>,
+    /// Registry index URLs (`https://`, `http://` or `file://`) listing installable templates.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub registries: Vec<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
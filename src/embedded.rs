@@ -1,6 +1,7 @@
 use anyhow::{Context, Result};
 use rust_embed::RustEmbed;
 
+use crate::source::TemplateSource;
 use crate::types::TemplateManifest;
 
 #[derive(RustEmbed)]
@@ -8,6 +9,9 @@ use crate::types::TemplateManifest;
 pub struct TemplateAssets;
 
 /// Load all template manifests from the embedded `templates/` directory.
+///
+/// Companion files (`files/`, `partials/`, scripts) stay embedded and are read on demand
+/// through the manifest's [`TemplateSource::Embedded`] prefix.
 pub fn load_embedded_templates() -> Result<Vec<TemplateManifest>> {
     let mut templates = Vec::new();
 
@@ -17,8 +21,11 @@ pub fn load_embedded_templates() -> Result<Vec<TemplateManifest>> {
                 .with_context(|| format!("reading embedded asset {path}"))?;
             let content = std::str::from_utf8(&file.data)
                 .with_context(|| format!("embedded asset {path} is not valid UTF-8"))?;
-            let manifest: TemplateManifest = toml::from_str(content)
+            let mut manifest: TemplateManifest = toml::from_str(content)
                 .with_context(|| format!("parsing embedded template {path}"))?;
+            manifest.source = Some(TemplateSource::Embedded {
+                prefix: path.trim_end_matches("template.toml").to_string(),
+            });
             templates.push(manifest);
         }
     }
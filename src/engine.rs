@@ -1,57 +1,321 @@
+use crate::capture::extract;
 use crate::condition::evaluate_condition;
-use crate::executor::{StepContext, StepResult, execute_step};
-use crate::renderer::Renderer;
-use crate::resolver::resolve_order;
-use crate::state::{PipelineState, StepState};
-use crate::types::TemplateManifest;
+use crate::executor::{CommandError, StepContext, StepResult, execute_with_retries};
+use crate::progress::{OutputTail, Reporter, StepInfo, StepOutcome, StepOutput};
+use crate::renderer::{Renderer, shell_join};
+use crate::resolver::{resolve_layers, resolve_order, step_variables};
+use crate::state::{PipelineState, StepChange, StepChangeKind, StepRecord, StepState, StepStatus};
+use crate::types::{StepDef, TemplateManifest, fields_hash, step_keys};
+use crate::vars::Value;
 use anyhow::{Context, Result};
+use chrono::Utc;
+use serde::Serialize;
 use std::collections::HashMap;
 use std::path::Path;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Variables captured by a template run, in capture order.
+type Captured = Vec<(String, String)>;
+
+/// Output lines per stream kept in a step's record.
+const OUTPUT_TAIL_LINES: usize = 20;
 
 pub struct Engine {
     project_dir: PathBuf,
-    renderer: Renderer,
+    reporter: Option<Arc<dyn Reporter>>,
+    jobs: usize,
+    rerun_drifted: bool,
+    only: Vec<String>,
+}
+
+/// One entry of an execution plan: a step with its fields rendered, or the reason it is skipped.
+#[derive(Debug, Clone, Serialize)]
+pub struct PlannedStep {
+    pub template: String,
+    /// 1-based position within the template, as shown in step errors.
+    pub step: usize,
+    #[serde(rename = "type")]
+    pub step_type: String,
+    pub action: PlanAction,
+    /// Why a step is skipped, or why a completed step runs again.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub working_dir: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub check: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PlanAction {
+    Run,
+    Skip,
 }
 
 impl Engine {
     pub fn new(project_dir: PathBuf) -> Self {
         Self {
             project_dir,
-            renderer: Renderer::new(),
+            reporter: None,
+            jobs: 1,
+            rerun_drifted: true,
+            only: Vec::new(),
         }
     }
 
-    pub fn run(
-        &self,
-        templates: &[TemplateManifest],
-        vars: &HashMap<String, String>,
-    ) -> Result<()> {
+    /// Run and plan only the steps of the named templates. The other templates are still
+    /// used to resolve the order; their captured variables come from the saved state.
+    pub fn with_only(mut self, templates: Vec<String>) -> Self {
+        self.only = templates;
+        self
+    }
+
+    /// Whether resuming re-runs completed steps whose rendered inputs changed since they
+    /// ran (the default). When off, completed steps are always skipped.
+    pub fn with_rerun_drifted(mut self, rerun: bool) -> Self {
+        self.rerun_drifted = rerun;
+        self
+    }
+
+    /// Run up to `jobs` independent templates at once (see [`resolve_layers`]).
+    /// With the default of 1, templates run one by one in [`resolve_order`].
+    pub fn with_jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs.max(1);
+        self
+    }
+
+    /// Report step progress and stream command output to `reporter` while running.
+    pub fn with_reporter(mut self, reporter: Arc<dyn Reporter>) -> Self {
+        self.reporter = Some(reporter);
+        self
+    }
+
+    pub fn run(&self, templates: &[TemplateManifest], vars: &HashMap<String, Value>) -> Result<()> {
         self.run_internal(templates, vars, None, false)
     }
 
     pub fn run_with_state(
         &self,
         templates: &[TemplateManifest],
-        vars: &HashMap<String, String>,
+        vars: &HashMap<String, Value>,
         state_path: &Path,
         resume: bool,
     ) -> Result<()> {
         self.run_internal(templates, vars, Some(state_path), resume)
     }
 
+    /// Resolve the order, evaluate conditions and render every step without executing anything.
+    pub fn plan(
+        &self,
+        templates: &[TemplateManifest],
+        vars: &HashMap<String, Value>,
+    ) -> Result<Vec<PlannedStep>> {
+        self.plan_internal(templates, vars, &PipelineState::new())
+    }
+
+    /// Like [`Engine::plan`], but steps already completed in the saved state are skipped
+    /// the way `tforge resume` would skip them.
+    pub fn plan_with_state(
+        &self,
+        templates: &[TemplateManifest],
+        vars: &HashMap<String, Value>,
+        state_path: &Path,
+    ) -> Result<Vec<PlannedStep>> {
+        let mut state = load_state(state_path, templates)?;
+        for tmpl in templates.iter().filter(|t| self.selected(&t.template.name)) {
+            state.forget_stale_steps(tmpl);
+        }
+        self.plan_internal(templates, vars, &state)
+    }
+
+    /// Steps added, changed or removed in the templates since they last ran, and completed
+    /// steps whose rendered inputs drifted under `vars`, in template order. `tforge resume`
+    /// runs added, changed and drifted steps and drops removed ones. Drift caused by values
+    /// captured during the resumed run itself is only found while running.
+    pub fn resume_changes(
+        &self,
+        templates: &[TemplateManifest],
+        vars: &HashMap<String, Value>,
+        state_path: &Path,
+    ) -> Result<Vec<StepChange>> {
+        let mut state = load_state(state_path, templates)?;
+        let vars = merge_captured_vars(vars, &state);
+        let mut changes = Vec::new();
+        for name in resolve_order(templates)? {
+            if !self.selected(&name) {
+                continue;
+            }
+            let tmpl = find_template(templates, &name)?;
+            changes.extend(state.step_changes(tmpl));
+            if !self.rerun_drifted {
+                continue;
+            }
+            state.forget_stale_steps(tmpl);
+            let renderer = template_renderer(tmpl);
+            for (i, key) in step_keys(&tmpl.steps).iter().enumerate() {
+                if drifted(&renderer, tmpl, i, state.step_record(&name, key), &vars) {
+                    changes.push(StepChange {
+                        template: name.clone(),
+                        step: i + 1,
+                        key: key.clone(),
+                        kind: StepChangeKind::Drifted,
+                    });
+                }
+            }
+        }
+        Ok(changes)
+    }
+
+    fn plan_internal(
+        &self,
+        templates: &[TemplateManifest],
+        vars: &HashMap<String, Value>,
+        state: &PipelineState,
+    ) -> Result<Vec<PlannedStep>> {
+        let order = resolve_order(templates)?;
+        let mut vars = merge_captured_vars(vars, state);
+        let mut plan = Vec::new();
+
+        for name in &order {
+            if !self.selected(name) {
+                continue;
+            }
+            let tmpl = find_template(templates, name)?;
+            let renderer = template_renderer(tmpl);
+            let keys = step_keys(&tmpl.steps);
+
+            for (i, step) in tmpl.steps.iter().enumerate() {
+                let record = state.step_record(name, &keys[i]);
+                let label = format!("[{name}] step {}", i + 1);
+                if self.rerun_drifted && drifted(&renderer, tmpl, i, record, &vars) {
+                    let mut planned = plan_step(&renderer, tmpl, i, step, &mut vars, &label)?;
+                    planned.reason = Some("inputs changed since the last run".into());
+                    plan.push(planned);
+                    continue;
+                }
+                if matches!(state.get(name, &keys[i]), StepState::Completed) {
+                    let mut planned = PlannedStep::skipped(name, i, step, "already completed");
+                    planned.source = step.source.clone();
+                    planned.url = step.url.clone();
+                    plan.push(planned);
+                    continue;
+                }
+                plan.push(plan_step(&renderer, tmpl, i, step, &mut vars, &label)?);
+            }
+        }
+
+        Ok(plan)
+    }
+
+    /// Run the `[[destroy]]` steps of every provisioned template in reverse dependency
+    /// order, restricted to `only` when it is not empty. A template counts as provisioned
+    /// when any of its steps is completed in the saved state; its step state is cleared
+    /// once all its destroy steps succeed. Returns the names of the destroyed templates.
+    pub fn destroy(
+        &self,
+        templates: &[TemplateManifest],
+        vars: &HashMap<String, Value>,
+        state_path: &Path,
+        only: &[String],
+    ) -> Result<Vec<String>> {
+        let mut state = load_state(state_path, templates)?;
+        let targets = destroy_targets(templates, &state, only)?;
+        let mut vars = merge_captured_vars(vars, &state);
+        let mut destroyed = Vec::new();
+
+        for tmpl in targets {
+            let name = &tmpl.template.name;
+            let renderer = template_renderer(tmpl);
+
+            for (i, step) in tmpl.destroy.iter().enumerate() {
+                let label = format!("[{name}] destroy step {}", i + 1);
+                let mut info = StepInfo {
+                    template: name.clone(),
+                    step: i + 1,
+                    total: tmpl.destroy.len(),
+                    step_type: format!("destroy {}", step.step_type),
+                    summary: String::new(),
+                };
+                if !condition_holds(&renderer, step, &vars, &label)? {
+                    self.report_finished(&info, StepOutcome::Skipped("condition false".into()));
+                    continue;
+                }
+
+                let rendered_step = render_step(&renderer, tmpl, step, &vars, &label)?;
+                let (result, _) = self.execute(tmpl, step, &rendered_step, &mut info, &vars);
+                if let Some((var, value)) =
+                    result.with_context(|| format!("{label} ({}) failed", step.step_type))?
+                {
+                    vars.insert(var, value.into());
+                }
+            }
+
+            state.clear_template(name);
+            save_state_if_needed(&state, Some(state_path))?;
+            destroyed.push(name.clone());
+        }
+
+        Ok(destroyed)
+    }
+
+    /// Render the `[[destroy]]` steps [`Engine::destroy`] would run, without executing them.
+    pub fn plan_destroy(
+        &self,
+        templates: &[TemplateManifest],
+        vars: &HashMap<String, Value>,
+        state_path: &Path,
+        only: &[String],
+    ) -> Result<Vec<PlannedStep>> {
+        let state = load_state(state_path, templates)?;
+        let targets = destroy_targets(templates, &state, only)?;
+        let mut vars = merge_captured_vars(vars, &state);
+        let mut plan = Vec::new();
+
+        for tmpl in targets {
+            let renderer = template_renderer(tmpl);
+            for (i, step) in tmpl.destroy.iter().enumerate() {
+                let label = format!("[{}] destroy step {}", tmpl.template.name, i + 1);
+                plan.push(plan_step(&renderer, tmpl, i, step, &mut vars, &label)?);
+            }
+        }
+
+        Ok(plan)
+    }
+
     fn run_internal(
         &self,
         templates: &[TemplateManifest],
-        vars: &HashMap<String, String>,
+        vars: &HashMap<String, Value>,
         state_path: Option<&Path>,
         resume: bool,
     ) -> Result<()> {
-        let order = resolve_order(templates)?;
-        let mut state = match state_path {
-            Some(path) if resume => PipelineState::load(path).with_context(|| {
-                format!("failed to load pipeline state from {}", path.display())
-            })?,
+        let layers = if self.jobs > 1 {
+            resolve_layers(templates)?
+        } else {
+            resolve_order(templates)?
+                .into_iter()
+                .map(|name| vec![name])
+                .collect()
+        };
+        let state = match state_path {
+            Some(path) if resume => {
+                let mut state = load_state(path, templates)?;
+                for tmpl in templates.iter().filter(|t| self.selected(&t.template.name)) {
+                    state.forget_stale_steps(tmpl);
+                }
+                state
+            }
             _ => PipelineState::new(),
         };
 
@@ -59,73 +323,584 @@ impl Engine {
             save_state_if_needed(&state, state_path)?;
         }
 
-        let template_map: HashMap<&str, &TemplateManifest> = templates
-            .iter()
-            .map(|t| (t.template.name.as_str(), t))
-            .collect();
+        let mut vars = merge_captured_vars(vars, &state);
+        let state = Mutex::new(state);
+        let failed = AtomicBool::new(false);
 
-        for name in &order {
-            let tmpl = template_map
-                .get(name.as_str())
-                .ok_or_else(|| anyhow::anyhow!("template '{name}' not found in map"))?;
+        for layer in &layers {
+            let layer = layer
+                .iter()
+                .map(|name| find_template(templates, name))
+                .collect::<Result<Vec<_>>>()?;
+            let results = self.run_layer(
+                &layer,
+                &TemplateRun {
+                    vars: &vars,
+                    state: &state,
+                    state_path,
+                    resume,
+                    failed: &failed,
+                },
+            );
 
-            for (i, step) in tmpl.steps.iter().enumerate() {
-                if resume && matches!(state.get(name, i), StepState::Completed) {
-                    continue;
+            // Captured variables become visible to the next layer, merged in layer order.
+            let mut first_err = None;
+            for result in results {
+                match result {
+                    Ok(captured) => vars.extend(
+                        captured
+                            .into_iter()
+                            .map(|(var, value)| (var, Value::String(value))),
+                    ),
+                    Err(err) => {
+                        first_err.get_or_insert(err);
+                    }
                 }
+            }
+            if let Some(err) = first_err {
+                return Err(err);
+            }
+        }
 
-                // Check condition
-                if let Some(cond) = &step.condition {
-                    let rendered_cond =
-                        self.renderer.render_string(cond, vars).with_context(|| {
-                            format!("[{name}] step {}: failed to render condition", i + 1)
-                        })?;
-                    if !evaluate_condition(&rendered_cond, vars)? {
-                        state.mark_completed(name, i);
-                        save_state_if_needed(&state, state_path)?;
-                        continue;
-                    }
+        Ok(())
+    }
+
+    /// Run the templates of one layer, at most `jobs` at a time. Results are returned in
+    /// layer order; templates not started because another one failed are left out.
+    fn run_layer(
+        &self,
+        layer: &[&TemplateManifest],
+        run: &TemplateRun<'_>,
+    ) -> Vec<Result<Captured>> {
+        if self.jobs <= 1 || layer.len() <= 1 {
+            let mut results = Vec::new();
+            for tmpl in layer {
+                let result = self.run_template(tmpl, run);
+                let stop = result.is_err();
+                results.push(result);
+                if stop {
+                    break;
                 }
+            }
+            return results;
+        }
+
+        let next = AtomicUsize::new(0);
+        let slots: Vec<Mutex<Option<Result<Captured>>>> =
+            layer.iter().map(|_| Mutex::new(None)).collect();
+        thread::scope(|scope| {
+            for _ in 0..self.jobs.min(layer.len()) {
+                scope.spawn(|| {
+                    loop {
+                        let idx = next.fetch_add(1, Ordering::SeqCst);
+                        let Some(tmpl) = layer.get(idx) else {
+                            break;
+                        };
+                        if run.failed.load(Ordering::SeqCst) {
+                            break;
+                        }
+                        let result = self.run_template(tmpl, run);
+                        if result.is_err() {
+                            run.failed.store(true, Ordering::SeqCst);
+                        }
+                        *lock(&slots[idx]) = Some(result);
+                    }
+                });
+            }
+        });
+        slots
+            .into_iter()
+            .filter_map(|slot| slot.into_inner().unwrap_or_else(PoisonError::into_inner))
+            .collect()
+    }
+
+    /// Run the steps of one template in order. Returns the variables it captured.
+    /// Stops early, without error, once another template has failed.
+    fn run_template(&self, tmpl: &TemplateManifest, run: &TemplateRun<'_>) -> Result<Captured> {
+        let name = &tmpl.template.name;
+        if !self.selected(name) {
+            return Ok(Vec::new());
+        }
+        let renderer = template_renderer(tmpl);
+        let mut vars = run.vars.clone();
+        let mut captured = Vec::new();
+        let keys = step_keys(&tmpl.steps);
+        run.update_state(|state| state.record_layout(tmpl))?;
 
-                // Render step fields
-                let mut rendered_step = step.clone();
-                if let Some(cmd) = &step.command {
-                    rendered_step.command =
-                        Some(self.renderer.render_string(cmd, vars).with_context(|| {
-                            format!("[{name}] step {}: failed to render command", i + 1)
-                        })?);
+        for (i, step) in tmpl.steps.iter().enumerate() {
+            let key = keys[i].as_str();
+            if run.failed.load(Ordering::SeqCst) {
+                break;
+            }
+            if run.resume && matches!(lock(run.state).get(name, key), StepState::Completed) {
+                let record = lock(run.state).step_record(name, key).cloned();
+                if !(self.rerun_drifted && drifted(&renderer, tmpl, i, record.as_ref(), &vars)) {
+                    continue;
                 }
-                if let Some(wd) = &step.working_dir {
-                    rendered_step.working_dir = Some(self.renderer.render_string(wd, vars)?);
+            }
+
+            let label = format!("[{name}] step {}", i + 1);
+            let mut info = StepInfo {
+                template: name.clone(),
+                step: i + 1,
+                total: tmpl.steps.len(),
+                step_type: step.step_type.clone(),
+                summary: String::new(),
+            };
+            if !condition_holds(&renderer, step, &vars, &label)? {
+                self.report_finished(&info, StepOutcome::Skipped("condition false".into()));
+                let mut record = StepRecord::new(StepStatus::Completed);
+                record.skipped = Some("condition false".into());
+                record.finished_at = Some(Utc::now());
+                record.inputs_hash = Some(inputs_hash(tmpl, step, None, &vars));
+                run.update_state(|state| state.record(name, key, record))?;
+                continue;
+            }
+
+            let rendered_step = render_step(&renderer, tmpl, step, &vars, &label)?;
+            let (result, mut record) = self.execute(tmpl, step, &rendered_step, &mut info, &vars);
+            record.inputs_hash = Some(inputs_hash(tmpl, step, Some(&rendered_step), &vars));
+
+            match result {
+                Ok(value) => {
+                    run.update_state(|state| {
+                        if let Some((var, value)) = &value {
+                            state.set_var(var, value);
+                        }
+                        state.record(name, key, record);
+                    })?;
+                    if let Some((var, value)) = value {
+                        vars.insert(var.clone(), value.clone().into());
+                        captured.push((var, value));
+                    }
                 }
-                if let Some(check) = &step.check {
-                    rendered_step.check = Some(self.renderer.render_string(check, vars)?);
+                Err(err) => {
+                    record.error = Some(format!("{err:#}"));
+                    run.update_state(|state| state.record(name, key, record))?;
+                    return Err(err)
+                        .with_context(|| format!("{label} ({}) failed", step.step_type));
                 }
+            }
+        }
 
-                let ctx = StepContext {
-                    project_dir: self.project_dir.clone(),
-                    vars: vars.clone(),
-                };
+        Ok(captured)
+    }
+
+    /// Execute a rendered step with its retry policy, reporting progress and applying its
+    /// `capture`. Returns the captured variable, if any, and the record of the run; the
+    /// caller adds the error message to the record of a failed step.
+    fn execute(
+        &self,
+        tmpl: &TemplateManifest,
+        step: &StepDef,
+        rendered_step: &StepDef,
+        info: &mut StepInfo,
+        vars: &HashMap<String, Value>,
+    ) -> (Result<Option<(String, String)>>, StepRecord) {
+        info.summary = step_summary(rendered_step);
+        let tail = Arc::new(OutputTail::new(self.reporter.clone(), OUTPUT_TAIL_LINES));
+        let ctx = StepContext {
+            project_dir: self.project_dir.clone(),
+            vars: vars.clone(),
+            source: tmpl.source.clone(),
+            output: Some(StepOutput {
+                reporter: Arc::clone(&tail) as Arc<dyn Reporter>,
+                step: info.clone(),
+            }),
+        };
 
-                match execute_step(&rendered_step, &ctx) {
-                    Ok(StepResult::Executed) | Ok(StepResult::Skipped) => {
-                        state.mark_completed(name, i);
-                        save_state_if_needed(&state, state_path)?;
+        let mut record = StepRecord::new(StepStatus::Completed);
+        record.command = match rendered_step.step_type.as_str() {
+            "command" => rendered_step.command.clone(),
+            _ => Some(info.summary.clone()),
+        };
+        record.working_dir = Some(
+            match &rendered_step.working_dir {
+                Some(dir) => self.project_dir.join(dir),
+                None => self.project_dir.clone(),
+            }
+            .display()
+            .to_string(),
+        );
+        record.started_at = Some(Utc::now());
+
+        if let Some(reporter) = &self.reporter {
+            reporter.step_started(info);
+        }
+        let started = Instant::now();
+        let mut outcome = StepOutcome::Done;
+        let runs_process = matches!(rendered_step.step_type.as_str(), "command" | "git");
+        let result = execute_with_retries(rendered_step, &ctx)
+            .map_err(|failure| {
+                record.attempts = failure.attempts;
+                record.exit_code = failure
+                    .source
+                    .downcast_ref::<CommandError>()
+                    .and_then(CommandError::exit_code);
+                failure.into_error()
+            })
+            .and_then(|(result, used)| {
+                record.attempts = used;
+                let stdout = match result {
+                    StepResult::Executed { stdout } => {
+                        record.exit_code = runs_process.then_some(0);
+                        stdout
                     }
-                    Err(err) => {
-                        let msg = err.to_string();
-                        state.mark_failed(name, i, &msg);
-                        save_state_if_needed(&state, state_path)?;
-                        return Err(err).with_context(|| {
-                            format!("[{name}] step {} ({}) failed", i + 1, step.step_type)
-                        });
+                    StepResult::Skipped { stdout } => {
+                        record.skipped = Some("check passed".into());
+                        outcome = StepOutcome::Skipped("check passed".into());
+                        stdout
                     }
+                };
+                match &step.capture {
+                    Some(capture) => extract(capture, &stdout)
+                        .with_context(|| format!("failed to capture '{}'", capture.var))
+                        .map(|value| Some((capture.var.clone(), value))),
+                    None => Ok(None),
                 }
+            });
+        if result.is_err() {
+            outcome = StepOutcome::Failed;
+            record.status = StepStatus::Failed;
+        }
+        let elapsed = started.elapsed();
+        record.finished_at = Some(Utc::now());
+        record.duration_ms = Some(elapsed.as_millis() as u64);
+        record.stdout_tail = tail.stdout();
+        record.stderr_tail = tail.stderr();
+        if let Some(reporter) = &self.reporter {
+            reporter.step_finished(info, &outcome, elapsed);
+        }
+        (result, record)
+    }
+
+    fn selected(&self, template: &str) -> bool {
+        self.only.is_empty() || self.only.iter().any(|name| name == template)
+    }
+
+    fn report_finished(&self, info: &StepInfo, outcome: StepOutcome) {
+        if let Some(reporter) = &self.reporter {
+            reporter.step_finished(info, &outcome, Duration::ZERO);
+        }
+    }
+}
+
+/// Shared state of one pipeline run, handed to every template runner.
+struct TemplateRun<'a> {
+    /// Variables visible at the start of the current layer.
+    vars: &'a HashMap<String, Value>,
+    state: &'a Mutex<PipelineState>,
+    state_path: Option<&'a Path>,
+    resume: bool,
+    /// Set once any template fails, so the others stop after their current step.
+    failed: &'a AtomicBool,
+}
+
+impl TemplateRun<'_> {
+    /// Apply `update` and persist the state while holding the lock, so concurrent
+    /// templates never write interleaved state files.
+    fn update_state(&self, update: impl FnOnce(&mut PipelineState)) -> Result<()> {
+        let mut state = lock(self.state);
+        update(&mut state);
+        save_state_if_needed(&state, self.state_path)
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl PlannedStep {
+    fn skipped(template: &str, idx: usize, step: &StepDef, reason: &str) -> Self {
+        Self {
+            template: template.to_string(),
+            step: idx + 1,
+            step_type: step.step_type.clone(),
+            action: PlanAction::Skip,
+            reason: Some(reason.to_string()),
+            command: None,
+            working_dir: None,
+            check: None,
+            source: None,
+            url: None,
+        }
+    }
+}
+
+/// Evaluate a step's condition and render it for a plan. Variables the step captures are
+/// replaced by placeholders for later steps, since their values are only known at run time.
+fn plan_step(
+    renderer: &Renderer,
+    tmpl: &TemplateManifest,
+    idx: usize,
+    step: &StepDef,
+    vars: &mut HashMap<String, Value>,
+    label: &str,
+) -> Result<PlannedStep> {
+    let name = &tmpl.template.name;
+    if !condition_holds(renderer, step, vars, label)? {
+        let reason = format!(
+            "condition `{}` is false",
+            step.condition.as_deref().unwrap_or_default()
+        );
+        let mut planned = PlannedStep::skipped(name, idx, step, &reason);
+        planned.source = step.source.clone();
+        planned.url = step.url.clone();
+        return Ok(planned);
+    }
+
+    let rendered = render_step(renderer, tmpl, step, vars, label)?;
+    if let Some(capture) = &step.capture {
+        vars.entry(capture.var.clone())
+            .or_insert_with(|| format!("<{} from {label}>", capture.var).into());
+    }
+    Ok(PlannedStep {
+        template: name.clone(),
+        step: idx + 1,
+        step_type: step.step_type.clone(),
+        action: PlanAction::Run,
+        reason: None,
+        command: rendered.command,
+        working_dir: rendered.working_dir,
+        check: rendered.check,
+        source: step.source.clone(),
+        url: step.url.clone(),
+    })
+}
+
+/// Templates to tear down, in reverse dependency order: provisioned (any step completed),
+/// declaring `[[destroy]]` steps, and named in `only` when it is not empty. Destroying a
+/// template while a provisioned template that requires it is kept is refused.
+fn destroy_targets<'a>(
+    templates: &'a [TemplateManifest],
+    state: &PipelineState,
+    only: &[String],
+) -> Result<Vec<&'a TemplateManifest>> {
+    for name in only {
+        if !templates.iter().any(|t| &t.template.name == name) {
+            anyhow::bail!("template '{name}' is not part of this project");
+        }
+    }
+    let selected = |name: &str| only.is_empty() || only.iter().any(|o| o == name);
+
+    for tmpl in templates {
+        let name = &tmpl.template.name;
+        if selected(name) || !state.has_completed(name) {
+            continue;
+        }
+        if let Some(dep) = tmpl
+            .dependencies
+            .requires_templates
+            .iter()
+            .map(|dep| dep.name.as_str())
+            .find(|dep| selected(dep) && state.has_completed(dep))
+        {
+            anyhow::bail!(
+                "template '{name}' requires '{dep}' and is still provisioned. Destroy it too with --template {name}."
+            );
+        }
+    }
+
+    let mut targets = Vec::new();
+    for name in resolve_order(templates)?.iter().rev() {
+        let tmpl = find_template(templates, name)?;
+        if !tmpl.destroy.is_empty() && selected(name) && state.has_completed(name) {
+            targets.push(tmpl);
+        }
+    }
+    Ok(targets)
+}
+
+/// Hash of what a step runs with: its rendered fields (`None` when its condition is false)
+/// and the values of the variables it reads, or of every variable when those cannot be
+/// determined.
+fn inputs_hash(
+    tmpl: &TemplateManifest,
+    step: &StepDef,
+    rendered: Option<&StepDef>,
+    vars: &HashMap<String, Value>,
+) -> String {
+    let names = step_variables(tmpl, step).unwrap_or_else(|| vars.keys().cloned().collect());
+    let values: Vec<(String, Option<String>)> = names
+        .iter()
+        .map(|name| (format!("var.{name}"), vars.get(name).map(Value::to_string)))
+        .collect();
+    let mut fields = vec![("type", Some(step.step_type.as_str()))];
+    match rendered {
+        Some(rendered) => fields.extend([
+            ("command", rendered.command.as_deref()),
+            ("working_dir", rendered.working_dir.as_deref()),
+            ("check", rendered.check.as_deref()),
+            ("action", rendered.action.as_deref()),
+            ("source", rendered.source.as_deref()),
+            ("url", rendered.url.as_deref()),
+        ]),
+        None => fields.push(("condition", Some("false"))),
+    }
+    fields.extend(
+        values
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_deref())),
+    );
+    fields_hash(fields)
+}
+
+/// Whether step `idx` completed with inputs other than the ones it would render with now.
+/// Records without an inputs hash (older state files) and steps that no longer render
+/// are not considered drifted.
+fn drifted(
+    renderer: &Renderer,
+    tmpl: &TemplateManifest,
+    idx: usize,
+    record: Option<&StepRecord>,
+    vars: &HashMap<String, Value>,
+) -> bool {
+    let Some(old) = record
+        .filter(|r| r.status == StepStatus::Completed)
+        .and_then(|r| r.inputs_hash.as_deref())
+    else {
+        return false;
+    };
+    let step = &tmpl.steps[idx];
+    let label = format!("[{}] step {}", tmpl.template.name, idx + 1);
+    let current = condition_holds(renderer, step, vars, &label).and_then(|holds| {
+        let rendered = holds
+            .then(|| render_step(renderer, tmpl, step, vars, &label))
+            .transpose()?;
+        Ok(inputs_hash(tmpl, step, rendered.as_ref(), vars))
+    });
+    current.is_ok_and(|current| current != old)
+}
+
+/// One-line description of a rendered step for progress output.
+fn step_summary(step: &StepDef) -> String {
+    const MAX: usize = 60;
+    match step.step_type.as_str() {
+        "bundled" => format!(
+            "{} {}",
+            step.action.as_deref().unwrap_or("overlay"),
+            step.source.as_deref().unwrap_or_default()
+        ),
+        "git" => format!("clone {}", step.url.as_deref().unwrap_or_default()),
+        _ => {
+            let command = step.command.as_deref().unwrap_or_default().trim();
+            let first = command.lines().next().unwrap_or_default();
+            if first.chars().count() > MAX {
+                format!("{}...", first.chars().take(MAX).collect::<String>())
+            } else if first.len() < command.len() {
+                format!("{first} ...")
+            } else {
+                first.to_string()
             }
         }
+    }
+}
 
-        Ok(())
+fn find_template<'a>(
+    templates: &'a [TemplateManifest],
+    name: &str,
+) -> Result<&'a TemplateManifest> {
+    templates
+        .iter()
+        .find(|t| t.template.name == name)
+        .ok_or_else(|| anyhow::anyhow!("template '{name}' not found in map"))
+}
+
+/// Captured variables from earlier runs are available to later steps.
+fn merge_captured_vars(
+    vars: &HashMap<String, Value>,
+    state: &PipelineState,
+) -> HashMap<String, Value> {
+    let mut vars = vars.clone();
+    for (key, value) in state.vars() {
+        vars.entry(key.clone())
+            .or_insert_with(|| Value::String(value.clone()));
+    }
+    vars
+}
+
+fn template_renderer(tmpl: &TemplateManifest) -> Renderer {
+    match &tmpl.source {
+        Some(source) => Renderer::with_source(source.clone()),
+        None => Renderer::new(),
+    }
+}
+
+fn condition_holds(
+    renderer: &Renderer,
+    step: &StepDef,
+    vars: &HashMap<String, Value>,
+    label: &str,
+) -> Result<bool> {
+    let Some(cond) = &step.condition else {
+        return Ok(true);
+    };
+    let rendered_cond = renderer
+        .render_string(cond, vars)
+        .with_context(|| format!("{label}: failed to render condition"))?;
+    evaluate_condition(&rendered_cond, vars)
+}
+
+/// Render the fields a step executes with: `script`, `command` or `args`, `working_dir` and
+/// `check`. Values interpolated into `script`, `command` and `check` are shell-quoted;
+/// rendered `args` are also joined into `command` for display and state. Template
+/// `[defaults]` fill in fields the step leaves unset.
+fn render_step(
+    renderer: &Renderer,
+    tmpl: &TemplateManifest,
+    step: &StepDef,
+    vars: &HashMap<String, Value>,
+    label: &str,
+) -> Result<StepDef> {
+    let mut rendered_step = step.clone();
+    if rendered_step.retry_on.is_none() {
+        rendered_step.retry_on = tmpl.defaults.retry_on.clone();
+    }
+    if let Some(args) = &step.args {
+        if step.command.is_some() || step.script.is_some() {
+            anyhow::bail!("{label}: 'args' cannot be combined with 'command' or 'script'");
+        }
+        if args.is_empty() {
+            anyhow::bail!("{label}: 'args' needs at least the program to run");
+        }
+        let args = args
+            .iter()
+            .map(|arg| renderer.render_string(arg, vars))
+            .collect::<Result<Vec<_>>>()
+            .with_context(|| format!("{label}: failed to render args"))?;
+        rendered_step.command = Some(shell_join(&args));
+        rendered_step.args = Some(args);
+    } else if let Some(script) = &step.script {
+        let body = tmpl
+            .source
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("template has no file source for script '{script}'"))
+            .and_then(|source| source.read_to_string(script))
+            .with_context(|| format!("{label}: failed to load script"))?;
+        rendered_step.command = Some(
+            renderer
+                .render_shell(&body, vars)
+                .with_context(|| format!("{label}: failed to render script '{script}'"))?,
+        );
+    } else if let Some(cmd) = &step.command {
+        rendered_step.command = Some(
+            renderer
+                .render_shell(cmd, vars)
+                .with_context(|| format!("{label}: failed to render command"))?,
+        );
+    }
+    if let Some(wd) = &step.working_dir {
+        rendered_step.working_dir = Some(renderer.render_string(wd, vars)?);
     }
+    if let Some(check) = &step.check {
+        rendered_step.check = Some(renderer.render_shell(check, vars)?);
+    }
+    Ok(rendered_step)
+}
+
+fn load_state(path: &Path, templates: &[TemplateManifest]) -> Result<PipelineState> {
+    PipelineState::load_for(path, templates)
+        .with_context(|| format!("failed to load pipeline state from {}", path.display()))
 }
 
 fn save_state_if_needed(state: &PipelineState, state_path: Option<&Path>) -> Result<()> {
@@ -1,76 +1,373 @@
+use crate::progress::{OutputStream, StepOutput};
+use crate::renderer::{Renderer, shell_join};
+use crate::source::TemplateSource;
 use crate::types::StepDef;
+use crate::vars::Value;
 use anyhow::{Context, Result, bail};
 use std::collections::HashMap;
-use std::path::PathBuf;
-use std::process::Command;
+use std::io::{BufRead, BufReader, Read};
+use std::os::unix::process::CommandExt;
+use std::path::{Component, Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
 
 pub struct StepContext {
     pub project_dir: PathBuf,
-    pub vars: HashMap<String, String>,
+    pub vars: HashMap<String, Value>,
+    /// Template files available to `bundled` steps.
+    pub source: Option<TemplateSource>,
+    /// Receives command output line by line while the step runs.
+    pub output: Option<StepOutput>,
 }
 
 pub enum StepResult {
-    Executed,
-    Skipped,
+    /// The step ran; `stdout` is the command output (empty for non-command steps).
+    Executed { stdout: String },
+    /// The `check` command succeeded; `stdout` is the check output.
+    Skipped { stdout: String },
 }
 
+/// A `command` or `git` process that exited unsuccessfully or ran past its `timeout`.
+#[derive(Debug, thiserror::Error)]
+pub enum CommandError {
+    #[error("{what} failed: {target}\n{stderr}")]
+    Failed {
+        what: &'static str,
+        target: String,
+        /// Exit code, or `None` when the process was ended by a signal.
+        code: Option<i32>,
+        stderr: String,
+    },
+    #[error("{what} timed out after {}s: {target}", .timeout.as_secs())]
+    TimedOut {
+        what: &'static str,
+        target: String,
+        timeout: Duration,
+        stderr: String,
+    },
+}
+
+impl CommandError {
+    /// Stderr collected before the process exited or was killed.
+    pub fn stderr(&self) -> &str {
+        match self {
+            CommandError::Failed { stderr, .. } | CommandError::TimedOut { stderr, .. } => stderr,
+        }
+    }
+
+    pub fn exit_code(&self) -> Option<i32> {
+        match self {
+            CommandError::Failed { code, .. } => *code,
+            CommandError::TimedOut { .. } => None,
+        }
+    }
+}
+
+/// A step that still failed after its last allowed attempt.
+#[derive(Debug, thiserror::Error)]
+#[error("gave up after {attempts} attempts")]
+pub struct StepFailure {
+    pub attempts: u32,
+    #[source]
+    pub source: anyhow::Error,
+}
+
+impl StepFailure {
+    /// The error to report: the underlying failure, wrapped with the attempt count
+    /// only when the step was retried.
+    pub fn into_error(self) -> anyhow::Error {
+        if self.attempts > 1 {
+            self.into()
+        } else {
+            self.source
+        }
+    }
+}
+
+/// Run a step, retrying according to its `retries`, `retry_delay`, `retry_backoff` and
+/// `retry_on` settings. Returns the result and the number of attempts made.
+pub fn execute_with_retries(
+    step: &StepDef,
+    ctx: &StepContext,
+) -> std::result::Result<(StepResult, u32), StepFailure> {
+    let retries = step.retries.unwrap_or(0);
+    let backoff = step.retry_backoff.unwrap_or(2.0).max(1.0);
+    let mut delay = Duration::from_secs(step.retry_delay.unwrap_or(2));
+
+    let mut attempt = 1;
+    loop {
+        let err = match execute_step(step, ctx) {
+            Ok(result) => return Ok((result, attempt)),
+            Err(err) => err,
+        };
+        // Only process failures are retried; a missing field or bad template is not transient.
+        let retryable = err.downcast_ref::<CommandError>().is_some_and(|failure| {
+            step.retry_on
+                .as_ref()
+                .is_none_or(|pattern| pattern.is_match(failure.stderr()))
+        });
+        if !retryable || attempt > retries {
+            return Err(StepFailure {
+                attempts: attempt,
+                source: err,
+            });
+        }
+
+        if let Some(output) = &ctx.output {
+            output.line(
+                OutputStream::Stderr,
+                &format!(
+                    "attempt {attempt}/{} failed; retrying in {:.1}s",
+                    retries + 1,
+                    delay.as_secs_f64()
+                ),
+            );
+        }
+        thread::sleep(delay);
+        delay = delay.mul_f64(backoff);
+        attempt += 1;
+    }
+}
+
+/// Run a step once. `timeout` is enforced; retries are handled by [`execute_with_retries`].
 pub fn execute_step(step: &StepDef, ctx: &StepContext) -> Result<StepResult> {
     let working_dir = match &step.working_dir {
         Some(dir) => ctx.project_dir.join(dir),
         None => ctx.project_dir.clone(),
     };
 
-    // Run idempotency check if present
+    let timeout = step.timeout.map(Duration::from_secs);
+
+    // Run idempotency check if present; it is bound by the step's timeout too.
     if let Some(check_cmd) = &step.check {
-        let status = Command::new("sh")
-            .arg("-c")
-            .arg(check_cmd)
-            .current_dir(&working_dir)
-            .status()
-            .context("failed to run check command")?;
-        if status.success() {
-            return Ok(StepResult::Skipped);
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(check_cmd).current_dir(&working_dir);
+        let output =
+            run_streamed(&mut command, None, timeout).context("failed to run check command")?;
+        match output.status {
+            Some(status) if status.success() => {
+                return Ok(StepResult::Skipped {
+                    stdout: output.stdout,
+                });
+            }
+            Some(_) => {}
+            None => output.check("check", check_cmd, timeout)?,
         }
     }
 
     match step.step_type.as_str() {
         "command" => {
-            let cmd = step
-                .command
-                .as_ref()
-                .ok_or_else(|| anyhow::anyhow!("command step missing 'command' field"))?;
-            let output = Command::new("sh")
-                .arg("-c")
-                .arg(cmd)
-                .current_dir(&working_dir)
-                .output()
+            let (mut command, cmd) = match step.args.as_deref() {
+                // `args` runs the program directly; no shell sees the values.
+                Some(argv @ [program, args @ ..]) => {
+                    let mut command = Command::new(program);
+                    command.args(args);
+                    (command, shell_join(argv))
+                }
+                _ => {
+                    let cmd = step
+                        .command
+                        .clone()
+                        .ok_or_else(|| anyhow::anyhow!("command step missing 'command' field"))?;
+                    let mut command = Command::new("sh");
+                    command.arg("-c").arg(&cmd);
+                    (command, cmd)
+                }
+            };
+            command.current_dir(&working_dir);
+            let output = run_streamed(&mut command, ctx.output.as_ref(), timeout)
                 .with_context(|| format!("failed to execute: {cmd}"))?;
-            if !output.status.success() {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                bail!("command failed: {cmd}\n{stderr}");
-            }
-            Ok(StepResult::Executed)
-        }
-        "bundled" => {
-            // Will be implemented when we add the bundled file provider
-            Ok(StepResult::Executed)
+            output.check("command", &cmd, timeout)?;
+            Ok(StepResult::Executed {
+                stdout: output.stdout,
+            })
         }
+        "bundled" => match step.action.as_deref().unwrap_or("overlay") {
+            "overlay" => {
+                overlay_files(step, ctx, &working_dir)?;
+                Ok(StepResult::Executed {
+                    stdout: String::new(),
+                })
+            }
+            other => bail!("unknown bundled action '{other}'. Valid actions: overlay"),
+        },
         "git" => {
             let url = step
                 .url
                 .as_ref()
                 .ok_or_else(|| anyhow::anyhow!("git step missing 'url' field"))?;
-            let output = Command::new("git")
+            let mut command = Command::new("git");
+            command
                 .args(["clone", "--depth", "1", url])
-                .current_dir(&working_dir)
-                .output()
+                .current_dir(&working_dir);
+            let output = run_streamed(&mut command, ctx.output.as_ref(), timeout)
                 .with_context(|| format!("failed to clone: {url}"))?;
-            if !output.status.success() {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                bail!("git clone failed: {url}\n{stderr}");
-            }
-            Ok(StepResult::Executed)
+            output.check("git clone", url, timeout)?;
+            Ok(StepResult::Executed {
+                stdout: String::new(),
+            })
         }
         other => bail!("unknown step type '{other}'. Valid types: command, bundled, git"),
     }
 }
+
+struct CommandOutput {
+    /// `None` when the process was killed after its timeout.
+    status: Option<ExitStatus>,
+    stdout: String,
+    stderr: String,
+}
+
+impl CommandOutput {
+    fn check(&self, what: &'static str, target: &str, timeout: Option<Duration>) -> Result<()> {
+        match (self.status, timeout) {
+            (Some(status), _) if status.success() => Ok(()),
+            (None, Some(timeout)) => Err(CommandError::TimedOut {
+                what,
+                target: target.to_string(),
+                timeout,
+                stderr: self.stderr.clone(),
+            }
+            .into()),
+            _ => Err(CommandError::Failed {
+                what,
+                target: target.to_string(),
+                code: self.status.and_then(|status| status.code()),
+                stderr: self.stderr.clone(),
+            }
+            .into()),
+        }
+    }
+}
+
+/// Run `command` with piped stdout/stderr, forwarding each line to `output` as it arrives
+/// while also collecting both streams for capture and error messages. The process runs in
+/// its own process group so a timeout kills everything it started.
+fn run_streamed(
+    command: &mut Command,
+    output: Option<&StepOutput>,
+    timeout: Option<Duration>,
+) -> Result<CommandOutput> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .process_group(0)
+        .spawn()?;
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let stderr = child.stderr.take().expect("stderr is piped");
+
+    let (status, stdout, stderr) = thread::scope(|scope| {
+        let out = scope.spawn(|| read_lines(stdout, OutputStream::Stdout, output));
+        let err = scope.spawn(|| read_lines(stderr, OutputStream::Stderr, output));
+        let status = wait_with_timeout(&mut child, timeout);
+        (
+            status,
+            out.join().expect("stdout reader panicked"),
+            err.join().expect("stderr reader panicked"),
+        )
+    });
+
+    Ok(CommandOutput {
+        status: status?,
+        stdout: stdout?,
+        stderr: stderr?,
+    })
+}
+
+/// Wait for `child`, killing its process group once `timeout` has elapsed.
+/// Returns `None` if the process was killed.
+fn wait_with_timeout(child: &mut Child, timeout: Option<Duration>) -> Result<Option<ExitStatus>> {
+    let Some(timeout) = timeout else {
+        return Ok(Some(child.wait()?));
+    };
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if Instant::now() >= deadline {
+            kill_process_group(child.id());
+            child.wait()?;
+            return Ok(None);
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+fn kill_process_group(pgid: u32) {
+    // SAFETY: kill(2) has no memory-safety preconditions; a negative pid targets the
+    // process group created by `process_group(0)`, whose id equals the child's pid.
+    unsafe {
+        libc::kill(-(pgid as libc::pid_t), libc::SIGKILL);
+    }
+}
+
+fn read_lines(
+    pipe: impl Read,
+    stream: OutputStream,
+    output: Option<&StepOutput>,
+) -> Result<String> {
+    let mut reader = BufReader::new(pipe);
+    let mut collected = String::new();
+    let mut buf = Vec::new();
+    loop {
+        buf.clear();
+        if reader.read_until(b'\n', &mut buf)? == 0 {
+            break;
+        }
+        let line = String::from_utf8_lossy(&buf);
+        if let Some(output) = output {
+            output.line(stream, line.trim_end_matches(['\n', '\r']));
+        }
+        collected.push_str(&line);
+    }
+    Ok(collected)
+}
+
+/// Copy the step's `source` directory from the template into `dest`, rendering every
+/// relative path and every UTF-8 file body. Existing files are overwritten.
+fn overlay_files(step: &StepDef, ctx: &StepContext, dest: &Path) -> Result<()> {
+    let source_dir = step
+        .source
+        .as_deref()
+        .ok_or_else(|| anyhow::anyhow!("bundled step missing 'source' field"))?
+        .trim_start_matches("./")
+        .trim_end_matches('/');
+    let source = ctx.source.as_ref().ok_or_else(|| {
+        anyhow::anyhow!("bundled step requires template files, but the template source is unknown")
+    })?;
+    let renderer = Renderer::with_source(source.clone());
+
+    for rel in source.list_files(source_dir)? {
+        let rendered_rel = renderer
+            .render_string(&rel, &ctx.vars)
+            .with_context(|| format!("failed to render file path '{rel}'"))?;
+        let rendered_path = Path::new(&rendered_rel);
+        if rendered_path
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_)))
+        {
+            bail!("bundled file path '{rendered_rel}' must stay inside the target directory");
+        }
+
+        let data = source.read_file(&format!("{source_dir}/{rel}"))?;
+        let body = match String::from_utf8(data) {
+            Ok(text) => renderer
+                .render_string(&text, &ctx.vars)
+                .with_context(|| format!("failed to render file '{rel}'"))?
+                .into_bytes(),
+            Err(err) => err.into_bytes(),
+        };
+
+        let target = dest.join(rendered_path);
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("creating {}", parent.display()))?;
+        }
+        std::fs::write(&target, body).with_context(|| format!("writing {}", target.display()))?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,279 @@
+use anyhow::{Context, Result, bail};
+use reqwest::Url;
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Component, Path, PathBuf};
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use crate::remote::{
+    RefKind, TemplatePin, add_named_template_in, cache_dir, normalize_repo_path, template_root,
+    write_pin,
+};
+use crate::types::TemplateManifest;
+
+/// A registry index: the templates a team publishes and where to download them. Written
+/// as TOML (`[[template]]` tables) or JSON (`{"templates": [...]}`).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RegistryIndex {
+    #[serde(default, rename = "template", alias = "templates")]
+    pub templates: Vec<IndexEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexEntry {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub category: String,
+    #[serde(default)]
+    pub version: Option<Version>,
+    #[serde(flatten)]
+    pub source: IndexSource,
+}
+
+/// Download location of an index entry.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum IndexSource {
+    /// A git repository, cloned like `tforge add <git> --ref <ref> --path <path>`.
+    Git {
+        git: String,
+        #[serde(rename = "ref", default, skip_serializing_if = "Option::is_none")]
+        git_ref: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        path: Option<String>,
+    },
+    /// A `.tar.gz` archive, verified against `sha256` before it is unpacked. `path` is the
+    /// template directory inside the archive; a single top-level directory is found
+    /// without it.
+    Tarball {
+        tarball: String,
+        sha256: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        path: Option<String>,
+    },
+}
+
+impl RegistryIndex {
+    /// Parse an index document: JSON when it starts with `{`, TOML otherwise.
+    pub fn parse(content: &str) -> Result<Self> {
+        let index: Self = if content.trim_start().starts_with('{') {
+            serde_json::from_str(content).context("parsing JSON registry index")?
+        } else {
+            toml::from_str(content).context("parsing TOML registry index")?
+        };
+        for entry in &index.templates {
+            check_entry_name(&entry.name)?;
+        }
+        Ok(index)
+    }
+
+    /// The entry named `name`; the highest version if the index lists several.
+    pub fn find(&self, name: &str) -> Option<&IndexEntry> {
+        find_entry(&self.templates, name)
+    }
+}
+
+/// Entry names become cache directories (`<cache>/<name>`); anything but a single plain
+/// directory name could make an install write outside the cache.
+fn check_entry_name(name: &str) -> Result<()> {
+    let mut components = Path::new(name).components();
+    let single =
+        matches!(components.next(), Some(Component::Normal(_))) && components.next().is_none();
+    if !single || name.contains(['/', '\\']) {
+        bail!("invalid template name '{name}' in registry index: must be a plain directory name");
+    }
+    Ok(())
+}
+
+/// The entry named `name` among `entries`; the highest version if there are several.
+pub fn find_entry<'a>(entries: &'a [IndexEntry], name: &str) -> Option<&'a IndexEntry> {
+    entries
+        .iter()
+        .filter(|e| e.name == name)
+        .max_by(|a, b| a.version.cmp(&b.version))
+}
+
+/// Fetch and parse the index at `url` (`https://`, `http://` or `file://`). Relative
+/// tarball locations are resolved against `url`.
+pub async fn fetch_index(url: &str) -> Result<RegistryIndex> {
+    let data = fetch(url).await?;
+    let content = String::from_utf8(data)
+        .with_context(|| format!("registry index {url} is not valid UTF-8"))?;
+    let mut index =
+        RegistryIndex::parse(&content).with_context(|| format!("reading registry index {url}"))?;
+    let base = Url::parse(url).with_context(|| format!("invalid registry URL '{url}'"))?;
+    for entry in &mut index.templates {
+        if let IndexSource::Tarball { tarball, .. } = &mut entry.source {
+            *tarball = base
+                .join(tarball)
+                .with_context(|| format!("invalid tarball location '{tarball}'"))?
+                .to_string();
+        }
+    }
+    Ok(index)
+}
+
+/// Fetch every index in `urls`, in order. Indexes that cannot be fetched are returned as
+/// errors next to their URL so callers can warn and carry on.
+pub async fn fetch_indexes(urls: &[String]) -> Vec<(String, Result<RegistryIndex>)> {
+    let mut indexes = Vec::new();
+    for url in urls {
+        indexes.push((url.clone(), fetch_index(url).await));
+    }
+    indexes
+}
+
+/// Install an index entry into the template cache.
+pub async fn install(entry: &IndexEntry) -> Result<()> {
+    install_in(&cache_dir(), entry).await
+}
+
+/// Install an index entry into `cache` as `<cache>/<name>`.
+pub async fn install_in(cache: &Path, entry: &IndexEntry) -> Result<()> {
+    check_entry_name(&entry.name)?;
+    let dest = cache.join(&entry.name);
+    if dest.exists() {
+        bail!(
+            "template '{}' is already installed. Remove {} to reinstall it.",
+            entry.name,
+            dest.display()
+        );
+    }
+    match &entry.source {
+        IndexSource::Git { git, git_ref, path } => {
+            add_named_template_in(cache, &entry.name, git, git_ref.as_deref(), path.as_deref())?
+        }
+        IndexSource::Tarball {
+            tarball,
+            sha256,
+            path,
+        } => {
+            let data = fetch(tarball).await?;
+            let actual = format!("{:x}", Sha256::digest(&data));
+            if !actual.eq_ignore_ascii_case(sha256) {
+                bail!("checksum mismatch for {tarball}: expected sha256 {sha256}, got {actual}");
+            }
+            unpack_tarball(cache, &entry.name, &data, path.as_deref())?;
+            write_pin(
+                &dest,
+                &TemplatePin {
+                    url: tarball.clone(),
+                    git_ref: None,
+                    path: None,
+                    ref_kind: RefKind::Tarball,
+                    sha256: Some(actual),
+                },
+            )?;
+        }
+    }
+
+    // The cache entry is named after the index entry; the manifest has to agree.
+    let manifest_path = template_root(&dest)?.join("template.toml");
+    let manifest: Result<TemplateManifest> = std::fs::read_to_string(&manifest_path)
+        .with_context(|| format!("reading {}", manifest_path.display()))
+        .and_then(|content| {
+            toml::from_str(&content).with_context(|| format!("parsing {}", manifest_path.display()))
+        });
+    match manifest {
+        Ok(manifest) if manifest.template.name == entry.name => Ok(()),
+        Ok(manifest) => {
+            let _ = std::fs::remove_dir_all(&dest);
+            bail!(
+                "index entry '{}' points to a template named '{}'",
+                entry.name,
+                manifest.template.name
+            )
+        }
+        Err(err) => {
+            let _ = std::fs::remove_dir_all(&dest);
+            Err(err)
+        }
+    }
+}
+
+/// Unpack a `.tar.gz` into `<cache>/<name>`, keeping only the template directory.
+fn unpack_tarball(cache: &Path, name: &str, data: &[u8], path: Option<&str>) -> Result<()> {
+    let staging = cache.join(format!(".{name}.download"));
+    if staging.exists() {
+        std::fs::remove_dir_all(&staging)
+            .with_context(|| format!("removing {}", staging.display()))?;
+    }
+    std::fs::create_dir_all(&staging).with_context(|| format!("creating {}", staging.display()))?;
+
+    let result = tar::Archive::new(flate2::read::GzDecoder::new(data))
+        .unpack(&staging)
+        .context("unpacking template tarball")
+        .and_then(|()| archive_root(&staging, path))
+        .and_then(|root| {
+            std::fs::rename(&root, cache.join(name))
+                .with_context(|| format!("moving {} into the cache", root.display()))
+        });
+    let _ = std::fs::remove_dir_all(&staging);
+    result
+}
+
+/// Directory holding `template.toml` inside an unpacked archive.
+fn archive_root(staging: &Path, path: Option<&str>) -> Result<PathBuf> {
+    let root = match path {
+        Some(path) => staging.join(normalize_repo_path(path)?),
+        None if staging.join("template.toml").exists() => staging.to_path_buf(),
+        None => {
+            let dirs = std::fs::read_dir(staging)?
+                .map(|entry| entry.map(|e| e.path()))
+                .collect::<std::io::Result<Vec<_>>>()?;
+            match dirs.as_slice() {
+                [single] if single.is_dir() => single.clone(),
+                _ => staging.to_path_buf(),
+            }
+        }
+    };
+    if !root.join("template.toml").exists() {
+        bail!("tarball does not contain a template.toml — not a valid tforge template");
+    }
+    Ok(root)
+}
+
+/// How long to wait for a registry to accept a connection, and then for each read.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// HTTP client shared by all fetches. The timeouts keep one slow or dead registry from
+/// hanging `tforge list` and `tforge search`, which fetch every configured index.
+static HTTP: LazyLock<reqwest::Client> = LazyLock::new(|| {
+    reqwest::Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .read_timeout(READ_TIMEOUT)
+        .build()
+        .expect("building the HTTP client")
+});
+
+async fn fetch(url: &str) -> Result<Vec<u8>> {
+    let parsed = Url::parse(url).with_context(|| format!("invalid URL '{url}'"))?;
+    match parsed.scheme() {
+        "file" => {
+            let path = parsed
+                .to_file_path()
+                .map_err(|()| anyhow::anyhow!("invalid file URL '{url}'"))?;
+            std::fs::read(&path).with_context(|| format!("reading {}", path.display()))
+        }
+        "http" | "https" => {
+            let response = HTTP
+                .get(url)
+                .send()
+                .await
+                .with_context(|| format!("fetching {url}"))?
+                .error_for_status()
+                .with_context(|| format!("fetching {url}"))?;
+            let bytes = response
+                .bytes()
+                .await
+                .with_context(|| format!("reading response from {url}"))?;
+            Ok(bytes.to_vec())
+        }
+        other => bail!("unsupported URL scheme '{other}' in '{url}'"),
+    }
+}
@@ -1,15 +1,21 @@
+pub mod capture;
 pub mod cli;
 pub mod condition;
 pub mod config;
 pub mod embedded;
 pub mod engine;
 pub mod executor;
+pub mod index;
 pub mod llm;
+pub mod lock;
+pub mod progress;
 pub mod prompts;
 pub mod registry;
 pub mod remote;
 pub mod renderer;
 pub mod resolver;
+pub mod source;
 pub mod state;
 pub mod toolcheck;
 pub mod types;
+pub mod vars;
@@ -1,5 +1,6 @@
 use crate::config::{LlmConfig, LlmProvider};
 use crate::registry::Registry;
+use crate::vars::Value;
 use anyhow::{Context, Result};
 use rig::client::CompletionClient;
 use rig::completion::Prompt;
@@ -9,7 +10,7 @@ use std::collections::HashMap;
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LlmRecipe {
     pub templates: Vec<String>,
-    pub parameters: HashMap<String, String>,
+    pub parameters: HashMap<String, Value>,
 }
 
 pub fn parse_llm_recipe_response(json: &str) -> Result<LlmRecipe> {
@@ -30,17 +31,28 @@ pub fn build_system_prompt(registry: &Registry) -> String {
             "- {} ({}): {}\n",
             tmpl.template.name, tmpl.template.category, tmpl.template.description
         ));
-        for (key, param) in &tmpl.parameters {
+        for (key, param) in tmpl.parameters.iter().filter(|(_, p)| !p.computed) {
             prompt.push_str(&format!("  param '{}': {} ", key, param.prompt));
             if !param.options.is_empty() {
                 prompt.push_str(&format!("options: [{}] ", param.options.join(", ")));
             }
+            if let Some(pattern) = &param.pattern {
+                prompt.push_str(&format!("must match: {pattern} "));
+            }
+            if let Some(help) = &param.help {
+                prompt.push_str(&format!("({help}) "));
+            }
             prompt.push('\n');
         }
         if !tmpl.dependencies.requires_templates.is_empty() {
             prompt.push_str(&format!(
                 "  requires: [{}]\n",
-                tmpl.dependencies.requires_templates.join(", ")
+                tmpl.dependencies
+                    .requires_templates
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
             ));
         }
     }
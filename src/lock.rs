@@ -0,0 +1,162 @@
+use anyhow::{Context, Result};
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::Path;
+
+use crate::remote::head_commit;
+use crate::source::TemplateSource;
+use crate::types::TemplateManifest;
+
+/// File name of the lockfile written next to `tforge.toml`.
+pub const LOCK_FILE: &str = "tforge.lock";
+
+const LOCK_HEADER: &str =
+    "# Written by tforge. Records the templates this project was built with.\n\n";
+
+/// The exact templates a project was last run with, so a later `tforge resume` can tell
+/// when a template changed underneath it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Lockfile {
+    #[serde(default, rename = "template")]
+    pub templates: Vec<LockedTemplate>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LockedTemplate {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<Version>,
+    /// Where the template was loaded from: `embedded`, `local` or `cache`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// Commit of the cached template's git checkout.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    /// SHA-256 over the template's files (see [`TemplateSource::content_hash`]).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+}
+
+impl LockedTemplate {
+    pub fn from_template(template: &TemplateManifest) -> Result<Self> {
+        let source = template.source.as_ref();
+        let hash = source
+            .map(TemplateSource::content_hash)
+            .transpose()
+            .with_context(|| format!("hashing template '{}'", template.template.name))?;
+        let commit = match source {
+            Some(TemplateSource::Cached(root)) => head_commit(root),
+            _ => None,
+        };
+        Ok(Self {
+            name: template.template.name.clone(),
+            version: template.template.version.clone(),
+            source: source.map(|s| source_kind(s).to_string()),
+            commit,
+            hash,
+        })
+    }
+
+    /// Version and short content hash, e.g. `1.2.0 (3f2a9c01b7de)`.
+    fn describe(&self) -> String {
+        let version = self
+            .version
+            .as_ref()
+            .map_or_else(|| "unversioned".to_string(), Version::to_string);
+        match &self.hash {
+            Some(hash) => format!("{version} ({})", &hash[..hash.len().min(12)]),
+            None => version,
+        }
+    }
+}
+
+/// A resolved template that does not match its `tforge.lock` entry.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LockMismatch {
+    pub name: String,
+    /// `None` when the template is not in the lockfile at all.
+    pub locked: Option<LockedTemplate>,
+    pub current: LockedTemplate,
+}
+
+impl fmt::Display for LockMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.locked {
+            Some(locked) => write!(
+                f,
+                "{}: locked {}, now {}",
+                self.name,
+                locked.describe(),
+                self.current.describe()
+            ),
+            None => write!(
+                f,
+                "{}: not in {LOCK_FILE}, now {}",
+                self.name,
+                self.current.describe()
+            ),
+        }
+    }
+}
+
+impl Lockfile {
+    /// Lock the given templates as currently resolved.
+    pub fn from_templates(templates: &[TemplateManifest]) -> Result<Self> {
+        let mut templates = templates
+            .iter()
+            .map(LockedTemplate::from_template)
+            .collect::<Result<Vec<_>>>()?;
+        templates.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(Self { templates })
+    }
+
+    /// Load a lockfile; `None` if the project has none yet.
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let lock = toml::from_str(&content)
+            .with_context(|| format!("failed to parse {}", path.display()))?;
+        Ok(Some(lock))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let content = toml::to_string_pretty(self).context("failed to serialize lockfile")?;
+        std::fs::write(path, format!("{LOCK_HEADER}{content}"))
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&LockedTemplate> {
+        self.templates.iter().find(|t| t.name == name)
+    }
+
+    /// Templates in `current` whose version or content differ from this lockfile, or that
+    /// it does not list. Locked templates missing from `current` are not reported.
+    pub fn mismatches(&self, current: &Lockfile) -> Vec<LockMismatch> {
+        current
+            .templates
+            .iter()
+            .filter_map(|now| {
+                let locked = self.get(&now.name);
+                let matches = locked
+                    .is_some_and(|locked| locked.version == now.version && locked.hash == now.hash);
+                (!matches).then(|| LockMismatch {
+                    name: now.name.clone(),
+                    locked: locked.cloned(),
+                    current: now.clone(),
+                })
+            })
+            .collect()
+    }
+}
+
+fn source_kind(source: &TemplateSource) -> &'static str {
+    match source {
+        TemplateSource::Embedded { .. } => "embedded",
+        TemplateSource::Local(_) => "local",
+        TemplateSource::Cached(_) => "cache",
+    }
+}
@@ -1,33 +1,76 @@
 use anyhow::{Context, Result, bail};
 use clap::Parser;
-use indicatif::{ProgressBar, ProgressStyle};
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use inquire::{Confirm, Select, Text};
 use owo_colors::OwoColorize;
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeSet, HashMap, HashSet};
 use std::fs;
 use std::path::Path;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
-use tforge::cli::{Cli, Commands};
+use tforge::cli::{AddTemplateArgs, ApplyArgs, Cli, Commands, DestroyArgs, NewArgs};
 use tforge::config::{LlmConfig, LlmProvider, TforgeConfig};
-use tforge::engine::Engine;
+use tforge::engine::{Engine, PlanAction, PlannedStep};
+use tforge::index::{IndexEntry, fetch_indexes, find_entry};
 use tforge::llm::{build_system_prompt, parse_llm_recipe_response, query_llm};
-use tforge::prompts::{RecipeSelection, prompt_recipe};
+use tforge::lock::{LOCK_FILE, Lockfile};
+use tforge::progress::{OutputStream, Reporter, RunLog, StepInfo, StepOutcome};
+use tforge::prompts::{
+    RecipeSelection, apply_parameter_defaults, compute_parameters, prompt_parameters, prompt_recipe,
+};
 use tforge::registry::Registry;
-use tforge::state::{PipelineState, StepState};
+use tforge::resolver::{ResolveError, check_versions};
+use tforge::state::{PipelineState, StepRecord, StepStatus};
 use tforge::toolcheck::{ToolStatus, check_tool, install_hint};
-use tforge::types::TemplateManifest;
+use tforge::types::{TemplateManifest, step_keys};
+use tforge::vars::{Value, coerce_parameter_values, load_var_file, parse_set_arg};
 
 const TEMPLATE_ROOT: &str = "templates";
 const STATE_FILE: &str = ".tforge-state.json";
 const RECIPE_FILE: &str = "tforge.toml";
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 struct SavedRecipe {
     project_name: String,
     templates: Vec<String>,
     #[serde(default)]
-    parameters: HashMap<String, String>,
+    parameters: HashMap<String, Value>,
+}
+
+impl SavedRecipe {
+    /// Variables to run the recipe with: its parameters converted to the types the
+    /// templates declare, plus `project_name` and the computed parameters of the recipe's
+    /// templates. Recipes saved before variables were typed hold every value as a string.
+    fn vars(&self, templates: &[TemplateManifest]) -> Result<HashMap<String, Value>> {
+        let mut vars = self.parameters.clone();
+        coerce_parameter_values(templates, &mut vars)?;
+        vars.insert("project_name".into(), self.project_name.as_str().into());
+        let saved: Vec<TemplateManifest> = templates
+            .iter()
+            .filter(|t| self.templates.contains(&t.template.name))
+            .cloned()
+            .collect();
+        compute_parameters(&saved, &mut vars)?;
+        Ok(vars)
+    }
+}
+
+/// `vars` without the computed parameters of `templates`, which are recomputed on every
+/// load instead of saved.
+fn saved_parameters(
+    templates: &[TemplateManifest],
+    vars: &HashMap<String, Value>,
+) -> HashMap<String, Value> {
+    let mut parameters = vars.clone();
+    for tmpl in templates {
+        for (key, param) in &tmpl.parameters {
+            if param.computed {
+                parameters.remove(key);
+            }
+        }
+    }
+    parameters
 }
 
 #[tokio::main(flavor = "current_thread")]
@@ -42,40 +85,78 @@ async fn run() -> Result<()> {
     let cli = Cli::parse();
 
     match cli.command {
-        Commands::New { name, ai } => run_new(&name, ai.as_deref()).await,
-        Commands::List => run_list(),
-        Commands::Search { query } => run_search(&query),
-        Commands::Add { url } => run_add(&url),
-        Commands::Resume => run_resume(),
+        Commands::New(args) => run_new(&args).await,
+        Commands::List => run_list().await,
+        Commands::Search { query } => run_search(&query).await,
+        Commands::Install { name } => run_install(&name).await,
+        Commands::Add { url, git_ref, path } => run_add(&url, git_ref.as_deref(), path.as_deref()),
+        Commands::Plan { json } => run_plan(json),
+        Commands::Apply(args) => run_apply(&args),
+        Commands::AddTemplate(args) => run_add_template(&args),
+        Commands::Resume {
+            jobs,
+            ignore_drift,
+            ignore_lock,
+        } => run_resume(jobs, ignore_drift, ignore_lock),
+        Commands::Destroy(args) => run_destroy(&args),
         Commands::Status => run_status(),
+        Commands::Log { template, step } => run_log(&template, step.as_deref()),
         Commands::Update => run_update(),
         Commands::Config { target, show } => run_config(&target, show),
     }
 }
 
-async fn run_new(project_name: &str, ai: Option<&str>) -> Result<()> {
+async fn run_new(args: &NewArgs) -> Result<()> {
+    let project_name = args.name.as_str();
     let registry = load_registry()?;
-
-    let mut selection = match ai {
-        Some(prompt) => select_recipe_with_ai(&registry, project_name, prompt).await?,
-        None => prompt_recipe(&registry, project_name)?,
+    let preset = preset_vars(&args.set, args.var_file.as_deref())?;
+
+    let mut selection = if let Some(prompt) = &args.ai {
+        let mut selection = select_recipe_with_ai(&registry, project_name, prompt).await?;
+        selection.vars.extend(preset);
+        selection
+    } else if !args.templates.is_empty() {
+        RecipeSelection {
+            templates: resolve_recipe_templates(&args.templates, &registry)?,
+            vars: preset,
+        }
+    } else if args.yes {
+        bail!("--yes needs templates to run. Pass them with --template <name> or use --ai.");
+    } else {
+        prompt_recipe(&registry, project_name, preset)?
     };
     selection
         .vars
-        .insert("project_name".to_string(), project_name.to_string());
+        .insert("project_name".to_string(), project_name.into());
 
     if selection.templates.is_empty() {
         bail!("No templates selected. Run `tforge new {project_name}` again.");
     }
 
     let templates = expand_required_templates(&selection.templates, &registry)?;
+    if args.yes {
+        apply_parameter_defaults(&templates, &mut selection.vars)?;
+    } else {
+        prompt_parameters(&templates, &mut selection.vars)?;
+    }
+
+    if args.dry_run {
+        let cwd = std::env::current_dir().context("failed to determine current directory")?;
+        let plan = Engine::new(cwd).plan(&templates, &selection.vars)?;
+        if !args.json {
+            print_recipe_summary(project_name, &templates, &selection.vars);
+        }
+        return print_plan(&plan, args.json);
+    }
+
     ensure_tools_available(&templates)?;
     print_recipe_summary(project_name, &templates, &selection.vars);
 
-    let confirmed = Confirm::new("Proceed with execution?")
-        .with_default(true)
-        .prompt()
-        .context("execution confirmation cancelled")?;
+    let confirmed = args.yes
+        || Confirm::new("Proceed with execution?")
+            .with_default(true)
+            .prompt()
+            .context("execution confirmation cancelled")?;
     if !confirmed {
         println!("{}", "Aborted.".yellow());
         return Ok(());
@@ -84,46 +165,81 @@ async fn run_new(project_name: &str, ai: Option<&str>) -> Result<()> {
     let cwd = std::env::current_dir().context("failed to determine current directory")?;
     let state_path = cwd.join(STATE_FILE);
     let recipe_path = cwd.join(RECIPE_FILE);
+    let lock_path = cwd.join(LOCK_FILE);
 
     let saved_recipe = SavedRecipe {
         project_name: project_name.to_string(),
         templates: templates.iter().map(|t| t.template.name.clone()).collect(),
-        parameters: selection.vars.clone(),
+        parameters: saved_parameters(&templates, &selection.vars),
     };
     save_recipe(&saved_recipe, &recipe_path)?;
-
-    let progress = spinner("Running template pipeline...");
-    let run_result =
-        Engine::new(cwd).run_with_state(&templates, &selection.vars, &state_path, false);
+    Lockfile::from_templates(&templates)?.save(&lock_path)?;
+
+    let reporter = TerminalReporter::create(&cwd)?;
+    let log_path = reporter.log.path().to_path_buf();
+    let run_result = Engine::new(cwd)
+        .with_reporter(reporter)
+        .with_jobs(args.jobs)
+        .run_with_state(&templates, &selection.vars, &state_path, false);
     match run_result {
         Ok(()) => {
-            progress
-                .finish_with_message(format!("Project '{project_name}' scaffolded successfully."));
+            println!(
+                "{}",
+                format!("Project '{project_name}' scaffolded successfully.").green()
+            );
             println!("Recipe saved: {}", recipe_path.display());
+            println!("Lockfile saved: {}", lock_path.display());
             println!("State saved: {}", state_path.display());
+            println!("Log saved: {}", log_path.display());
             Ok(())
         }
         Err(err) => {
-            progress.abandon_with_message(
+            eprintln!(
+                "{}",
                 "Pipeline failed. Run `tforge status` for details and `tforge resume` to retry."
-                    .to_string(),
+                    .yellow()
             );
+            eprintln!("Full output: {}", log_path.display());
             Err(err)
         }
     }
 }
 
-fn run_list() -> Result<()> {
+async fn run_list() -> Result<()> {
     let registry = load_registry()?;
+    let installable = index_entries_not_installed(&registry).await;
 
     println!("{}", "Available templates".bold());
     for category in registry.categories() {
         println!();
         println!("{} {}", category.bold(), "templates:".bold());
         for template in registry.by_category(&category) {
+            let version = template
+                .template
+                .version
+                .as_ref()
+                .map_or_else(|| "-".to_string(), ToString::to_string);
+            println!(
+                "  - {:<20} {:<8} {}",
+                template.template.name, version, template.template.description
+            );
+        }
+    }
+
+    if !installable.is_empty() {
+        println!();
+        println!(
+            "{}",
+            "Available from registries (`tforge install <name>`):".bold()
+        );
+        for entry in &installable {
+            let version = entry
+                .version
+                .as_ref()
+                .map_or_else(|| "-".to_string(), ToString::to_string);
             println!(
-                "  - {:<20} {}",
-                template.template.name, template.template.description
+                "  - {:<20} {:<8} {}",
+                entry.name, version, entry.description
             );
         }
     }
@@ -131,8 +247,9 @@ fn run_list() -> Result<()> {
     Ok(())
 }
 
-fn run_search(query: &str) -> Result<()> {
+async fn run_search(query: &str) -> Result<()> {
     let registry = load_registry()?;
+    let installable = index_entries_not_installed(&registry).await;
     let needle = query.to_lowercase();
     let mut matches = Vec::new();
 
@@ -145,7 +262,16 @@ fn run_search(query: &str) -> Result<()> {
         }
     }
 
-    if matches.is_empty() {
+    let index_matches: Vec<&IndexEntry> = installable
+        .iter()
+        .filter(|e| {
+            [&e.name, &e.category, &e.description]
+                .iter()
+                .any(|field| field.to_lowercase().contains(&needle))
+        })
+        .collect();
+
+    if matches.is_empty() && index_matches.is_empty() {
         println!("No templates matched query '{query}'.");
         return Ok(());
     }
@@ -157,13 +283,87 @@ fn run_search(query: &str) -> Result<()> {
             template.template.name, template.template.category, template.template.description
         );
     }
+    for entry in index_matches {
+        println!(
+            "  - {} ({}) — {} {}",
+            entry.name,
+            entry.category,
+            entry.description,
+            "[not installed]".dimmed()
+        );
+    }
 
     Ok(())
 }
 
-fn run_add(url: &str) -> Result<()> {
+async fn run_install(name: &str) -> Result<()> {
+    let registry = load_registry()?;
+    if registry.find(name).is_some() {
+        println!("Template '{name}' is already available.");
+        return Ok(());
+    }
+    let entries = registry_index_entries().await?;
+    let Some(entry) = find_entry(&entries, name) else {
+        bail!(
+            "template '{name}' is not listed in any configured registry index. Run `tforge list` to see available templates."
+        );
+    };
+
+    let progress = spinner(&format!("Installing '{name}'..."));
+    match tforge::index::install(entry).await {
+        Ok(()) => {
+            let version = entry
+                .version
+                .as_ref()
+                .map(|v| format!(" {v}"))
+                .unwrap_or_default();
+            progress.finish_with_message(format!("Installed template '{name}'{version}."));
+            Ok(())
+        }
+        Err(err) => {
+            progress.abandon_with_message("Failed to install template.".to_string());
+            Err(err.context(format!("failed to install template '{name}'")))
+        }
+    }
+}
+
+/// Entries of every registry index in the config. Indexes that cannot be fetched are
+/// reported as warnings.
+async fn registry_index_entries() -> Result<Vec<IndexEntry>> {
+    let path = TforgeConfig::default_path();
+    let config = TforgeConfig::load(&path)
+        .with_context(|| format!("failed to load config from {}", path.display()))?;
+    let mut entries = Vec::new();
+    for (url, index) in fetch_indexes(&config.registries).await {
+        match index {
+            Ok(index) => entries.extend(index.templates),
+            Err(err) => eprintln!("warning: registry index {url}: {err:#}"),
+        }
+    }
+    Ok(entries)
+}
+
+/// The newest entry of each index template that is not in the registry yet, by name.
+/// Registries that cannot be read only produce a warning, so listing still works.
+async fn index_entries_not_installed(registry: &Registry) -> Vec<IndexEntry> {
+    let entries = registry_index_entries().await.unwrap_or_else(|err| {
+        eprintln!("warning: {err:#}");
+        Vec::new()
+    });
+    let names: BTreeSet<&str> = entries
+        .iter()
+        .map(|e| e.name.as_str())
+        .filter(|name| registry.find(name).is_none())
+        .collect();
+    names
+        .into_iter()
+        .filter_map(|name| find_entry(&entries, name).cloned())
+        .collect()
+}
+
+fn run_add(url: &str, git_ref: Option<&str>, path: Option<&str>) -> Result<()> {
     let progress = spinner("Adding template...");
-    match tforge::remote::add_template(url) {
+    match tforge::remote::add_template(url, git_ref, path) {
         Ok(name) => {
             progress.finish_with_message(format!("Added template '{name}' to cache."));
             Ok(())
@@ -178,15 +378,19 @@ fn run_add(url: &str) -> Result<()> {
 fn run_update() -> Result<()> {
     let progress = spinner("Updating cached templates...");
     match tforge::remote::update_templates() {
-        Ok(updated) => {
-            if updated.is_empty() {
+        Ok(report) => {
+            if report.updated.is_empty() {
                 progress.finish_with_message("No cached templates to update.");
             } else {
-                progress.finish_with_message(format!("Updated {} template(s).", updated.len()));
-                for name in &updated {
+                progress
+                    .finish_with_message(format!("Updated {} template(s).", report.updated.len()));
+                for name in &report.updated {
                     println!("  - {name}");
                 }
             }
+            for (name, git_ref) in &report.pinned {
+                println!("  - {name} (pinned to {git_ref}, not updated)");
+            }
             Ok(())
         }
         Err(err) => {
@@ -196,7 +400,26 @@ fn run_update() -> Result<()> {
     }
 }
 
-fn run_resume() -> Result<()> {
+fn run_plan(json: bool) -> Result<()> {
+    let cwd = std::env::current_dir().context("failed to determine current directory")?;
+    let recipe_path = cwd.join(RECIPE_FILE);
+    let state_path = cwd.join(STATE_FILE);
+    let registry = load_registry()?;
+
+    let recipe = load_recipe(&recipe_path)?;
+    let templates = resolve_recipe_templates(&recipe.templates, &registry)?;
+    let vars = recipe.vars(&templates)?;
+
+    let engine = Engine::new(cwd);
+    let plan = if state_path.exists() {
+        engine.plan_with_state(&templates, &vars, &state_path)?
+    } else {
+        engine.plan(&templates, &vars)?
+    };
+    print_plan(&plan, json)
+}
+
+fn run_resume(jobs: usize, ignore_drift: bool, ignore_lock: bool) -> Result<()> {
     let cwd = std::env::current_dir().context("failed to determine current directory")?;
     let recipe_path = cwd.join(RECIPE_FILE);
     let state_path = cwd.join(STATE_FILE);
@@ -211,25 +434,470 @@ fn run_resume() -> Result<()> {
 
     let recipe = load_recipe(&recipe_path)?;
     let templates = resolve_recipe_templates(&recipe.templates, &registry)?;
-    let mut vars = recipe.parameters.clone();
-    vars.insert("project_name".into(), recipe.project_name.clone());
+    let vars = recipe.vars(&templates)?;
+
+    let lock_path = cwd.join(LOCK_FILE);
+    let current_lock = Lockfile::from_templates(&templates)?;
+    let locked = Lockfile::load(&lock_path)?;
+    if let Some(lock) = &locked {
+        let mismatches = lock.mismatches(&current_lock);
+        if !mismatches.is_empty() {
+            let list = mismatches
+                .iter()
+                .map(|m| format!("  - {m}"))
+                .collect::<Vec<_>>()
+                .join("\n");
+            if !ignore_lock {
+                bail!(
+                    "templates no longer match {LOCK_FILE}:\n{list}\n\
+                     Run `tforge apply` to review the changes, or `tforge resume --ignore-lock` \
+                     to continue with the current templates."
+                );
+            }
+            println!(
+                "{}",
+                format!("Templates no longer match {LOCK_FILE}; continuing with --ignore-lock:")
+                    .yellow()
+            );
+            println!("{list}\n");
+        }
+    }
+    if locked.as_ref() != Some(&current_lock) {
+        current_lock.save(&lock_path)?;
+    }
+
+    let engine = Engine::new(cwd.clone())
+        .with_jobs(jobs)
+        .with_rerun_drifted(!ignore_drift);
+    let changes = engine.resume_changes(&templates, &vars, &state_path)?;
+    if !changes.is_empty() {
+        println!("{}", "Templates changed since the last run:".bold());
+        for change in &changes {
+            println!("  - {change}");
+        }
+        println!();
+    }
 
-    let progress = spinner("Resuming template pipeline...");
-    let run_result = Engine::new(cwd).run_with_state(&templates, &vars, &state_path, true);
+    let reporter = TerminalReporter::create(&cwd)?;
+    let log_path = reporter.log.path().to_path_buf();
+    let run_result =
+        engine
+            .with_reporter(reporter)
+            .run_with_state(&templates, &vars, &state_path, true);
     match run_result {
         Ok(()) => {
-            progress.finish_with_message("Resume completed successfully.");
+            println!("{}", "Resume completed successfully.".green());
+            println!("Log saved: {}", log_path.display());
             Ok(())
         }
         Err(err) => {
-            progress.abandon_with_message(
-                "Resume failed. See `tforge status` for details.".to_string(),
+            eprintln!(
+                "{}",
+                "Resume failed. See `tforge status` for details.".yellow()
             );
+            eprintln!("Full output: {}", log_path.display());
             Err(err)
         }
     }
 }
 
+fn run_apply(args: &ApplyArgs) -> Result<()> {
+    let cwd = std::env::current_dir().context("failed to determine current directory")?;
+    let recipe_path = cwd.join(RECIPE_FILE);
+    let state_path = cwd.join(STATE_FILE);
+    let registry = load_registry()?;
+
+    let recipe = load_recipe(&recipe_path)?;
+    let selected = resolve_recipe_templates(&recipe.templates, &registry)?;
+    let templates = expand_required_templates(&selected, &registry)?;
+    let mut vars = recipe.vars(&templates)?;
+    if args.yes {
+        apply_parameter_defaults(&templates, &mut vars)?;
+    } else {
+        prompt_parameters(&templates, &mut vars)?;
+    }
+    let mut parameters = saved_parameters(&templates, &vars);
+    if !recipe.parameters.contains_key("project_name") {
+        parameters.remove("project_name");
+    }
+    let desired = SavedRecipe {
+        project_name: recipe.project_name.clone(),
+        templates: templates.iter().map(|t| t.template.name.clone()).collect(),
+        parameters,
+    };
+
+    let engine = Engine::new(cwd.clone())
+        .with_jobs(args.jobs)
+        .with_rerun_drifted(!args.ignore_drift);
+    let plan = engine.plan_with_state(&templates, &vars, &state_path)?;
+    if args.dry_run && args.json {
+        return print_plan(&plan, true);
+    }
+
+    let added: Vec<&str> = desired
+        .templates
+        .iter()
+        .filter(|name| !recipe.templates.contains(name))
+        .map(String::as_str)
+        .collect();
+    if !added.is_empty() {
+        println!(
+            "{} {}",
+            "Required templates added:".bold(),
+            added.join(", ")
+        );
+    }
+    let changes = engine.resume_changes(&templates, &vars, &state_path)?;
+    if !changes.is_empty() {
+        println!("{}", "Changed since the last run:".bold());
+        for change in &changes {
+            println!("  - {change}");
+        }
+    }
+    let lock_path = cwd.join(LOCK_FILE);
+    let current_lock = Lockfile::from_templates(&templates)?;
+    let locked = Lockfile::load(&lock_path)?;
+    if let Some(lock) = &locked {
+        let updated: Vec<_> = lock
+            .mismatches(&current_lock)
+            .into_iter()
+            .filter(|m| m.locked.is_some())
+            .collect();
+        if !updated.is_empty() {
+            println!("{}", format!("Templates updated since {LOCK_FILE}:").bold());
+            for mismatch in &updated {
+                println!("  - {mismatch}");
+            }
+        }
+    }
+    let lock_changed = locked.as_ref() != Some(&current_lock);
+    let state = PipelineState::load_for(&state_path, &templates)
+        .with_context(|| format!("failed to load {}", state_path.display()))?;
+    for name in state.templates() {
+        if !desired.templates.iter().any(|t| t == name) {
+            println!(
+                "{}",
+                format!(
+                    "Template '{name}' is no longer in {RECIPE_FILE}; its resources are left in place."
+                )
+                .yellow()
+            );
+        }
+    }
+
+    let to_run: BTreeSet<&str> = plan
+        .iter()
+        .filter(|step| step.action == PlanAction::Run)
+        .map(|step| step.template.as_str())
+        .collect();
+    if to_run.is_empty() {
+        if !args.dry_run {
+            if desired != recipe {
+                save_recipe(&desired, &recipe_path)?;
+            }
+            if lock_changed {
+                current_lock.save(&lock_path)?;
+            }
+        }
+        println!("Nothing to apply: the project matches {RECIPE_FILE}.");
+        return Ok(());
+    }
+    print_plan(&plan, false)?;
+    if args.dry_run {
+        return Ok(());
+    }
+
+    let run_templates: Vec<TemplateManifest> = templates
+        .iter()
+        .filter(|t| to_run.contains(t.template.name.as_str()))
+        .cloned()
+        .collect();
+    ensure_tools_available(&run_templates)?;
+    let confirmed = args.yes
+        || Confirm::new("Apply these changes?")
+            .with_default(true)
+            .prompt()
+            .context("apply confirmation cancelled")?;
+    if !confirmed {
+        println!("{}", "Aborted.".yellow());
+        return Ok(());
+    }
+    if desired != recipe {
+        save_recipe(&desired, &recipe_path)?;
+    }
+    if lock_changed {
+        current_lock.save(&lock_path)?;
+    }
+
+    let reporter = TerminalReporter::create(&cwd)?;
+    let log_path = reporter.log.path().to_path_buf();
+    let run_result =
+        engine
+            .with_reporter(reporter)
+            .run_with_state(&templates, &vars, &state_path, true);
+    match run_result {
+        Ok(()) => {
+            println!(
+                "{}",
+                format!("Project is up to date with {RECIPE_FILE}.").green()
+            );
+            println!("Log saved: {}", log_path.display());
+            Ok(())
+        }
+        Err(err) => {
+            eprintln!(
+                "{}",
+                "Apply failed. Run `tforge status` for details and `tforge apply` or `tforge resume` to retry."
+                    .yellow()
+            );
+            eprintln!("Full output: {}", log_path.display());
+            Err(err)
+        }
+    }
+}
+
+fn run_add_template(args: &AddTemplateArgs) -> Result<()> {
+    let cwd = std::env::current_dir().context("failed to determine current directory")?;
+    let recipe_path = cwd.join(RECIPE_FILE);
+    let state_path = cwd.join(STATE_FILE);
+    let registry = load_registry()?;
+
+    let recipe = load_recipe(&recipe_path)?;
+    let mut selected = resolve_recipe_templates(&recipe.templates, &registry)?;
+    for name in &args.templates {
+        if recipe.templates.contains(name) {
+            println!("Template '{name}' is already part of the project.");
+            continue;
+        }
+        let template = registry.find(name).ok_or_else(|| {
+            anyhow::anyhow!(
+                "template '{name}' not found. Run `tforge list` to see available templates."
+            )
+        })?;
+        selected.push(template.clone());
+    }
+    let templates = expand_required_templates(&selected, &registry)?;
+    let added: Vec<TemplateManifest> = templates
+        .iter()
+        .filter(|t| !recipe.templates.contains(&t.template.name))
+        .cloned()
+        .collect();
+    if added.is_empty() {
+        println!("Nothing to add.");
+        return Ok(());
+    }
+    let added_names: Vec<String> = added.iter().map(|t| t.template.name.clone()).collect();
+
+    let mut vars = recipe.vars(&templates)?;
+    vars.extend(preset_vars(&args.set, args.var_file.as_deref())?);
+    vars.insert("project_name".into(), recipe.project_name.as_str().into());
+    if args.yes {
+        apply_parameter_defaults(&added, &mut vars)?;
+    } else {
+        prompt_parameters(&added, &mut vars)?;
+    }
+
+    let engine = Engine::new(cwd.clone())
+        .with_jobs(args.jobs)
+        .with_only(added_names.clone());
+    let plan = engine.plan_with_state(&templates, &vars, &state_path)?;
+    if !args.json {
+        println!("{} {}", "Adding templates:".bold(), added_names.join(", "));
+    }
+    print_plan(&plan, args.json)?;
+    if args.dry_run {
+        return Ok(());
+    }
+
+    ensure_tools_available(&added)?;
+    let confirmed = args.yes
+        || Confirm::new("Proceed with execution?")
+            .with_default(true)
+            .prompt()
+            .context("execution confirmation cancelled")?;
+    if !confirmed {
+        println!("{}", "Aborted.".yellow());
+        return Ok(());
+    }
+
+    let mut parameters = saved_parameters(&templates, &vars);
+    if !recipe.parameters.contains_key("project_name") {
+        parameters.remove("project_name");
+    }
+    save_recipe(
+        &SavedRecipe {
+            project_name: recipe.project_name.clone(),
+            templates: templates.iter().map(|t| t.template.name.clone()).collect(),
+            parameters,
+        },
+        &recipe_path,
+    )?;
+    Lockfile::from_templates(&templates)?.save(&cwd.join(LOCK_FILE))?;
+
+    let reporter = TerminalReporter::create(&cwd)?;
+    let log_path = reporter.log.path().to_path_buf();
+    let run_result =
+        engine
+            .with_reporter(reporter)
+            .run_with_state(&templates, &vars, &state_path, true);
+    match run_result {
+        Ok(()) => {
+            println!(
+                "{}",
+                format!("Added {} to the project.", added_names.join(", ")).green()
+            );
+            println!("Log saved: {}", log_path.display());
+            Ok(())
+        }
+        Err(err) => {
+            eprintln!(
+                "{}",
+                "Pipeline failed. Run `tforge status` for details and `tforge resume` to retry."
+                    .yellow()
+            );
+            eprintln!("Full output: {}", log_path.display());
+            Err(err)
+        }
+    }
+}
+
+fn run_destroy(args: &DestroyArgs) -> Result<()> {
+    let cwd = std::env::current_dir().context("failed to determine current directory")?;
+    let recipe_path = cwd.join(RECIPE_FILE);
+    let state_path = cwd.join(STATE_FILE);
+    let registry = load_registry()?;
+
+    let recipe = load_recipe(&recipe_path)?;
+    let templates = resolve_recipe_templates(&recipe.templates, &registry)?;
+    let vars = recipe.vars(&templates)?;
+
+    let engine = Engine::new(cwd.clone());
+    let plan = engine.plan_destroy(&templates, &vars, &state_path, &args.templates)?;
+    if args.dry_run {
+        return print_plan(&plan, args.json);
+    }
+    if plan.is_empty() {
+        println!("Nothing to destroy: no provisioned template declares destroy steps.");
+        return Ok(());
+    }
+
+    let mut targets: Vec<&str> = Vec::new();
+    for step in &plan {
+        if !targets.contains(&step.template.as_str()) {
+            targets.push(&step.template);
+        }
+    }
+    println!("{}", "Templates to destroy (in this order):".bold());
+    for name in &targets {
+        println!("  - {name}");
+    }
+    let confirmed = args.yes
+        || Confirm::new("Destroy these resources? This cannot be undone.")
+            .with_default(false)
+            .prompt()
+            .context("destroy confirmation cancelled")?;
+    if !confirmed {
+        println!("{}", "Aborted.".yellow());
+        return Ok(());
+    }
+
+    let reporter = TerminalReporter::create(&cwd)?;
+    let log_path = reporter.log.path().to_path_buf();
+    let run_result = Engine::new(cwd).with_reporter(reporter).destroy(
+        &templates,
+        &vars,
+        &state_path,
+        &args.templates,
+    );
+    match run_result {
+        Ok(destroyed) => {
+            println!(
+                "{}",
+                format!("Destroyed {} template(s).", destroyed.len()).green()
+            );
+            println!("Log saved: {}", log_path.display());
+            Ok(())
+        }
+        Err(err) => {
+            eprintln!(
+                "{}",
+                "Destroy failed. Fix the problem and run `tforge destroy` again.".yellow()
+            );
+            eprintln!("Full output: {}", log_path.display());
+            Err(err)
+        }
+    }
+}
+
+/// Streams step output to the terminal under one spinner per running step and
+/// mirrors everything into the run log. Steps of concurrently running templates each
+/// get their own spinner; their output lines are told apart by the `[template:step]` prefix.
+struct TerminalReporter {
+    log: RunLog,
+    multi: MultiProgress,
+    running: Mutex<HashMap<String, ProgressBar>>,
+}
+
+impl TerminalReporter {
+    fn create(project_dir: &Path) -> Result<Arc<Self>> {
+        Ok(Arc::new(Self {
+            log: RunLog::create(project_dir)?,
+            multi: MultiProgress::new(),
+            running: Mutex::new(HashMap::new()),
+        }))
+    }
+}
+
+impl Reporter for TerminalReporter {
+    fn step_started(&self, step: &StepInfo) {
+        self.log.step_started(step);
+        let progress = self.multi.add(spinner(&step.to_string()));
+        if let Ok(mut running) = self.running.lock() {
+            running.insert(step.prefix(), progress);
+        }
+    }
+
+    fn output_line(&self, step: &StepInfo, stream: OutputStream, line: &str) {
+        self.log.output_line(step, stream, line);
+        self.multi.suspend(|| match stream {
+            OutputStream::Stdout => println!("{} {line}", step.prefix().dimmed()),
+            OutputStream::Stderr => eprintln!("{} {line}", step.prefix().dimmed()),
+        });
+    }
+
+    fn step_finished(&self, step: &StepInfo, outcome: &StepOutcome, elapsed: Duration) {
+        self.log.step_finished(step, outcome, elapsed);
+        let progress = self
+            .running
+            .lock()
+            .ok()
+            .and_then(|mut running| running.remove(&step.prefix()));
+        if let Some(progress) = progress {
+            progress.finish_and_clear();
+            self.multi.remove(&progress);
+        }
+        self.multi.suspend(|| match outcome {
+            StepOutcome::Done => println!(
+                "{} {step} {}",
+                "✓".green(),
+                format!("({:.1}s)", elapsed.as_secs_f64()).dimmed()
+            ),
+            StepOutcome::Skipped(reason) => {
+                println!(
+                    "{} {step} {}",
+                    "-".dimmed(),
+                    format!("skipped: {reason}").dimmed()
+                )
+            }
+            StepOutcome::Failed => eprintln!(
+                "{} {step} {}",
+                "✗".red(),
+                format!("failed after {:.1}s", elapsed.as_secs_f64()).red()
+            ),
+        });
+    }
+}
+
 fn run_status() -> Result<()> {
     let cwd = std::env::current_dir().context("failed to determine current directory")?;
     let recipe_path = cwd.join(RECIPE_FILE);
@@ -243,7 +911,12 @@ fn run_status() -> Result<()> {
 
     let recipe = load_recipe(&recipe_path)?;
     let registry = load_registry()?;
-    let state = PipelineState::load(&state_path)
+    let known: Vec<TemplateManifest> = recipe
+        .templates
+        .iter()
+        .filter_map(|name| registry.find(name).cloned())
+        .collect();
+    let state = PipelineState::load_for(&state_path, &known)
         .with_context(|| format!("failed to load {}", state_path.display()))?;
 
     println!("{} {}", "Project:".bold(), recipe.project_name);
@@ -252,6 +925,7 @@ fn run_status() -> Result<()> {
     println!();
     println!("{}", "Template status".bold());
 
+    let mut flaky = Vec::new();
     for template_name in &recipe.templates {
         let Some(template) = registry.find(template_name) else {
             println!(
@@ -263,23 +937,45 @@ fn run_status() -> Result<()> {
 
         let mut completed = 0usize;
         let mut pending = 0usize;
-        let mut failed: Option<(usize, String)> = None;
-        for idx in 0..template.steps.len() {
-            match state.get(template_name, idx) {
-                StepState::Completed => completed += 1,
-                StepState::Pending => pending += 1,
-                StepState::Failed(msg) => failed = Some((idx + 1, msg)),
+        let mut failed: Option<(usize, &StepRecord)> = None;
+        for (idx, key) in step_keys(&template.steps).iter().enumerate() {
+            match state.step_record(template_name, key) {
+                Some(record) if record.status == StepStatus::Completed => {
+                    completed += 1;
+                    if record.attempts > 1 {
+                        flaky.push((template_name, idx + 1, record.attempts));
+                    }
+                }
+                Some(record) => failed = Some((idx + 1, record)),
+                None => pending += 1,
             }
         }
 
-        if let Some((step, msg)) = failed {
+        if let Some((step, record)) = failed {
+            let tries = if record.attempts > 1 {
+                format!(" after {} attempts", record.attempts)
+            } else {
+                String::new()
+            };
             println!(
-                "  - {}: {} at step {} ({})",
+                "  - {}: {} at step {}{} ({})",
                 template_name,
                 "failed".red(),
                 step,
-                msg
+                tries,
+                record.error.as_deref().unwrap_or_default()
             );
+            let mut details = Vec::new();
+            if let Some(finished) = record.finished_at {
+                details.push(format!("at {}", finished.format("%Y-%m-%d %H:%M:%S UTC")));
+            }
+            if let Some(code) = record.exit_code {
+                details.push(format!("exit code {code}"));
+            }
+            details.push(format!(
+                "run `tforge log {template_name} {step}` for output"
+            ));
+            println!("      {}", details.join(", ").dimmed());
         } else if pending == 0 {
             println!(
                 "  - {}: {} ({}/{})",
@@ -299,9 +995,147 @@ fn run_status() -> Result<()> {
         }
     }
 
+    if !flaky.is_empty() {
+        println!();
+        println!("{}", "Flaky steps".bold());
+        for (template_name, step, attempts) in flaky {
+            println!(
+                "  - {template_name} step {step}: succeeded on attempt {}",
+                attempts.yellow()
+            );
+        }
+    }
+
+    if !state.vars().is_empty() {
+        let mut captured: Vec<_> = state.vars().iter().collect();
+        captured.sort();
+        println!();
+        println!("{}", "Captured variables".bold());
+        for (key, value) in captured {
+            println!("  - {key} = {value}");
+        }
+    }
+
     Ok(())
 }
 
+fn run_log(template_name: &str, step: Option<&str>) -> Result<()> {
+    let cwd = std::env::current_dir().context("failed to determine current directory")?;
+    let recipe = load_recipe(&cwd.join(RECIPE_FILE))?;
+    let registry = load_registry()?;
+    if !recipe.templates.iter().any(|t| t == template_name) {
+        bail!("Template '{template_name}' is not part of this project.");
+    }
+    let templates = resolve_recipe_templates(&recipe.templates, &registry)?;
+    let template = templates
+        .iter()
+        .find(|t| t.template.name == template_name)
+        .expect("recipe templates are resolved");
+    let state_path = cwd.join(STATE_FILE);
+    let state = PipelineState::load_for(&state_path, &templates)
+        .with_context(|| format!("failed to load {}", state_path.display()))?;
+
+    let keys = step_keys(&template.steps);
+    let selected: Vec<usize> = match step {
+        None => (0..keys.len()).collect(),
+        Some(step) => {
+            let idx = match step.parse::<usize>() {
+                Ok(n) if (1..=keys.len()).contains(&n) => Some(n - 1),
+                Ok(_) => None,
+                Err(_) => keys.iter().position(|key| key == step),
+            };
+            match idx {
+                Some(idx) => vec![idx],
+                None => bail!(
+                    "Template '{template_name}' has no step '{step}'. Use a step number from 1 to {} or a step id.",
+                    keys.len()
+                ),
+            }
+        }
+    };
+
+    for (n, idx) in selected.into_iter().enumerate() {
+        if n > 0 {
+            println!();
+        }
+        let step = &template.steps[idx];
+        let header = format!(
+            "[{template_name}] step {}/{} ({})",
+            idx + 1,
+            keys.len(),
+            step.step_type
+        );
+        match state.step_record(template_name, &keys[idx]) {
+            Some(record) => print_step_record(&header, &keys[idx], record),
+            None => println!("{} {}", header.bold(), "pending".yellow()),
+        }
+    }
+    Ok(())
+}
+
+fn print_step_record(header: &str, key: &str, record: &StepRecord) {
+    let status = match (record.status, &record.skipped) {
+        (StepStatus::Failed, _) => "failed".red().to_string(),
+        (StepStatus::Completed, Some(reason)) => format!("skipped ({reason})").dimmed().to_string(),
+        (StepStatus::Completed, None) => "completed".green().to_string(),
+    };
+    println!("{} {status}", header.bold());
+
+    let field = |label: &str, value: &str| {
+        let mut lines = value.lines();
+        println!(
+            "  {:<10} {}",
+            format!("{label}:"),
+            lines.next().unwrap_or_default()
+        );
+        for line in lines {
+            println!("  {:<10} {line}", "");
+        }
+    };
+    let time = |t: &chrono::DateTime<chrono::Utc>| t.format("%Y-%m-%d %H:%M:%S UTC").to_string();
+
+    field("key", key);
+    if let Some(started) = &record.started_at {
+        field("started", &time(started));
+    }
+    if let Some(finished) = &record.finished_at {
+        let duration = record
+            .duration_ms
+            .map(|ms| format!(" ({:.1}s)", ms as f64 / 1000.0))
+            .unwrap_or_default();
+        field("finished", &format!("{}{duration}", time(finished)));
+    }
+    if record.attempts > 0 {
+        field("attempts", &record.attempts.to_string());
+    }
+    if let Some(code) = record.exit_code {
+        field("exit code", &code.to_string());
+    }
+    if let Some(version) = &record.tforge_version {
+        field("tforge", version);
+    }
+    if let Some(dir) = &record.working_dir {
+        field("dir", dir);
+    }
+    if let Some(command) = &record.command {
+        field("command", command.trim_end());
+    }
+    if let Some(error) = &record.error {
+        field("error", error.trim_end());
+    }
+    for (label, tail) in [
+        ("stdout", &record.stdout_tail),
+        ("stderr", &record.stderr_tail),
+    ] {
+        if let Some(tail) = tail {
+            println!("  {label} (last lines):");
+            for line in tail.lines() {
+                println!("    {line}");
+            }
+        }
+    }
+}
+
 fn run_config(target: &str, show: bool) -> Result<()> {
     match target {
         "llm" => {
@@ -416,14 +1250,16 @@ fn load_registry() -> Result<Registry> {
     // Dev override: if local templates/ dir exists, merge those too
     let template_dir = Path::new(TEMPLATE_ROOT);
     if template_dir.exists() {
-        if let Ok(local) = Registry::from_directory(template_dir) {
-            registry.merge(local);
+        match Registry::from_directory(template_dir) {
+            Ok(local) => registry.merge(local),
+            Err(err) => eprintln!("warning: skipping local templates: {err:#}"),
         }
     }
 
     // Merge cached remote templates
-    if let Ok(cached) = Registry::from_cache_dir() {
-        registry.merge(cached);
+    match Registry::from_cache_dir() {
+        Ok(cached) => registry.merge(cached),
+        Err(err) => eprintln!("warning: skipping cached templates: {err:#}"),
     }
 
     if registry.templates().is_empty() {
@@ -481,11 +1317,24 @@ async fn select_recipe_with_ai(
     }
 
     let mut vars = parsed.parameters;
-    vars.insert("project_name".to_string(), project_name.to_string());
+    vars.insert("project_name".to_string(), project_name.into());
 
     Ok(RecipeSelection { templates, vars })
 }
 
+/// Parameter values given on the command line: `--var-file` first, then `--set` overrides.
+fn preset_vars(set: &[String], var_file: Option<&Path>) -> Result<HashMap<String, Value>> {
+    let mut vars = match var_file {
+        Some(path) => load_var_file(path)?,
+        None => HashMap::new(),
+    };
+    for arg in set {
+        let (key, value) = parse_set_arg(arg)?;
+        vars.insert(key, value.into());
+    }
+    Ok(vars)
+}
+
 fn ensure_tools_available(templates: &[TemplateManifest]) -> Result<()> {
     let mut required_tools = BTreeSet::new();
     for template in templates {
@@ -529,20 +1378,21 @@ fn expand_required_templates(
     while idx < ordered.len() {
         let deps = ordered[idx].dependencies.requires_templates.clone();
         for dep in deps {
-            if seen.insert(dep.clone()) {
-                let dep_template = registry.find(&dep).ok_or_else(|| {
-                    anyhow::anyhow!(
-                        "template '{}' requires missing dependency template '{}'",
-                        ordered[idx].template.name,
-                        dep
-                    )
-                })?;
+            if seen.insert(dep.name.clone()) {
+                let dep_template =
+                    registry
+                        .find(&dep.name)
+                        .ok_or_else(|| ResolveError::MissingDependency {
+                            template: ordered[idx].template.name.clone(),
+                            dependency: dep.name.clone(),
+                        })?;
                 ordered.push(dep_template.clone());
             }
         }
         idx += 1;
     }
 
+    check_versions(&ordered)?;
     Ok(ordered)
 }
 
@@ -569,7 +1419,7 @@ fn resolve_recipe_templates(
 fn print_recipe_summary(
     project_name: &str,
     templates: &[TemplateManifest],
-    vars: &HashMap<String, String>,
+    vars: &HashMap<String, Value>,
 ) {
     println!();
     println!("{} {}", "Project:".bold(), project_name);
@@ -584,6 +1434,63 @@ fn print_recipe_summary(
     println!("{} {}", "Parameters:".bold(), param_count);
 }
 
+fn print_plan(plan: &[PlannedStep], json: bool) -> Result<()> {
+    if json {
+        let content = serde_json::to_string_pretty(plan).context("failed to serialize plan")?;
+        println!("{content}");
+        return Ok(());
+    }
+
+    let to_run = plan.iter().filter(|s| s.action == PlanAction::Run).count();
+    println!();
+    println!(
+        "{} {} step(s) to run, {} skipped",
+        "Execution plan:".bold(),
+        to_run,
+        plan.len() - to_run
+    );
+    for step in plan {
+        let label = format!(
+            "[{}] step {} ({})",
+            step.template, step.step, step.step_type
+        );
+        match step.action {
+            PlanAction::Run => {
+                match &step.reason {
+                    Some(reason) => println!("  {} {} — {}", "run ".green(), label, reason),
+                    None => println!("  {} {}", "run ".green(), label),
+                }
+                if let Some(dir) = &step.working_dir {
+                    println!("        in: {dir}");
+                }
+                if let Some(command) = &step.command {
+                    for line in command.trim_end().lines() {
+                        println!("        $ {line}");
+                    }
+                }
+                if let Some(source) = &step.source {
+                    println!("        source: {source}");
+                }
+                if let Some(url) = &step.url {
+                    println!("        url: {url}");
+                }
+                if let Some(check) = &step.check {
+                    println!("        unless: {check}");
+                }
+            }
+            PlanAction::Skip => {
+                println!(
+                    "  {} {} — {}",
+                    "skip".dimmed(),
+                    label,
+                    step.reason.as_deref().unwrap_or_default()
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
 fn save_recipe(recipe: &SavedRecipe, path: &Path) -> Result<()> {
     let content = toml::to_string_pretty(recipe).context("failed to serialize recipe")?;
     fs::write(path, content).with_context(|| format!("failed to write {}", path.display()))
@@ -0,0 +1,203 @@
+use anyhow::{Context, Result};
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Identifies the step being reported.
+#[derive(Debug, Clone)]
+pub struct StepInfo {
+    pub template: String,
+    /// 1-based position within the template.
+    pub step: usize,
+    /// Number of steps in the template.
+    pub total: usize,
+    pub step_type: String,
+    /// One-line description: the first command line, overlay source or clone URL.
+    pub summary: String,
+}
+
+impl StepInfo {
+    /// Prefix used for every streamed output line, e.g. `[axum-server:2]`.
+    pub fn prefix(&self) -> String {
+        format!("[{}:{}]", self.template, self.step)
+    }
+}
+
+impl fmt::Display for StepInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{}] step {}/{} ({})",
+            self.template, self.step, self.total, self.step_type
+        )?;
+        if !self.summary.is_empty() {
+            write!(f, ": {}", self.summary)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StepOutcome {
+    Done,
+    /// The step did not run; the string says why (check passed, condition false).
+    Skipped(String),
+    Failed,
+}
+
+/// Receives pipeline progress while the engine runs. Output lines may arrive from
+/// reader threads, so implementations must be thread-safe.
+pub trait Reporter: Send + Sync {
+    fn step_started(&self, step: &StepInfo);
+    fn output_line(&self, step: &StepInfo, stream: OutputStream, line: &str);
+    fn step_finished(&self, step: &StepInfo, outcome: &StepOutcome, elapsed: Duration);
+}
+
+/// A reporter bound to the step currently executing, handed to the executor.
+#[derive(Clone)]
+pub struct StepOutput {
+    pub reporter: Arc<dyn Reporter>,
+    pub step: StepInfo,
+}
+
+impl StepOutput {
+    pub fn line(&self, stream: OutputStream, line: &str) {
+        self.reporter.output_line(&self.step, stream, line);
+    }
+}
+
+/// Keeps the last lines of each output stream of one step, forwarding every event to an
+/// optional inner reporter.
+pub struct OutputTail {
+    inner: Option<Arc<dyn Reporter>>,
+    limit: usize,
+    stdout: Mutex<VecDeque<String>>,
+    stderr: Mutex<VecDeque<String>>,
+}
+
+impl OutputTail {
+    pub fn new(inner: Option<Arc<dyn Reporter>>, limit: usize) -> Self {
+        Self {
+            inner,
+            limit,
+            stdout: Mutex::new(VecDeque::new()),
+            stderr: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// The kept stdout lines joined with newlines; `None` if there was no output.
+    pub fn stdout(&self) -> Option<String> {
+        Self::joined(&self.stdout)
+    }
+
+    pub fn stderr(&self) -> Option<String> {
+        Self::joined(&self.stderr)
+    }
+
+    fn joined(lines: &Mutex<VecDeque<String>>) -> Option<String> {
+        let lines = lines.lock().unwrap_or_else(|e| e.into_inner());
+        (!lines.is_empty()).then(|| Vec::from(lines.clone()).join("\n"))
+    }
+}
+
+impl Reporter for OutputTail {
+    fn step_started(&self, step: &StepInfo) {
+        if let Some(inner) = &self.inner {
+            inner.step_started(step);
+        }
+    }
+
+    fn output_line(&self, step: &StepInfo, stream: OutputStream, line: &str) {
+        let lines = match stream {
+            OutputStream::Stdout => &self.stdout,
+            OutputStream::Stderr => &self.stderr,
+        };
+        let mut lines = lines.lock().unwrap_or_else(|e| e.into_inner());
+        if lines.len() == self.limit {
+            lines.pop_front();
+        }
+        lines.push_back(line.to_string());
+        drop(lines);
+        if let Some(inner) = &self.inner {
+            inner.output_line(step, stream, line);
+        }
+    }
+
+    fn step_finished(&self, step: &StepInfo, outcome: &StepOutcome, elapsed: Duration) {
+        if let Some(inner) = &self.inner {
+            inner.step_finished(step, outcome, elapsed);
+        }
+    }
+}
+
+/// Writes the full output of one run to `.tforge/logs/run-<unix-time>.log`.
+pub struct RunLog {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl RunLog {
+    /// Create a new log file under `<project_dir>/.tforge/logs/`.
+    pub fn create(project_dir: &Path) -> Result<Self> {
+        let dir = project_dir.join(".tforge").join("logs");
+        fs::create_dir_all(&dir).with_context(|| format!("creating {}", dir.display()))?;
+        let stamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let mut path = dir.join(format!("run-{stamp}.log"));
+        let mut n = 1;
+        while path.exists() {
+            path = dir.join(format!("run-{stamp}-{n}.log"));
+            n += 1;
+        }
+        let file = File::create(&path).with_context(|| format!("creating {}", path.display()))?;
+        Ok(Self {
+            path,
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn write_line(&self, line: &str) {
+        // Logging must never abort a run; a failed write only loses log lines.
+        if let Ok(mut file) = self.file.lock() {
+            let _ = writeln!(file, "{line}");
+        }
+    }
+}
+
+impl Reporter for RunLog {
+    fn step_started(&self, step: &StepInfo) {
+        self.write_line(&format!("==> {step}"));
+    }
+
+    fn output_line(&self, step: &StepInfo, stream: OutputStream, line: &str) {
+        match stream {
+            OutputStream::Stdout => self.write_line(&format!("{} {line}", step.prefix())),
+            OutputStream::Stderr => self.write_line(&format!("{} stderr: {line}", step.prefix())),
+        }
+    }
+
+    fn step_finished(&self, step: &StepInfo, outcome: &StepOutcome, elapsed: Duration) {
+        let status = match outcome {
+            StepOutcome::Done => format!("done in {:.1}s", elapsed.as_secs_f64()),
+            StepOutcome::Skipped(reason) => format!("skipped ({reason})"),
+            StepOutcome::Failed => format!("failed after {:.1}s", elapsed.as_secs_f64()),
+        };
+        self.write_line(&format!("<== {step}: {status}"));
+    }
+}
@@ -1,26 +1,136 @@
-use crate::condition::evaluate_condition;
+use crate::condition::{evaluate_condition, parse_condition};
 use crate::registry::Registry;
+use crate::renderer::Renderer;
 use crate::types::{ParamDef, ParamType, TemplateManifest};
-use anyhow::{Context, Result};
-use inquire::{Confirm, MultiSelect, Select, Text};
-use std::collections::HashMap;
+use crate::vars::{Value, toml_value_to_var};
+use anyhow::{Context, Result, bail};
+use inquire::list_option::ListOption;
+use inquire::validator::Validation;
+use inquire::{Confirm, CustomType, MultiSelect, Select, Text};
+use owo_colors::OwoColorize;
+use std::collections::{BTreeSet, HashMap};
 
 pub struct RecipeSelection {
     pub templates: Vec<TemplateManifest>,
-    pub vars: HashMap<String, String>,
+    pub vars: HashMap<String, Value>,
 }
 
-pub fn parameter_keys_in_prompt_order(template: &TemplateManifest) -> Vec<String> {
-    let mut keys: Vec<String> = template.parameters.keys().cloned().collect();
-    keys.sort();
-    keys
+pub fn parameter_keys_in_prompt_order(template: &TemplateManifest) -> Result<Vec<String>> {
+    Ok(parameter_prompt_order(std::slice::from_ref(template))?
+        .into_iter()
+        .map(|(_, key)| key.to_string())
+        .collect())
+}
+
+/// Order in which the parameters of `templates` are prompted for: template by template,
+/// parameters with an `order` first, the rest in ascending lexical order, with the members
+/// of a `group` kept together at the position of its first member. A parameter still
+/// comes after every parameter its `default` or `when` reads.
+pub fn parameter_prompt_order(
+    templates: &[TemplateManifest],
+) -> Result<Vec<(&TemplateManifest, &str)>> {
+    let nodes: Vec<(&TemplateManifest, &str)> = templates
+        .iter()
+        .flat_map(|tmpl| {
+            template_prompt_order(tmpl)
+                .into_iter()
+                .map(move |key| (tmpl, key))
+        })
+        .collect();
+    let deps: Vec<Vec<usize>> = nodes
+        .iter()
+        .enumerate()
+        .map(|(i, (tmpl, key))| {
+            let names = parameter_dependencies(&tmpl.parameters[*key]);
+            nodes
+                .iter()
+                .enumerate()
+                .filter(|(j, (_, other))| *j != i && names.contains(*other))
+                .map(|(j, _)| j)
+                .collect()
+        })
+        .collect();
+
+    let mut placed = vec![false; nodes.len()];
+    let mut order = Vec::with_capacity(nodes.len());
+    while order.len() < nodes.len() {
+        let ready = (0..nodes.len()).find(|&i| !placed[i] && deps[i].iter().all(|&d| placed[d]));
+        let Some(next) = ready else {
+            let remaining: Vec<String> = (0..nodes.len())
+                .filter(|&i| !placed[i])
+                .map(|i| format!("{}.{}", nodes[i].0.template.name, nodes[i].1))
+                .collect();
+            bail!(
+                "parameter defaults and `when` conditions depend on each other in a cycle: {}",
+                remaining.join(", ")
+            );
+        };
+        placed[next] = true;
+        order.push(nodes[next]);
+    }
+    Ok(order)
+}
+
+/// Declared prompt order of one template's parameters, before dependencies are applied.
+fn template_prompt_order(template: &TemplateManifest) -> Vec<&str> {
+    let mut keys: Vec<&str> = template.parameters.keys().map(String::as_str).collect();
+    keys.sort_by_key(|key| {
+        let order = template.parameters[*key].order;
+        (order.is_none(), order, *key)
+    });
+
+    let group = |key: &str| template.parameters[key].group.as_deref();
+    let mut ordered = Vec::with_capacity(keys.len());
+    let mut placed_groups = Vec::new();
+    for key in &keys {
+        match group(key) {
+            None => ordered.push(*key),
+            Some(name) if !placed_groups.contains(&name) => {
+                placed_groups.push(name);
+                ordered.extend(keys.iter().filter(|k| group(k) == Some(name)));
+            }
+            Some(_) => {}
+        }
+    }
+    ordered
+}
+
+/// Variables a parameter's `when` condition and templated `default` read.
+fn parameter_dependencies(param: &ParamDef) -> BTreeSet<String> {
+    let mut names = BTreeSet::new();
+    if let Some(when) = &param.when
+        && let Ok(condition) = parse_condition(when)
+    {
+        names.extend(condition.variables());
+    }
+    if let Some(toml::Value::String(default)) = &param.default
+        && let Ok(vars) = Renderer::new().variables(default)
+    {
+        names.extend(vars);
+    }
+    names
+}
+
+/// The parameter's default for the current variables, converted to its type. String
+/// defaults are rendered as templates first (`"{{project_name}}-dev"`).
+pub fn parameter_default(param: &ParamDef, vars: &HashMap<String, Value>) -> Result<Option<Value>> {
+    let Some(default) = &param.default else {
+        return Ok(None);
+    };
+    let value = match default {
+        toml::Value::String(template) => {
+            Value::String(Renderer::new().render_string(template, vars)?)
+        }
+        other => toml_value_to_var(other)?,
+    };
+    value.coerce(&param.param_type).map(Some)
 }
 
 pub fn should_prompt_parameter(
     template_name: &str,
     param_key: &str,
     param: &ParamDef,
-    vars: &HashMap<String, String>,
+    vars: &HashMap<String, Value>,
 ) -> Result<bool> {
     let Some(condition) = &param.when else {
         return Ok(true);
@@ -33,9 +143,14 @@ pub fn should_prompt_parameter(
     })
 }
 
-pub fn prompt_recipe(registry: &Registry, project_name: &str) -> Result<RecipeSelection> {
+/// Interactively select templates and prompt for their parameters.
+/// Values already present in `vars` (e.g. from `--set`) are not prompted.
+pub fn prompt_recipe(
+    registry: &Registry,
+    project_name: &str,
+    mut vars: HashMap<String, Value>,
+) -> Result<RecipeSelection> {
     let mut selected_templates: Vec<TemplateManifest> = Vec::new();
-    let mut vars = HashMap::new();
 
     vars.insert("project_name".into(), project_name.into());
 
@@ -77,10 +192,11 @@ pub fn prompt_recipe(registry: &Registry, project_name: &str) -> Result<RecipeSe
         .by_category("integration")
         .into_iter()
         .filter(|t| {
-            t.dependencies
-                .requires_templates
-                .iter()
-                .all(|req| selected_templates.iter().any(|s| s.template.name == *req))
+            t.dependencies.requires_templates.iter().all(|req| {
+                selected_templates
+                    .iter()
+                    .any(|s| s.template.name == req.name)
+            })
         })
         .collect();
 
@@ -107,57 +223,234 @@ pub fn prompt_recipe(registry: &Registry, project_name: &str) -> Result<RecipeSe
     }
 
     // Step 3: Collect parameters for all selected templates
-    for tmpl in &selected_templates {
-        for key in parameter_keys_in_prompt_order(tmpl) {
-            if vars.contains_key(&key) {
-                continue;
-            }
+    prompt_parameters(&selected_templates, &mut vars)?;
 
-            let param = tmpl.parameters.get(&key).expect("parameter key must exist");
-            if !should_prompt_parameter(&tmpl.template.name, &key, param, &vars)? {
-                continue;
-            }
+    Ok(RecipeSelection {
+        templates: selected_templates,
+        vars,
+    })
+}
 
-            let value = match &param.param_type {
-                ParamType::String => {
-                    let mut prompt = Text::new(&param.prompt);
-                    if let Some(toml::Value::String(d)) = &param.default {
-                        prompt = prompt.with_default(d);
-                    }
-                    prompt.prompt().context("input cancelled")?
+/// Prompt for every parameter of `templates` that is not yet set in `vars`
+/// and whose `when` condition holds. `computed` parameters are recomputed from their
+/// default instead, replacing any value already given.
+pub fn prompt_parameters(
+    templates: &[TemplateManifest],
+    vars: &mut HashMap<String, Value>,
+) -> Result<()> {
+    let mut heading: Option<(&str, &str)> = None;
+    for (tmpl, key) in parameter_prompt_order(templates)? {
+        let name = &tmpl.template.name;
+        let param = &tmpl.parameters[key];
+        if param.computed {
+            compute_parameter(name, key, param, vars)?;
+            continue;
+        }
+        if coerce_preset(name, key, param, vars)? {
+            continue;
+        }
+        if !should_prompt_parameter(name, key, param, vars)? {
+            continue;
+        }
+        if let Some(group) = param.group.as_deref()
+            && heading != Some((name, group))
+        {
+            println!("\n{}", group.bold());
+            heading = Some((name, group));
+        }
+        let help = param.help.as_deref();
+
+        let default = parameter_default(param, vars)
+            .with_context(|| format!("invalid default for template '{name}' parameter '{key}'"))?;
+        let value = match &param.param_type {
+            ParamType::String => {
+                let rules = param.clone();
+                let default = default.map(|d| d.to_string());
+                let mut prompt = Text::new(&param.prompt)
+                    .with_validator(move |input: &str| Ok(validation(&rules, Value::from(input))));
+                if let Some(d) = &default {
+                    prompt = prompt.with_default(d);
+                }
+                if let Some(help) = help {
+                    prompt = prompt.with_help_message(help);
+                }
+                Value::String(prompt.prompt().context("input cancelled")?)
+            }
+            ParamType::Select => {
+                let cursor = default
+                    .and_then(|d| param.options.iter().position(|o| d == o.as_str()))
+                    .unwrap_or(0);
+                let mut prompt =
+                    Select::new(&param.prompt, param.options.clone()).with_starting_cursor(cursor);
+                if let Some(help) = help {
+                    prompt = prompt.with_help_message(help);
                 }
-                ParamType::Select => {
-                    let selected = Select::new(&param.prompt, param.options.clone())
-                        .prompt()
-                        .context("selection cancelled")?;
-                    selected
+                Value::String(prompt.prompt().context("selection cancelled")?)
+            }
+            ParamType::MultiSelect => {
+                let rules = param.clone();
+                let selected: Vec<usize> = default
+                    .map(|d| {
+                        let items = d.items();
+                        (0..param.options.len())
+                            .filter(|&i| items.iter().any(|item| item == &param.options[i]))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                let mut prompt = MultiSelect::new(&param.prompt, param.options.clone())
+                    .with_default(&selected)
+                    .with_validator(move |selected: &[ListOption<&String>]| {
+                        let items = selected.iter().map(|o| o.value.clone()).collect();
+                        Ok(validation(&rules, Value::List(items)))
+                    });
+                if let Some(help) = help {
+                    prompt = prompt.with_help_message(help);
+                }
+                Value::List(prompt.prompt().context("selection cancelled")?)
+            }
+            ParamType::Bool => {
+                let default_val = matches!(default, Some(Value::Bool(true)));
+                let mut prompt = Confirm::new(&param.prompt).with_default(default_val);
+                if let Some(help) = help {
+                    prompt = prompt.with_help_message(help);
                 }
-                ParamType::MultiSelect => {
-                    let selected = MultiSelect::new(&param.prompt, param.options.clone())
-                        .prompt()
-                        .context("selection cancelled")?;
-                    selected.join(",")
+                Value::Bool(prompt.prompt().context("confirm cancelled")?)
+            }
+            ParamType::Integer => {
+                let rules = param.clone();
+                let mut prompt = CustomType::<i64>::new(&param.prompt)
+                    .with_error_message("Please enter a whole number")
+                    .with_validator(move |n: &i64| Ok(validation(&rules, Value::Integer(*n))));
+                if let Some(Value::Integer(d)) = default {
+                    prompt = prompt.with_default(d);
                 }
-                ParamType::Bool => {
-                    let default_val = param
-                        .default
-                        .as_ref()
-                        .and_then(|v| v.as_bool())
-                        .unwrap_or(false);
-                    let result = Confirm::new(&param.prompt)
-                        .with_default(default_val)
-                        .prompt()
-                        .context("confirm cancelled")?;
-                    result.to_string()
+                if let Some(help) = help {
+                    prompt = prompt.with_help_message(help);
                 }
-            };
+                Value::Integer(prompt.prompt().context("input cancelled")?)
+            }
+        };
 
-            vars.insert(key, value);
+        vars.insert(key.to_string(), value);
+    }
+
+    Ok(())
+}
+
+/// Fill every parameter of `templates` that is not yet set in `vars` from its
+/// manifest default, without prompting. Parameters hidden by `when` are skipped;
+/// a shown parameter without a default is an error. `computed` parameters are always
+/// recomputed.
+pub fn apply_parameter_defaults(
+    templates: &[TemplateManifest],
+    vars: &mut HashMap<String, Value>,
+) -> Result<()> {
+    for (tmpl, key) in parameter_prompt_order(templates)? {
+        let name = &tmpl.template.name;
+        let param = &tmpl.parameters[key];
+        if param.computed {
+            compute_parameter(name, key, param, vars)?;
+            continue;
+        }
+        if coerce_preset(name, key, param, vars)? {
+            continue;
+        }
+        if !should_prompt_parameter(name, key, param, vars)? {
+            continue;
         }
+        let value = required_default(name, key, param, vars)?;
+        vars.insert(key.to_string(), value);
     }
 
-    Ok(RecipeSelection {
-        templates: selected_templates,
-        vars,
-    })
+    Ok(())
+}
+
+/// Recompute every `computed` parameter of `templates` from the other variables.
+/// Computed parameters are left out of saved recipes, so this runs whenever one is loaded.
+pub fn compute_parameters(
+    templates: &[TemplateManifest],
+    vars: &mut HashMap<String, Value>,
+) -> Result<()> {
+    for (tmpl, key) in parameter_prompt_order(templates)? {
+        let param = &tmpl.parameters[key];
+        if param.computed {
+            compute_parameter(&tmpl.template.name, key, param, vars)?;
+        }
+    }
+    Ok(())
+}
+
+/// Set a computed parameter from its default, dropping any value given for it. A
+/// parameter hidden by `when` is left unset.
+fn compute_parameter(
+    template_name: &str,
+    key: &str,
+    param: &ParamDef,
+    vars: &mut HashMap<String, Value>,
+) -> Result<()> {
+    vars.remove(key);
+    if should_prompt_parameter(template_name, key, param, vars)? {
+        let value = required_default(template_name, key, param, vars)?;
+        vars.insert(key.to_string(), value);
+    }
+    Ok(())
+}
+
+/// The parameter's default for the current variables, which has to exist and pass the
+/// parameter's validation rules.
+fn required_default(
+    template_name: &str,
+    key: &str,
+    param: &ParamDef,
+    vars: &HashMap<String, Value>,
+) -> Result<Value> {
+    let context = || format!("invalid default for template '{template_name}' parameter '{key}'");
+    let Some(value) = parameter_default(param, vars).with_context(context)? else {
+        if param.computed {
+            bail!("computed parameter '{key}' of template '{template_name}' has no default");
+        }
+        bail!(
+            "template '{template_name}' requires parameter '{key}' ({}). Pass it with --set {key}=<value> or --var-file.",
+            param.prompt
+        );
+    };
+    param
+        .validate(&value)
+        .map_err(anyhow::Error::msg)
+        .with_context(context)?;
+    Ok(value)
+}
+
+/// Convert a value given for `key` before prompting (`--set`, a var file, the LLM, a
+/// saved recipe) to the parameter's type and check its validation rules. Returns whether
+/// a value was present.
+fn coerce_preset(
+    template_name: &str,
+    key: &str,
+    param: &ParamDef,
+    vars: &mut HashMap<String, Value>,
+) -> Result<bool> {
+    let Some(value) = vars.remove(key) else {
+        return Ok(false);
+    };
+    let value = checked(param, value).with_context(|| {
+        format!("invalid value for template '{template_name}' parameter '{key}'")
+    })?;
+    vars.insert(key.to_string(), value);
+    Ok(true)
+}
+
+/// `value` converted to the parameter's type, if it passes the validation rules.
+fn checked(param: &ParamDef, value: Value) -> Result<Value> {
+    let value = value.coerce(&param.param_type)?;
+    param.validate(&value).map_err(anyhow::Error::msg)?;
+    Ok(value)
+}
+
+/// Result of an inquire validator enforcing the parameter's validation rules.
+fn validation(param: &ParamDef, value: Value) -> Validation {
+    match param.validate(&value) {
+        Ok(()) => Validation::Valid,
+        Err(message) => Validation::Invalid(message.into()),
+    }
 }
@@ -1,6 +1,7 @@
+use crate::source::TemplateSource;
 use crate::types::TemplateManifest;
-use anyhow::{Context, Result};
-use std::path::Path;
+use anyhow::{Context, Result, bail};
+use std::path::{Path, PathBuf};
 
 pub struct Registry {
     templates: Vec<TemplateManifest>,
@@ -34,32 +35,43 @@ impl Registry {
 
     /// Load templates from the cache directory (~/.config/tforge/templates/).
     pub fn from_cache_dir() -> Result<Self> {
-        let cache = crate::remote::cache_dir();
-        if cache.exists() {
-            Self::from_directory(&cache)
-        } else {
-            Ok(Self {
-                templates: Vec::new(),
-            })
-        }
+        Self::from_cache_dir_in(&crate::remote::cache_dir())
+    }
+
+    /// Load templates from the template cache at `cache`.
+    pub fn from_cache_dir_in(cache: &Path) -> Result<Self> {
+        Self::load_directory(cache, TemplateSource::Cached, crate::remote::template_root)
     }
 
+    /// Load templates from a local template directory (one sub-directory per template).
     pub fn from_directory(path: &Path) -> Result<Self> {
+        Self::load_directory(path, TemplateSource::Local, |dir| Ok(dir.to_path_buf()))
+    }
+
+    /// Load every `<dir>/template.toml` under `path`; `root` maps an entry to the directory
+    /// holding its manifest (a cached checkout pinned to a `--path`). An entry that cannot
+    /// be loaded is skipped with a warning, so one broken template does not hide the rest.
+    fn load_directory(
+        path: &Path,
+        source: fn(PathBuf) -> TemplateSource,
+        root: fn(&Path) -> Result<PathBuf>,
+    ) -> Result<Self> {
         let mut templates = Vec::new();
 
         if !path.exists() {
             return Ok(Self { templates });
         }
 
-        for entry in std::fs::read_dir(path)? {
-            let entry = entry?;
-            let template_toml = entry.path().join("template.toml");
-            if template_toml.exists() {
-                let content = std::fs::read_to_string(&template_toml)
-                    .with_context(|| format!("reading {}", template_toml.display()))?;
-                let manifest: TemplateManifest = toml::from_str(&content)
-                    .with_context(|| format!("parsing {}", template_toml.display()))?;
-                templates.push(manifest);
+        let entries =
+            std::fs::read_dir(path).with_context(|| format!("reading {}", path.display()))?;
+        for entry in entries {
+            let entry = entry
+                .with_context(|| format!("reading {}", path.display()))?
+                .path();
+            match Self::load_entry(&entry, source, root) {
+                Ok(Some(manifest)) => templates.push(manifest),
+                Ok(None) => {}
+                Err(err) => eprintln!("warning: skipping {}: {err:#}", entry.display()),
             }
         }
 
@@ -67,6 +79,31 @@ impl Registry {
         Ok(Self { templates })
     }
 
+    /// The manifest of one directory entry, or `None` when it holds no `template.toml`.
+    fn load_entry(
+        entry: &Path,
+        source: fn(PathBuf) -> TemplateSource,
+        root: fn(&Path) -> Result<PathBuf>,
+    ) -> Result<Option<TemplateManifest>> {
+        let dir = root(entry)?;
+        let template_toml = dir.join("template.toml");
+        if !template_toml.exists() {
+            if dir != entry {
+                bail!(
+                    "pinned template path {} has no template.toml",
+                    dir.display()
+                );
+            }
+            return Ok(None);
+        }
+        let content = std::fs::read_to_string(&template_toml)
+            .with_context(|| format!("reading {}", template_toml.display()))?;
+        let mut manifest: TemplateManifest = toml::from_str(&content)
+            .with_context(|| format!("parsing {}", template_toml.display()))?;
+        manifest.source = Some(source(dir));
+        Ok(Some(manifest))
+    }
+
     pub fn templates(&self) -> &[TemplateManifest] {
         &self.templates
     }
@@ -75,6 +112,27 @@ impl Registry {
         self.templates.iter().find(|t| t.template.name == name)
     }
 
+    /// Source root of a template's companion files (embedded prefix, local or cache directory).
+    pub fn source(&self, name: &str) -> Option<&TemplateSource> {
+        self.find(name).and_then(|t| t.source.as_ref())
+    }
+
+    /// Read a companion file shipped next to a template's manifest.
+    pub fn read_file(&self, name: &str, path: &str) -> Result<Vec<u8>> {
+        let source = self
+            .source(name)
+            .ok_or_else(|| anyhow::anyhow!("template '{name}' has no file source"))?;
+        source.read_file(path)
+    }
+
+    /// List companion files under `dir` of a template, relative to `dir`.
+    pub fn list_files(&self, name: &str, dir: &str) -> Result<Vec<String>> {
+        let source = self
+            .source(name)
+            .ok_or_else(|| anyhow::anyhow!("template '{name}' has no file source"))?;
+        source.list_files(dir)
+    }
+
     pub fn by_category(&self, category: &str) -> Vec<&TemplateManifest> {
         self.templates
             .iter()
@@ -1,8 +1,10 @@
 use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
 
 use crate::config::TforgeConfig;
 use crate::registry::Registry;
+use crate::source::TemplateSource;
 use crate::types::TemplateManifest;
 
 /// Get the template cache directory (~/.config/tforge/templates/).
@@ -26,14 +28,87 @@ fn repo_name_from_url(url: &str) -> Result<String> {
     Ok(name.to_string())
 }
 
+/// Pin metadata written next to a cached checkout by `tforge add`.
+pub const PIN_FILE: &str = ".tforge-pin.toml";
+
+/// What a cached template was added from, stored in [`PIN_FILE`] at the checkout root.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TemplatePin {
+    pub url: String,
+    /// Tag, branch or commit given with `--ref`; the default branch when absent.
+    #[serde(rename = "ref", default, skip_serializing_if = "Option::is_none")]
+    pub git_ref: Option<String>,
+    /// Directory of the template inside the repository (`--path`); the root when absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    pub ref_kind: RefKind,
+    /// Checksum of the downloaded archive, for templates installed from a tarball.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RefKind {
+    DefaultBranch,
+    Branch,
+    Tag,
+    Commit,
+    /// Installed from a tarball listed in a registry index.
+    Tarball,
+}
+
+impl TemplatePin {
+    /// Branches (including the default branch) move on `tforge update`; tags, commits and
+    /// tarballs stay where they were added.
+    pub fn is_floating(&self) -> bool {
+        matches!(self.ref_kind, RefKind::DefaultBranch | RefKind::Branch)
+    }
+}
+
+/// Result of [`update_templates`].
+#[derive(Debug, Default)]
+pub struct UpdateReport {
+    pub updated: Vec<String>,
+    /// Templates pinned to a tag or commit, with the ref they stay on.
+    pub pinned: Vec<(String, String)>,
+}
+
 /// Add a template from a git URL — clone to cache directory.
-pub fn add_template(git_url: &str) -> Result<String> {
-    let name = repo_name_from_url(git_url)?;
-    let cache = cache_dir();
-    std::fs::create_dir_all(&cache)
+pub fn add_template(git_url: &str, git_ref: Option<&str>, path: Option<&str>) -> Result<String> {
+    add_template_in(&cache_dir(), git_url, git_ref, path)
+}
+
+/// Clone `git_url` into `cache`, checked out at `git_ref`, with the template in the
+/// repository directory `path`. The cache entry is named after `path`'s last component,
+/// or after the repository.
+pub fn add_template_in(
+    cache: &Path,
+    git_url: &str,
+    git_ref: Option<&str>,
+    path: Option<&str>,
+) -> Result<String> {
+    let name = match path.map(normalize_repo_path).transpose()? {
+        Some(path) => path.rsplit('/').next().unwrap_or(&path).to_string(),
+        None => repo_name_from_url(git_url)?,
+    };
+    add_named_template_in(cache, &name, git_url, git_ref, path)?;
+    Ok(name)
+}
+
+/// Clone `git_url` into the cache entry `name`; see [`add_template_in`].
+pub(crate) fn add_named_template_in(
+    cache: &Path,
+    name: &str,
+    git_url: &str,
+    git_ref: Option<&str>,
+    path: Option<&str>,
+) -> Result<()> {
+    let path = path.map(normalize_repo_path).transpose()?;
+    std::fs::create_dir_all(cache)
         .with_context(|| format!("creating cache directory {}", cache.display()))?;
 
-    let dest = cache.join(&name);
+    let dest = cache.join(name);
     if dest.exists() {
         bail!(
             "template '{}' already exists in cache. Remove it first or run `tforge update`.",
@@ -41,39 +116,156 @@ pub fn add_template(git_url: &str) -> Result<String> {
         );
     }
 
-    let output = std::process::Command::new("git")
-        .args(["clone", "--depth", "1", git_url])
-        .arg(&dest)
-        .output()
-        .context("failed to run git clone — is git installed?")?;
+    let ref_kind = match clone(git_url, git_ref, &dest) {
+        Ok(kind) => kind,
+        Err(err) => {
+            let _ = std::fs::remove_dir_all(&dest);
+            return Err(err);
+        }
+    };
+
+    // Verify template.toml exists in cloned repo
+    let root = match &path {
+        Some(path) => dest.join(path),
+        None => dest.clone(),
+    };
+    if !root.join("template.toml").exists() {
+        let _ = std::fs::remove_dir_all(&dest);
+        match &path {
+            Some(path) => bail!(
+                "'{path}' in the cloned repository does not contain a template.toml — not a valid tforge template"
+            ),
+            None => bail!(
+                "cloned repository does not contain a template.toml — not a valid tforge template"
+            ),
+        }
+    }
+
+    let pin = TemplatePin {
+        url: git_url.to_string(),
+        git_ref: git_ref.map(str::to_string),
+        path,
+        ref_kind,
+        sha256: None,
+    };
+    write_pin(&dest, &pin)?;
+    // Keep the pin file out of `git status` in the checkout.
+    let exclude = dest.join(".git/info/exclude");
+    if let Some(dir) = exclude.parent() {
+        std::fs::create_dir_all(dir)?;
+        let mut excluded = std::fs::read_to_string(&exclude).unwrap_or_default();
+        excluded.push_str(&format!("{PIN_FILE}\n"));
+        std::fs::write(&exclude, excluded)?;
+    }
+
+    Ok(())
+}
+
+/// Write the pin metadata of the cache entry at `dest`.
+pub(crate) fn write_pin(dest: &Path, pin: &TemplatePin) -> Result<()> {
+    let content = toml::to_string_pretty(pin).context("failed to serialize template pin")?;
+    std::fs::write(dest.join(PIN_FILE), content)
+        .with_context(|| format!("writing {}", dest.join(PIN_FILE).display()))
+}
+
+/// Clone `git_url` into `dest` at `git_ref` and report what kind of ref it was.
+fn clone(git_url: &str, git_ref: Option<&str>, dest: &Path) -> Result<RefKind> {
+    let Some(git_ref) = git_ref else {
+        git(None, &["clone", "--depth", "1", git_url], Some(dest))?;
+        return Ok(RefKind::DefaultBranch);
+    };
 
+    // Branches and tags can be cloned directly; anything else is tried as a commit.
+    if git(
+        None,
+        &["clone", "--depth", "1", "--branch", git_ref, git_url],
+        Some(dest),
+    )
+    .is_ok()
+    {
+        let on_branch = git(Some(dest), &["symbolic-ref", "-q", "HEAD"], None).is_ok();
+        return Ok(if on_branch {
+            RefKind::Branch
+        } else {
+            RefKind::Tag
+        });
+    }
+    let _ = std::fs::remove_dir_all(dest);
+    git(None, &["clone", git_url], Some(dest))?;
+    git(Some(dest), &["checkout", "-q", "--detach", git_ref], None)
+        .with_context(|| format!("ref '{git_ref}' not found in {git_url}"))?;
+    Ok(RefKind::Commit)
+}
+
+/// Run git, in `dir` when given and with `dest` appended as the last argument.
+fn git(dir: Option<&Path>, args: &[&str], dest: Option<&Path>) -> Result<String> {
+    let mut cmd = std::process::Command::new("git");
+    cmd.args(args);
+    if let Some(dest) = dest {
+        cmd.arg(dest);
+    }
+    if let Some(dir) = dir {
+        cmd.current_dir(dir);
+    }
+    let output = cmd
+        .output()
+        .context("failed to run git — is git installed?")?;
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
-        bail!("git clone failed: {}", stderr.trim());
+        bail!("git {} failed: {}", args[0], stderr.trim());
     }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
 
-    // Verify template.toml exists in cloned repo
-    if !dest.join("template.toml").exists() {
-        let _ = std::fs::remove_dir_all(&dest);
-        bail!("cloned repository does not contain a template.toml — not a valid tforge template");
+/// Reject absolute paths and `..` components in a `--path` argument.
+pub(crate) fn normalize_repo_path(path: &str) -> Result<String> {
+    let trimmed = path.trim_start_matches("./").trim_matches('/');
+    if trimmed.is_empty() || path.starts_with('/') || trimmed.split('/').any(|c| c == "..") {
+        bail!("invalid template path '{path}': must be a directory inside the repository");
     }
+    Ok(trimmed.to_string())
+}
 
-    Ok(name)
+/// Pin metadata of a cached checkout; `None` for templates cached before pins existed.
+pub fn load_pin(checkout: &Path) -> Result<Option<TemplatePin>> {
+    let path = checkout.join(PIN_FILE);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content =
+        std::fs::read_to_string(&path).with_context(|| format!("reading {}", path.display()))?;
+    let pin = toml::from_str(&content).with_context(|| format!("parsing {}", path.display()))?;
+    Ok(Some(pin))
+}
+
+/// Directory holding `template.toml` for a cached checkout (the pinned `--path`, or the
+/// checkout itself).
+pub fn template_root(checkout: &Path) -> Result<PathBuf> {
+    Ok(match load_pin(checkout)?.and_then(|pin| pin.path) {
+        Some(path) => checkout.join(path),
+        None => checkout.to_path_buf(),
+    })
 }
 
 /// Update all cached templates by running `git pull` in each.
-pub fn update_templates() -> Result<Vec<String>> {
-    let cache = cache_dir();
+pub fn update_templates() -> Result<UpdateReport> {
+    update_templates_in(&cache_dir())
+}
+
+/// Pull every cached template in `cache` that follows a branch; templates pinned to a
+/// tag or commit are left where they are.
+pub fn update_templates_in(cache: &Path) -> Result<UpdateReport> {
+    let mut report = UpdateReport::default();
     if !cache.exists() {
-        return Ok(Vec::new());
+        return Ok(report);
     }
 
-    let mut updated = Vec::new();
-    for entry in std::fs::read_dir(&cache)
+    let mut entries = std::fs::read_dir(cache)
         .with_context(|| format!("reading cache directory {}", cache.display()))?
-    {
-        let entry = entry?;
-        let path = entry.path();
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort();
+    for path in entries {
         if !path.is_dir() || !path.join(".git").exists() {
             continue;
         }
@@ -83,21 +275,45 @@ pub fn update_templates() -> Result<Vec<String>> {
             .map(|n| n.to_string_lossy().to_string())
             .unwrap_or_default();
 
-        let output = std::process::Command::new("git")
-            .args(["pull", "--ff-only"])
-            .current_dir(&path)
-            .output()
-            .with_context(|| format!("running git pull in {}", path.display()))?;
+        if let Some(pin) = load_pin(&path)?
+            && !pin.is_floating()
+        {
+            report
+                .pinned
+                .push((name, pin.git_ref.clone().unwrap_or_default()));
+            continue;
+        }
 
-        if output.status.success() {
-            updated.push(name);
-        } else {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            eprintln!("warning: git pull failed for '{}': {}", name, stderr.trim());
+        match git(Some(&path), &["pull", "--ff-only"], None) {
+            Ok(_) => report.updated.push(name),
+            Err(err) => eprintln!("warning: git pull failed for '{name}': {err:#}"),
         }
     }
 
-    Ok(updated)
+    Ok(report)
+}
+
+/// Commit checked out in a cached template's git repository, if it is one.
+/// `path` may be a template directory inside the checkout (`--path`).
+pub fn head_commit(path: &Path) -> Option<String> {
+    head_commit_in(&cache_dir(), path)
+}
+
+/// Like [`head_commit`] for a template in `cache`. Only the cache entry `<cache>/<name>`
+/// holding `path` is looked at (`path` itself when it is outside `cache`), never a
+/// repository that happens to enclose the cache. Tarball installs have no commit.
+pub fn head_commit_in(cache: &Path, path: &Path) -> Option<String> {
+    let checkout = path
+        .ancestors()
+        .find(|dir| dir.parent() == Some(cache))
+        .unwrap_or(path);
+    let pin = load_pin(checkout).ok().flatten();
+    if pin.is_some_and(|pin| pin.ref_kind == RefKind::Tarball) || !checkout.join(".git").exists() {
+        return None;
+    }
+    git(Some(checkout), &["rev-parse", "HEAD"], None)
+        .ok()
+        .filter(|commit| !commit.is_empty())
 }
 
 /// List cached remote templates from a specific directory.
@@ -110,13 +326,16 @@ pub fn list_cached_templates_in(cache: &Path) -> Result<Vec<TemplateManifest>> {
     for entry in std::fs::read_dir(cache)
         .with_context(|| format!("reading directory {}", cache.display()))?
     {
-        let entry = entry?;
-        let template_toml = entry.path().join("template.toml");
+        let root = template_root(&entry?.path())?;
+        let template_toml = root.join("template.toml");
         if template_toml.exists() {
             let content = std::fs::read_to_string(&template_toml)
                 .with_context(|| format!("reading {}", template_toml.display()))?;
             match toml::from_str::<TemplateManifest>(&content) {
-                Ok(manifest) => templates.push(manifest),
+                Ok(mut manifest) => {
+                    manifest.source = Some(TemplateSource::Cached(root));
+                    templates.push(manifest);
+                }
                 Err(e) => {
                     eprintln!("warning: skipping {}: {}", template_toml.display(), e);
                 }
@@ -1,19 +1,60 @@
-use anyhow::{Context, Result};
-use minijinja::{Environment, UndefinedBehavior};
-use std::collections::HashMap;
+use anyhow::{Context, Result, bail};
+use minijinja::{AutoEscape, Environment, ErrorKind, UndefinedBehavior, value::ValueKind};
+use regex::Regex;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::sync::LazyLock;
+
+use crate::source::TemplateSource;
+use crate::vars::Value;
+
+/// Auto-escape mode of [`Renderer::render_shell`]: interpolated values are shell-quoted.
+const SHELL_ESCAPE: AutoEscape = AutoEscape::Custom("shell");
 
 pub struct Renderer {
     env: Environment<'static>,
+    /// Same templates and loader as `env`, with values shell-quoted on output.
+    shell_env: Environment<'static>,
+}
+
+impl Default for Renderer {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Renderer {
     pub fn new() -> Self {
-        let mut env = Environment::new();
-        env.set_undefined_behavior(UndefinedBehavior::Strict);
-        Self { env }
+        Self {
+            env: environment(AutoEscape::None),
+            shell_env: environment(SHELL_ESCAPE),
+        }
+    }
+
+    /// Create a renderer that resolves `{% include %}`/`{% import %}` names
+    /// (e.g. `partials/header.txt`) against a template's companion files.
+    pub fn with_source(source: TemplateSource) -> Self {
+        let mut renderer = Self::new();
+        for (env, shell) in [(&mut renderer.env, false), (&mut renderer.shell_env, true)] {
+            let source = source.clone();
+            env.set_loader(move |name| {
+                if !source.exists(name) {
+                    return Ok(None);
+                }
+                let content = source.read_to_string(name).map_err(|err| {
+                    minijinja::Error::new(minijinja::ErrorKind::InvalidOperation, err.to_string())
+                })?;
+                Ok(Some(if shell {
+                    quote_in_context(&content).into_owned()
+                } else {
+                    content
+                }))
+            });
+        }
+        renderer
     }
 
-    pub fn render_string(&self, template: &str, vars: &HashMap<String, String>) -> Result<String> {
+    pub fn render_string(&self, template: &str, vars: &HashMap<String, Value>) -> Result<String> {
         let tmpl = self
             .env
             .template_from_str(template)
@@ -21,4 +62,329 @@ impl Renderer {
         let result = tmpl.render(vars).context("failed to render template")?;
         Ok(result)
     }
+
+    /// Render a shell command: every interpolated value is shell-quoted (see
+    /// [`shell_quote`]), including inside included partials, unless it goes through the
+    /// `raw` filter (`{{ flags|raw }}`). A value already inside `"..."`, `'...'` or a
+    /// heredoc is escaped for that context instead (see [`quote_in_context`]).
+    pub fn render_shell(&self, template: &str, vars: &HashMap<String, Value>) -> Result<String> {
+        let template = quote_in_context(template);
+        let tmpl = self
+            .shell_env
+            .template_from_str(&template)
+            .context("failed to parse template string")?;
+        let result = tmpl.render(vars).context("failed to render template")?;
+        Ok(result)
+    }
+
+    /// Names of the variables `template` reads, including those read by the partials it
+    /// includes or imports. Fails when a partial cannot be loaded or is named by an
+    /// expression instead of a string literal, as its variables are then unknown.
+    pub fn variables(&self, template: &str) -> Result<HashSet<String>> {
+        let tmpl = self
+            .env
+            .template_from_str(template)
+            .context("failed to parse template string")?;
+        let mut names = tmpl.undeclared_variables(false);
+        let mut pending = partial_names(template)?;
+        let mut seen = HashSet::new();
+        while let Some((name, ignore_missing)) = pending.pop() {
+            if !seen.insert(name.clone()) {
+                continue;
+            }
+            let partial = match self.env.get_template(&name) {
+                Ok(partial) => partial,
+                Err(err) if ignore_missing && err.kind() == ErrorKind::TemplateNotFound => continue,
+                Err(err) => {
+                    return Err(err).with_context(|| format!("failed to load partial '{name}'"));
+                }
+            };
+            names.extend(partial.undeclared_variables(false));
+            pending.extend(partial_names(partial.source())?);
+        }
+        Ok(names)
+    }
+}
+
+/// Partials named by the `include`, `import`, `from` and `extends` tags of `template`, with
+/// whether the tag tolerates a missing partial (`ignore missing`).
+fn partial_names(template: &str) -> Result<Vec<(String, bool)>> {
+    static TAG: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r"\{%[-+]?\s*(include|import|from|extends)\s+(.*?)\s*[-+]?%\}")
+            .expect("valid tag regex")
+    });
+    static STRING: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r#"^(?:"([^"]*)"|'([^']*)')"#).expect("valid string regex"));
+
+    let mut names = Vec::new();
+    for tag in TAG.captures_iter(template) {
+        let mut rest = tag[2].trim();
+        let ignore_missing = rest.ends_with("ignore missing");
+        // `include ["a.sh", "b.sh"]` tries each name in turn.
+        let list = rest.starts_with('[');
+        if list {
+            rest = rest[1..].trim_start();
+        }
+        loop {
+            let Some(literal) = STRING.captures(rest) else {
+                bail!("partial named by an expression in '{}'", &tag[0]);
+            };
+            let name = literal
+                .get(1)
+                .or(literal.get(2))
+                .expect("one branch matches");
+            names.push((name.as_str().to_string(), ignore_missing || list));
+            rest = rest[literal[0].len()..].trim_start();
+            match rest.strip_prefix(',') {
+                Some(more) if list => rest = more.trim_start(),
+                _ => break,
+            }
+            if rest.starts_with(']') {
+                break;
+            }
+        }
+    }
+    Ok(names)
+}
+
+fn environment(auto_escape: AutoEscape) -> Environment<'static> {
+    let mut env = Environment::new();
+    env.set_undefined_behavior(UndefinedBehavior::Strict);
+    env.set_keep_trailing_newline(true);
+    env.set_auto_escape_callback(move |_| auto_escape);
+    env.set_formatter(|out, state, value| {
+        if value.is_safe() {
+            minijinja::escape_formatter(out, state, value)
+        } else if state.auto_escape() == SHELL_ESCAPE {
+            out.write_str(&shell_quote(&display(value)))?;
+            Ok(())
+        } else {
+            out.write_str(&display(value))?;
+            Ok(())
+        }
+    });
+    // Marks a value as intentionally unquoted in shell commands; a no-op elsewhere.
+    env.add_filter("raw", |value: minijinja::Value| {
+        minijinja::Value::from_safe_string(display(&value))
+    });
+    // Inserted by `quote_in_context` for values the template already quotes.
+    for quoting in [Quoting::Single, Quoting::Double, Quoting::Heredoc] {
+        env.add_filter(quoting.filter(), move |value: minijinja::Value| {
+            if value.is_safe() {
+                return value;
+            }
+            minijinja::Value::from_safe_string(quoting.escape(&display(&value)))
+        });
+    }
+    env
+}
+
+/// Shell quoting in effect at a point of a command template.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Quoting {
+    None,
+    Single,
+    Double,
+    /// Body of a heredoc with an unquoted delimiter (`<<EOF`): `$`, `` ` `` and `\` expand.
+    Heredoc,
+    /// Body of a heredoc with a quoted delimiter (`<<'EOF'`): nothing expands.
+    Literal,
+}
+
+impl Quoting {
+    /// Filter that escapes a value for this context.
+    fn filter(self) -> &'static str {
+        match self {
+            Quoting::None => "",
+            Quoting::Single => "__shell_single_quoted",
+            Quoting::Double => "__shell_double_quoted",
+            Quoting::Heredoc => "__shell_heredoc",
+            Quoting::Literal => "raw",
+        }
+    }
+
+    fn escape(self, value: &str) -> String {
+        let special: &[char] = match self {
+            Quoting::Single => return value.replace('\'', "'\\''"),
+            Quoting::Double => &['"', '\\', '$', '`'],
+            Quoting::Heredoc => &['\\', '$', '`'],
+            Quoting::None | Quoting::Literal => return value.to_string(),
+        };
+        let mut escaped = String::with_capacity(value.len());
+        for c in value.chars() {
+            if special.contains(&c) {
+                escaped.push('\\');
+            }
+            escaped.push(c);
+        }
+        escaped
+    }
+}
+
+/// Rewrite the `{{ ... }}` expressions of a shell template that sit inside quotes the
+/// template already has, so their values are escaped for those quotes rather than quoted
+/// again: `"{{x}}"` stays one double-quoted word and a quoted heredoc body gets values
+/// verbatim. Expressions outside quotes are left to the shell-quoting formatter.
+fn quote_in_context(template: &str) -> Cow<'_, str> {
+    if !template.contains("{{") {
+        return Cow::Borrowed(template);
+    }
+    let mut out = String::with_capacity(template.len());
+    let mut quoting = Quoting::None;
+    // Heredocs opened on the current line, as (delimiter, quoted); their bodies follow it.
+    let mut pending_heredocs: Vec<(String, bool)> = Vec::new();
+    let mut heredoc: Option<String> = None;
+    let mut line_start = 0;
+    let mut i = 0;
+
+    while i < template.len() {
+        let rest = &template[i..];
+        // Template tags and comments do not affect shell quoting.
+        if rest.starts_with("{%") || rest.starts_with("{#") {
+            let close = if rest.starts_with("{%") { "%}" } else { "#}" };
+            let end = rest.find(close).map_or(rest.len(), |e| e + close.len());
+            out.push_str(&rest[..end]);
+            i += end;
+            continue;
+        }
+        if rest.starts_with("{{") {
+            let end = rest.find("}}").map_or(rest.len(), |e| e + 2);
+            let expr = &rest[..end];
+            let filter = (quoting != Quoting::None).then(|| quoting.filter());
+            match (
+                filter,
+                expr.strip_prefix("{{").and_then(|e| e.strip_suffix("}}")),
+            ) {
+                (Some(filter), Some(inner)) => {
+                    let open = if inner.starts_with(['-', '+']) {
+                        &inner[..1]
+                    } else {
+                        ""
+                    };
+                    let close = if inner.ends_with(['-', '+']) {
+                        &inner[inner.len() - 1..]
+                    } else {
+                        ""
+                    };
+                    let body = &inner[open.len()..inner.len() - close.len()];
+                    out.push_str(&format!("{{{{{open} ({body})|{filter} {close}}}}}"));
+                }
+                _ => out.push_str(expr),
+            }
+            i += end;
+            continue;
+        }
+
+        let c = rest.chars().next().expect("non-empty rest");
+        out.push(c);
+        i += c.len_utf8();
+        if let Some(delimiter) = &heredoc {
+            if c == '\n' {
+                if template[line_start..i - 1].trim_start_matches('\t') == delimiter {
+                    heredoc = None;
+                    quoting = Quoting::None;
+                    start_heredoc(&mut pending_heredocs, &mut heredoc, &mut quoting);
+                }
+                line_start = i;
+            }
+            continue;
+        }
+        match (quoting, c) {
+            (Quoting::None, '\'') => quoting = Quoting::Single,
+            (Quoting::Single, '\'') => quoting = Quoting::None,
+            (Quoting::None, '"') => quoting = Quoting::Double,
+            (Quoting::Double, '"') => quoting = Quoting::None,
+            (Quoting::None | Quoting::Double, '\\') => {
+                if let Some(next) = template[i..].chars().next().filter(|&n| n != '{') {
+                    out.push(next);
+                    i += next.len_utf8();
+                }
+            }
+            (Quoting::None, '#')
+                if template[..i - 1]
+                    .chars()
+                    .next_back()
+                    .is_none_or(|p| p.is_whitespace() || ";|&(".contains(p)) =>
+            {
+                let end = template[i..].find('\n').map_or(template.len(), |e| i + e);
+                out.push_str(&template[i..end]);
+                i = end;
+            }
+            (Quoting::None, '<')
+                if template[i..].starts_with('<')
+                    && !template[i..].starts_with("<<")
+                    && !template[..i - 1].ends_with('<') =>
+            {
+                out.push('<');
+                i += 1;
+                let spec = template[i..]
+                    .trim_start_matches('-')
+                    .trim_start_matches([' ', '\t']);
+                let word: String = spec
+                    .chars()
+                    .take_while(|c| !c.is_whitespace() && !";|&<>()".contains(*c))
+                    .collect();
+                let delimiter = word.replace(['\'', '"', '\\'], "");
+                if !delimiter.is_empty() {
+                    pending_heredocs.push((delimiter, word.contains(['\'', '"', '\\'])));
+                }
+            }
+            (Quoting::None, '\n') => {
+                line_start = i;
+                start_heredoc(&mut pending_heredocs, &mut heredoc, &mut quoting);
+            }
+            _ => {}
+        }
+    }
+    Cow::Owned(out)
+}
+
+/// Enter the body of the next heredoc opened on the line just ended, if any.
+fn start_heredoc(
+    pending: &mut Vec<(String, bool)>,
+    heredoc: &mut Option<String>,
+    quoting: &mut Quoting,
+) {
+    if !pending.is_empty() {
+        let (delimiter, quoted) = pending.remove(0);
+        *quoting = if quoted {
+            Quoting::Literal
+        } else {
+            Quoting::Heredoc
+        };
+        *heredoc = Some(delimiter);
+    }
+}
+
+/// String form of a rendered value; lists print comma-joined like [`Value`]'s `Display`.
+fn display(value: &minijinja::Value) -> String {
+    match value.try_iter() {
+        Ok(items) if value.kind() == ValueKind::Seq => items
+            .map(|item| display(&item))
+            .collect::<Vec<_>>()
+            .join(","),
+        _ => value.to_string(),
+    }
+}
+
+/// Quote `value` as a single shell word. Values made only of characters the shell treats
+/// literally are returned as-is, so common names and IDs stay readable.
+pub fn shell_quote(value: &str) -> Cow<'_, str> {
+    let plain = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_-./=:,+@%".contains(c));
+    if plain {
+        Cow::Borrowed(value)
+    } else {
+        Cow::Owned(format!("'{}'", value.replace('\'', "'\\''")))
+    }
+}
+
+/// Join arguments into a shell command line that reproduces them exactly.
+pub fn shell_join<S: AsRef<str>>(args: &[S]) -> String {
+    args.iter()
+        .map(|arg| shell_quote(arg.as_ref()))
+        .collect::<Vec<_>>()
+        .join(" ")
 }
@@ -1,60 +1,252 @@
-use crate::types::TemplateManifest;
-use anyhow::{Result, bail};
-use std::collections::{HashMap, HashSet};
+use crate::condition::parse_condition;
+use crate::renderer::Renderer;
+use crate::types::{StepDef, TemplateManifest, TemplateRequirement};
+use semver::Version;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
 
-pub fn resolve_order(templates: &[TemplateManifest]) -> Result<Vec<String>> {
-    let names: HashSet<&str> = templates.iter().map(|t| t.template.name.as_str()).collect();
-    let deps: HashMap<&str, Vec<&str>> = templates
-        .iter()
-        .map(|t| {
-            let name = t.template.name.as_str();
-            let reqs: Vec<&str> = t
-                .dependencies
-                .requires_templates
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum ResolveError {
+    /// `path` lists the templates on the cycle, starting and ending with the same name.
+    #[error("circular dependency: {}", .path.join(" -> "))]
+    Cycle { path: Vec<String> },
+    #[error("template '{template}' requires missing dependency template '{dependency}'")]
+    MissingDependency {
+        template: String,
+        dependency: String,
+    },
+    /// Every `requires_templates` version constraint that the available templates break.
+    #[error(
+        "template version conflicts:\n{}",
+        .0.iter().map(|c| format!("  - {c}")).collect::<Vec<_>>().join("\n")
+    )]
+    VersionConflicts(Vec<VersionConflict>),
+}
+
+/// A template requiring a version of another template that is not the one available.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionConflict {
+    pub template: String,
+    pub requirement: TemplateRequirement,
+    /// Version of the available template; `None` if it is unversioned.
+    pub found: Option<Version>,
+}
+
+impl fmt::Display for VersionConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "'{}' requires '{}', but ",
+            self.template, self.requirement
+        )?;
+        match &self.found {
+            Some(version) => write!(f, "version {version} is available"),
+            None => write!(f, "the available template has no version"),
+        }
+    }
+}
+
+/// Check every versioned `requires_templates` entry against the version of the required
+/// template among `templates` (as found in the registry). Requirements on templates that
+/// are not in `templates` are left to [`resolve_order`].
+pub fn check_versions(templates: &[TemplateManifest]) -> Result<(), ResolveError> {
+    let mut conflicts = Vec::new();
+    for t in templates {
+        for req in &t.dependencies.requires_templates {
+            let Some(dep) = templates.iter().find(|d| d.template.name == req.name) else {
+                continue;
+            };
+            if !req.matches(dep.template.version.as_ref()) {
+                conflicts.push(VersionConflict {
+                    template: t.template.name.clone(),
+                    requirement: req.clone(),
+                    found: dep.template.version.clone(),
+                });
+            }
+        }
+    }
+    if conflicts.is_empty() {
+        Ok(())
+    } else {
+        Err(ResolveError::VersionConflicts(conflicts))
+    }
+}
+
+/// Order templates so every template comes after the templates it requires.
+///
+/// The order is deterministic: templates keep the order they were given in (the user's
+/// selection order), each template's dependencies are placed right before it, and
+/// dependencies of the same template are visited alphabetically. Version constraints
+/// are checked with [`check_versions`].
+pub fn resolve_order(templates: &[TemplateManifest]) -> Result<Vec<String>, ResolveError> {
+    let mut deps: HashMap<&str, Vec<&str>> = HashMap::new();
+    for t in templates {
+        let name = t.template.name.as_str();
+        let mut reqs = Vec::new();
+        for req in &t.dependencies.requires_templates {
+            if !templates
                 .iter()
-                .filter(|r| names.contains(r.as_str()))
-                .map(|r| r.as_str())
-                .collect();
-            (name, reqs)
-        })
-        .collect();
+                .any(|other| other.template.name == req.name)
+            {
+                return Err(ResolveError::MissingDependency {
+                    template: name.to_string(),
+                    dependency: req.name.clone(),
+                });
+            }
+            reqs.push(req.name.as_str());
+        }
+        reqs.sort_unstable();
+        reqs.dedup();
+        deps.insert(name, reqs);
+    }
+    check_versions(templates)?;
 
     let mut order = Vec::new();
     let mut visited = HashSet::new();
-    let mut in_stack = HashSet::new();
+    let mut stack = Vec::new();
 
-    for name in &names {
-        if !visited.contains(name) {
-            visit(name, &deps, &mut visited, &mut in_stack, &mut order)?;
-        }
+    for t in templates {
+        visit(
+            t.template.name.as_str(),
+            &deps,
+            &mut visited,
+            &mut stack,
+            &mut order,
+        )?;
     }
 
     Ok(order)
 }
 
+/// Group templates into layers that can run concurrently. Every template is in a later
+/// layer than the templates it requires and than any earlier template (in
+/// [`resolve_order`]) that captures a variable it reads. Layers keep the
+/// [`resolve_order`] order.
+pub fn resolve_layers(templates: &[TemplateManifest]) -> Result<Vec<Vec<String>>, ResolveError> {
+    let order = resolve_order(templates)?;
+    let by_name: HashMap<&str, &TemplateManifest> = templates
+        .iter()
+        .map(|t| (t.template.name.as_str(), t))
+        .collect();
+
+    let mut producers: HashMap<String, &str> = HashMap::new();
+    let mut layer_of: HashMap<&str, usize> = HashMap::new();
+    let mut layers: Vec<Vec<String>> = Vec::new();
+
+    for name in &order {
+        let tmpl = by_name[name.as_str()];
+        let mut after: BTreeSet<&str> = tmpl
+            .dependencies
+            .requires_templates
+            .iter()
+            .map(|req| req.name.as_str())
+            .collect();
+        for step in &tmpl.steps {
+            match step_variables(tmpl, step) {
+                Some(names) => after.extend(names.iter().filter_map(|var| producers.get(var))),
+                // Unknown reads: wait for every template that captured something.
+                None => after.extend(producers.values()),
+            }
+        }
+
+        let layer = after
+            .iter()
+            .filter_map(|dep| layer_of.get(dep))
+            .map(|l| l + 1)
+            .max()
+            .unwrap_or(0);
+        layer_of.insert(tmpl.template.name.as_str(), layer);
+        if layers.len() <= layer {
+            layers.resize_with(layer + 1, Vec::new);
+        }
+        layers[layer].push(name.clone());
+
+        for step in &tmpl.steps {
+            if let Some(capture) = &step.capture {
+                producers
+                    .entry(capture.var.clone())
+                    .or_insert(tmpl.template.name.as_str());
+            }
+        }
+    }
+
+    Ok(layers)
+}
+
+/// Variables a step reads in its `command` (or `script` body), `args`, `condition`,
+/// `working_dir` and `check`, in the partials those include, and in the paths and bodies
+/// of the files a `bundled` step overlays. `None` when any of them cannot be scanned (a
+/// parse error, a missing file or a partial named by an expression), so the step may read
+/// any variable.
+pub fn step_variables(tmpl: &TemplateManifest, step: &StepDef) -> Option<BTreeSet<String>> {
+    let renderer = match &tmpl.source {
+        Some(source) => Renderer::with_source(source.clone()),
+        None => Renderer::new(),
+    };
+    let script = match &step.script {
+        Some(path) => Some(tmpl.source.as_ref()?.read_to_string(path).ok()?),
+        None => None,
+    };
+    let mut texts: Vec<String> = [
+        script.as_ref().or(step.command.as_ref()),
+        step.condition.as_ref(),
+        step.working_dir.as_ref(),
+        step.check.as_ref(),
+    ]
+    .into_iter()
+    .flatten()
+    .chain(step.args.iter().flatten())
+    .cloned()
+    .collect();
+    if step.step_type == "bundled" {
+        let source = tmpl.source.as_ref()?;
+        let dir = step
+            .source
+            .as_deref()?
+            .trim_start_matches("./")
+            .trim_end_matches('/');
+        for rel in source.list_files(dir).ok()? {
+            let data = source.read_file(&format!("{dir}/{rel}")).ok()?;
+            texts.extend(String::from_utf8(data).ok());
+            texts.push(rel);
+        }
+    }
+
+    let mut names = BTreeSet::new();
+    for text in &texts {
+        names.extend(renderer.variables(text).ok()?);
+    }
+    if let Some(cond) = &step.condition {
+        names.extend(parse_condition(cond).ok()?.variables());
+    }
+    Some(names)
+}
+
 fn visit<'a>(
     node: &'a str,
     deps: &HashMap<&'a str, Vec<&'a str>>,
     visited: &mut HashSet<&'a str>,
-    in_stack: &mut HashSet<&'a str>,
+    stack: &mut Vec<&'a str>,
     order: &mut Vec<String>,
-) -> Result<()> {
-    if in_stack.contains(node) {
-        bail!("circular dependency detected involving '{node}'");
+) -> Result<(), ResolveError> {
+    if let Some(pos) = stack.iter().position(|n| *n == node) {
+        let mut path: Vec<String> = stack[pos..].iter().map(|n| n.to_string()).collect();
+        path.push(node.to_string());
+        return Err(ResolveError::Cycle { path });
     }
     if visited.contains(node) {
         return Ok(());
     }
 
-    in_stack.insert(node);
+    stack.push(node);
 
     if let Some(node_deps) = deps.get(node) {
         for dep in node_deps {
-            visit(dep, deps, visited, in_stack, order)?;
+            visit(dep, deps, visited, stack, order)?;
         }
     }
 
-    in_stack.remove(node);
+    stack.pop();
     visited.insert(node);
     order.push(node.to_string());
     Ok(())
@@ -0,0 +1,149 @@
+use anyhow::{Context, Result, bail};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::embedded::TemplateAssets;
+use crate::remote::PIN_FILE;
+
+/// Location of a template's manifest and the companion files shipped next to it
+/// (`files/`, `partials/`, scripts).
+#[derive(Debug, Clone, PartialEq)]
+pub enum TemplateSource {
+    /// Embedded in the binary; `prefix` is the asset path of the template directory (e.g. `axum-server/`).
+    Embedded { prefix: String },
+    /// Template directory under the local `templates/` development override.
+    Local(PathBuf),
+    /// Template directory in the remote template cache.
+    Cached(PathBuf),
+}
+
+impl TemplateSource {
+    /// Returns true if `path` (relative to the template root) is a file.
+    pub fn exists(&self, path: &str) -> bool {
+        let Ok(path) = normalize_path(path) else {
+            return false;
+        };
+        match self {
+            TemplateSource::Embedded { prefix } => {
+                TemplateAssets::get(&format!("{prefix}{path}")).is_some()
+            }
+            TemplateSource::Local(root) | TemplateSource::Cached(root) => root.join(path).is_file(),
+        }
+    }
+
+    /// List every file under `dir` (relative to the template root), returned relative to `dir`
+    /// with `/` separators and sorted.
+    pub fn list_files(&self, dir: &str) -> Result<Vec<String>> {
+        let dir = format!("{}/", normalize_path(dir)?);
+        let mut files = match self {
+            TemplateSource::Embedded { prefix } => {
+                let full = format!("{prefix}{dir}");
+                TemplateAssets::iter()
+                    .filter_map(|path| path.strip_prefix(full.as_str()).map(str::to_string))
+                    .filter(|rel| !rel.is_empty())
+                    .collect::<Vec<_>>()
+            }
+            TemplateSource::Local(root) | TemplateSource::Cached(root) => {
+                let base = root.join(&dir);
+                if !base.is_dir() {
+                    bail!("template directory '{}' not found", base.display());
+                }
+                let mut files = Vec::new();
+                walk_dir(&base, &base, &mut files)?;
+                files
+            }
+        };
+        if files.is_empty() {
+            bail!("template source '{dir}' contains no files");
+        }
+        files.sort();
+        Ok(files)
+    }
+
+    /// Read a file relative to the template root.
+    pub fn read_file(&self, path: &str) -> Result<Vec<u8>> {
+        let path = normalize_path(path)?;
+        match self {
+            TemplateSource::Embedded { prefix } => {
+                let full = format!("{prefix}{path}");
+                let file = TemplateAssets::get(&full)
+                    .with_context(|| format!("embedded template file '{full}' not found"))?;
+                Ok(file.data.into_owned())
+            }
+            TemplateSource::Local(root) | TemplateSource::Cached(root) => {
+                let full = root.join(path);
+                std::fs::read(&full).with_context(|| format!("reading {}", full.display()))
+            }
+        }
+    }
+
+    /// SHA-256 (hex) over every file of the template (manifest and companion files), in
+    /// path order. A git checkout's `.git` directory and pin file are not part of the
+    /// template.
+    pub fn content_hash(&self) -> Result<String> {
+        let mut files = match self {
+            TemplateSource::Embedded { prefix } => TemplateAssets::iter()
+                .filter_map(|path| path.strip_prefix(prefix.as_str()).map(str::to_string))
+                .collect::<Vec<_>>(),
+            TemplateSource::Local(root) | TemplateSource::Cached(root) => {
+                let mut files = Vec::new();
+                walk_dir(root, root, &mut files)?;
+                files.retain(|f| f != PIN_FILE && f != ".git" && !f.starts_with(".git/"));
+                files
+            }
+        };
+        files.sort();
+        let mut hasher = Sha256::new();
+        for file in &files {
+            let data = self.read_file(file)?;
+            hasher.update(format!("{}:{}:{}:", file.len(), file, data.len()));
+            hasher.update(&data);
+        }
+        Ok(format!("{:x}", hasher.finalize()))
+    }
+
+    /// Read a UTF-8 text file relative to the template root.
+    pub fn read_to_string(&self, path: &str) -> Result<String> {
+        String::from_utf8(self.read_file(path)?)
+            .with_context(|| format!("template file '{path}' is not valid UTF-8"))
+    }
+}
+
+impl fmt::Display for TemplateSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateSource::Embedded { prefix } => write!(f, "embedded:{prefix}"),
+            TemplateSource::Local(root) => write!(f, "local:{}", root.display()),
+            TemplateSource::Cached(root) => write!(f, "cache:{}", root.display()),
+        }
+    }
+}
+
+/// Normalize a template-relative path, rejecting absolute paths and `..` components.
+fn normalize_path(path: &str) -> Result<String> {
+    let trimmed = path.trim_start_matches("./").trim_end_matches('/');
+    if trimmed.is_empty() || trimmed.starts_with('/') || trimmed.split('/').any(|c| c == "..") {
+        bail!("invalid template path '{path}'");
+    }
+    Ok(trimmed.to_string())
+}
+
+fn walk_dir(base: &Path, dir: &Path, files: &mut Vec<String>) -> Result<()> {
+    for entry in std::fs::read_dir(dir).with_context(|| format!("reading {}", dir.display()))? {
+        let path = entry?.path();
+        if path.is_dir() {
+            walk_dir(base, &path, files)?;
+        } else {
+            let rel = path
+                .strip_prefix(base)
+                .expect("walked path must be under base")
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+                .join("/");
+            files.push(rel);
+        }
+    }
+    Ok(())
+}
@@ -1,17 +1,104 @@
+use crate::types::{TemplateManifest, step_keys};
 use anyhow::Result;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::path::Path;
 
-#[derive(Debug, Serialize, Deserialize, Default)]
+/// Version written to new state files. Older files are upgraded when loaded:
+/// - 0: steps keyed by position; converted to step keys by [`PipelineState::load_for`].
+/// - 1: entries are `"Completed"` or `{"Failed": msg}` and attempts are kept in a
+///   separate map; converted to [`StepRecord`]s by [`PipelineState::load`].
+const SCHEMA_VERSION: u32 = 2;
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct PipelineState {
-    steps: HashMap<String, HashMap<usize, StepStateEntry>>,
+    #[serde(default)]
+    schema_version: u32,
+    /// The last run of each step per template, keyed by step key (see [`step_keys`]).
+    steps: HashMap<String, HashMap<String, StepRecord>>,
+    /// Variables captured from step output, restored on resume.
+    #[serde(default)]
+    vars: HashMap<String, String>,
+    /// The steps each template had when it last ran, to detect template changes.
+    #[serde(default)]
+    layouts: HashMap<String, Vec<RecordedStep>>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-enum StepStateEntry {
+/// What is known about the last run of a step. Entries migrated from older state files
+/// only have `status`, `error` and `attempts`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StepRecord {
+    pub status: StepStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Why the step did not run its command (condition false, check passed).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub skipped: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub started_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+    /// Attempts used, including retries; 0 when the step never executed.
+    #[serde(default)]
+    pub attempts: u32,
+    /// Exit code of the last attempt; absent for steps that run no process or were killed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    /// The rendered command (or script body, overlay source, clone URL).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub working_dir: Option<String>,
+    /// Last lines the step printed, including retry notices and earlier attempts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stdout_tail: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stderr_tail: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tforge_version: Option<String>,
+    /// Hash of the rendered inputs the step ran with, to detect drift on resume.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inputs_hash: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StepStatus {
     Completed,
-    Failed(String),
+    Failed,
+}
+
+impl StepRecord {
+    /// A record for a step run by this version of tforge.
+    pub fn new(status: StepStatus) -> Self {
+        Self {
+            status,
+            error: None,
+            skipped: None,
+            started_at: None,
+            finished_at: None,
+            duration_ms: None,
+            attempts: 0,
+            exit_code: None,
+            command: None,
+            working_dir: None,
+            stdout_tail: None,
+            stderr_tail: None,
+            tforge_version: Some(env!("CARGO_PKG_VERSION").to_string()),
+            inputs_hash: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct RecordedStep {
+    key: String,
+    hash: String,
 }
 
 #[derive(Debug, PartialEq)]
@@ -21,6 +108,62 @@ pub enum StepState {
     Failed(String),
 }
 
+/// A difference between a template's current steps and the ones it last ran with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StepChange {
+    pub template: String,
+    /// 1-based position: in the current template, or the old one for removed steps.
+    pub step: usize,
+    pub key: String,
+    pub kind: StepChangeKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepChangeKind {
+    /// The step is new and runs on resume.
+    Added,
+    /// The step's definition changed; it runs again on resume even if it completed.
+    Changed,
+    /// The step completed, but its rendered command, working dir, check or the variables
+    /// it reads differ from its last run (e.g. an edited `tforge.toml`); it runs again.
+    Drifted,
+    /// The step is gone from the template; its recorded progress is dropped.
+    Removed,
+}
+
+impl fmt::Display for StepChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (template, step) = (&self.template, self.step);
+        match self.kind {
+            StepChangeKind::Added => write!(f, "[{template}] step {step} is new and will run"),
+            StepChangeKind::Changed => write!(
+                f,
+                "[{template}] step {step} changed since the last run and will run again"
+            ),
+            StepChangeKind::Drifted => write!(
+                f,
+                "[{template}] step {step} inputs changed since the last run and will run again"
+            ),
+            StepChangeKind::Removed => write!(
+                f,
+                "[{template}] former step {step} ({}) was removed; its progress is dropped",
+                self.key
+            ),
+        }
+    }
+}
+
+impl Default for PipelineState {
+    fn default() -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            steps: HashMap::new(),
+            vars: HashMap::new(),
+            layouts: HashMap::new(),
+        }
+    }
+}
+
 impl PipelineState {
     pub fn new() -> Self {
         Self::default()
@@ -31,7 +174,31 @@ impl PipelineState {
             return Ok(Self::new());
         }
         let content = std::fs::read_to_string(path)?;
-        let state: PipelineState = serde_json::from_str(&content)?;
+        let mut value: Value = serde_json::from_str(&content)?;
+        let version = value
+            .get("schema_version")
+            .and_then(Value::as_u64)
+            .unwrap_or(0);
+        if version < 2 {
+            migrate_entries_to_records(&mut value);
+        }
+        let mut state: PipelineState = serde_json::from_value(value)?;
+        // Positional keys (version 0) need the templates; `load_for` converts them.
+        if version >= 1 {
+            state.schema_version = SCHEMA_VERSION;
+        }
+        Ok(state)
+    }
+
+    /// Load the state for a project made of `templates`. State files that key steps by
+    /// position are converted to step keys using the templates' current steps; progress
+    /// of templates not in `templates` cannot be converted and is dropped.
+    pub fn load_for(path: &Path, templates: &[TemplateManifest]) -> Result<Self> {
+        let mut state = Self::load(path)?;
+        if state.schema_version < 1 {
+            state.migrate_positional_keys(templates);
+        }
+        state.schema_version = SCHEMA_VERSION;
         Ok(state)
     }
 
@@ -41,28 +208,189 @@ impl PipelineState {
         Ok(())
     }
 
-    pub fn mark_completed(&mut self, template: &str, step_idx: usize) {
+    /// Store the record of a step's last run, replacing any earlier one.
+    pub fn record(&mut self, template: &str, key: &str, record: StepRecord) {
         self.steps
             .entry(template.to_string())
             .or_default()
-            .insert(step_idx, StepStateEntry::Completed);
+            .insert(key.to_string(), record);
+    }
+
+    pub fn step_record(&self, template: &str, key: &str) -> Option<&StepRecord> {
+        self.steps.get(template)?.get(key)
     }
 
-    pub fn mark_failed(&mut self, template: &str, step_idx: usize, error: &str) {
+    /// Names of the templates with recorded steps, sorted.
+    pub fn templates(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.steps.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// Whether any step of `template` has completed.
+    pub fn has_completed(&self, template: &str) -> bool {
         self.steps
-            .entry(template.to_string())
-            .or_default()
-            .insert(step_idx, StepStateEntry::Failed(error.to_string()));
+            .get(template)
+            .is_some_and(|s| s.values().any(|r| r.status == StepStatus::Completed))
+    }
+
+    /// Forget all step progress of `template`, e.g. after it was destroyed.
+    pub fn clear_template(&mut self, template: &str) {
+        self.steps.remove(template);
+        self.layouts.remove(template);
+    }
+
+    pub fn set_var(&mut self, name: &str, value: &str) {
+        self.vars.insert(name.to_string(), value.to_string());
+    }
+
+    pub fn vars(&self) -> &HashMap<String, String> {
+        &self.vars
     }
 
-    pub fn get(&self, template: &str, step_idx: usize) -> StepState {
+    pub fn get(&self, template: &str, key: &str) -> StepState {
         self.steps
             .get(template)
-            .and_then(|s| s.get(&step_idx))
-            .map(|e| match e {
-                StepStateEntry::Completed => StepState::Completed,
-                StepStateEntry::Failed(msg) => StepState::Failed(msg.clone()),
+            .and_then(|s| s.get(key))
+            .map(|r| match r.status {
+                StepStatus::Completed => StepState::Completed,
+                StepStatus::Failed => StepState::Failed(r.error.clone().unwrap_or_default()),
             })
             .unwrap_or(StepState::Pending)
     }
+
+    /// Remember the steps `tmpl` is about to run with, for [`PipelineState::step_changes`].
+    pub fn record_layout(&mut self, tmpl: &TemplateManifest) {
+        let layout = step_keys(&tmpl.steps)
+            .into_iter()
+            .zip(&tmpl.steps)
+            .map(|(key, step)| RecordedStep {
+                key,
+                hash: step.content_hash(),
+            })
+            .collect();
+        self.layouts.insert(tmpl.template.name.clone(), layout);
+    }
+
+    /// Steps added, changed or removed since `tmpl` last ran. Empty when it never ran.
+    /// A step without an `id` is keyed by its content, so editing it replaces its key;
+    /// a removed and an added step at the same position are reported as one change.
+    pub fn step_changes(&self, tmpl: &TemplateManifest) -> Vec<StepChange> {
+        let name = &tmpl.template.name;
+        let Some(recorded) = self.layouts.get(name) else {
+            return Vec::new();
+        };
+        let keys = step_keys(&tmpl.steps);
+        let current: HashSet<&str> = keys.iter().map(String::as_str).collect();
+        let old: HashMap<&str, &RecordedStep> =
+            recorded.iter().map(|r| (r.key.as_str(), r)).collect();
+        let change = |step: usize, key: &str, kind| StepChange {
+            template: name.clone(),
+            step,
+            key: key.to_string(),
+            kind,
+        };
+
+        let mut changes = Vec::new();
+        for (i, (key, step)) in keys.iter().zip(&tmpl.steps).enumerate() {
+            match old.get(key.as_str()) {
+                Some(r) if r.hash != step.content_hash() => {
+                    changes.push(change(i + 1, key, StepChangeKind::Changed))
+                }
+                Some(_) => {}
+                None if recorded
+                    .get(i)
+                    .is_some_and(|r| !current.contains(r.key.as_str())) =>
+                {
+                    changes.push(change(i + 1, key, StepChangeKind::Changed))
+                }
+                None => changes.push(change(i + 1, key, StepChangeKind::Added)),
+            }
+        }
+        for (i, r) in recorded.iter().enumerate() {
+            let replaced = keys.get(i).is_some_and(|k| !old.contains_key(k.as_str()));
+            if !current.contains(r.key.as_str()) && !replaced {
+                changes.push(change(i + 1, &r.key, StepChangeKind::Removed));
+            }
+        }
+        changes
+    }
+
+    /// Drop the progress of steps that changed or were removed since `tmpl` last ran, so
+    /// they are not mistaken for completed, and record its current steps.
+    pub fn forget_stale_steps(&mut self, tmpl: &TemplateManifest) {
+        let name = &tmpl.template.name;
+        if let Some(recorded) = self.layouts.get(name) {
+            let keys = step_keys(&tmpl.steps);
+            let stale: Vec<String> = recorded
+                .iter()
+                .filter(|r| {
+                    keys.iter()
+                        .zip(&tmpl.steps)
+                        .find(|(key, _)| **key == r.key)
+                        .is_none_or(|(_, step)| step.content_hash() != r.hash)
+                })
+                .map(|r| r.key.clone())
+                .collect();
+            for key in &stale {
+                if let Some(steps) = self.steps.get_mut(name) {
+                    steps.remove(key);
+                }
+            }
+        }
+        self.record_layout(tmpl);
+    }
+
+    /// Rename positional keys (`"0"`, `"1"`, ...) to the step keys of the current steps.
+    fn migrate_positional_keys(&mut self, templates: &[TemplateManifest]) {
+        fn rekey<T>(entries: HashMap<String, T>, keys: &[String]) -> HashMap<String, T> {
+            entries
+                .into_iter()
+                .filter_map(|(idx, entry)| {
+                    let key = keys.get(idx.parse::<usize>().ok()?)?;
+                    Some((key.clone(), entry))
+                })
+                .collect()
+        }
+
+        let mut steps = std::mem::take(&mut self.steps);
+        for tmpl in templates {
+            let name = &tmpl.template.name;
+            if let Some(entries) = steps.remove(name) {
+                self.steps
+                    .insert(name.clone(), rekey(entries, &step_keys(&tmpl.steps)));
+                self.record_layout(tmpl);
+            }
+        }
+    }
+}
+
+/// Convert version 0/1 entries (`"Completed"`, `{"Failed": msg}`) to [`StepRecord`]s and
+/// move the separate `attempts` map into them.
+fn migrate_entries_to_records(state: &mut Value) {
+    let attempts = state
+        .as_object_mut()
+        .and_then(|obj| obj.remove("attempts"))
+        .unwrap_or_default();
+    let Some(templates) = state.get_mut("steps").and_then(Value::as_object_mut) else {
+        return;
+    };
+    for (template, entries) in templates.iter_mut() {
+        let Some(entries) = entries.as_object_mut() else {
+            continue;
+        };
+        for (key, entry) in entries.iter_mut() {
+            let mut record = match entry {
+                Value::String(s) if s == "Completed" => serde_json::json!({"status": "completed"}),
+                Value::Object(obj) if obj.contains_key("Failed") => {
+                    serde_json::json!({"status": "failed", "error": obj["Failed"]})
+                }
+                _ => continue,
+            };
+            if let Some(n) = attempts.get(template).and_then(|a| a.get(key)) {
+                record["attempts"] = n.clone();
+            }
+            *entry = record;
+        }
+    }
 }
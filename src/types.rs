@@ -1,20 +1,42 @@
-use serde::Deserialize;
+use regex::Regex;
+use semver::VersionReq;
+use serde::{Deserialize, Deserializer};
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::renderer::shell_join;
+use crate::source::TemplateSource;
+use crate::vars::Value;
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct TemplateManifest {
     pub template: TemplateInfo,
     #[serde(default)]
     pub dependencies: Dependencies,
-    #[serde(default)]
+    /// `[parameters]` table keyed by name, or a `[[parameters]]` array of entries with a
+    /// `name`, see `deserialize_parameters`.
+    #[serde(default, deserialize_with = "deserialize_parameters")]
     pub parameters: HashMap<String, ParamDef>,
     #[serde(default)]
+    pub defaults: StepDefaults,
+    #[serde(default)]
     pub steps: Vec<StepDef>,
+    /// Teardown steps run by `tforge destroy`, in declaration order.
+    #[serde(default)]
+    pub destroy: Vec<StepDef>,
+    /// Where the manifest was loaded from; set by the loaders, not by the manifest itself.
+    #[serde(skip)]
+    pub source: Option<TemplateSource>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct TemplateInfo {
     pub name: String,
+    /// Semantic version of the template, recorded in `tforge.lock`.
+    #[serde(default)]
+    pub version: Option<semver::Version>,
     pub description: String,
     pub category: String,
     pub provider: Provider,
@@ -28,25 +50,295 @@ pub enum Provider {
     Command,
 }
 
+/// `[defaults]` for every `[[steps]]` and `[[destroy]]` entry that does not set the field
+/// itself.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct StepDefaults {
+    pub retry_on: Option<RetryPattern>,
+}
+
 #[derive(Debug, Deserialize, Clone, Default)]
 pub struct Dependencies {
     #[serde(default)]
     pub required_tools: Vec<String>,
     #[serde(default)]
-    pub requires_templates: Vec<String>,
+    pub requires_templates: Vec<TemplateRequirement>,
+}
+
+/// A `requires_templates` entry: a template name, optionally followed by a semver
+/// requirement (`"gcp-project >=1.2, <2"`).
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+#[serde(try_from = "String")]
+pub struct TemplateRequirement {
+    pub name: String,
+    pub version: Option<VersionReq>,
+}
+
+impl TemplateRequirement {
+    /// Whether a template with `version` satisfies this requirement. An unversioned
+    /// template only satisfies a requirement without a version constraint.
+    pub fn matches(&self, version: Option<&semver::Version>) -> bool {
+        match (&self.version, version) {
+            (None, _) => true,
+            (Some(req), Some(version)) => req.matches(version),
+            (Some(_), None) => false,
+        }
+    }
+}
+
+impl FromStr for TemplateRequirement {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (name, constraint) = match s.split_once(char::is_whitespace) {
+            Some((name, rest)) => (name, Some(rest.trim())),
+            None => (s, None),
+        };
+        if name.is_empty() {
+            return Err("empty template requirement".to_string());
+        }
+        let version = constraint
+            .map(|c| {
+                VersionReq::parse(c)
+                    .map_err(|e| format!("invalid version requirement '{c}' for '{name}': {e}"))
+            })
+            .transpose()?;
+        Ok(Self {
+            name: name.to_string(),
+            version,
+        })
+    }
+}
+
+impl TryFrom<String> for TemplateRequirement {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for TemplateRequirement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.version {
+            Some(req) => write!(f, "{} {req}", self.name),
+            None => f.write_str(&self.name),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct ParamDef {
     #[serde(rename = "type")]
     pub param_type: ParamType,
+    /// Question shown when prompting; `computed` parameters may leave it out.
+    #[serde(default)]
     pub prompt: String,
+    /// Static value, or for strings a minijinja template over earlier variables
+    /// (`"{{project_name}}-dev"`).
     #[serde(default)]
     pub default: Option<toml::Value>,
+    /// Never prompted or saved to the recipe: the value is always recomputed from
+    /// `default`, ignoring any value given for it.
+    #[serde(default)]
+    pub computed: bool,
     #[serde(default)]
     pub options: Vec<String>,
     #[serde(default)]
     pub when: Option<String>,
+    /// Prompt position within the template: parameters with an `order` come first, in
+    /// ascending order, the rest follow by name.
+    #[serde(default)]
+    pub order: Option<i64>,
+    /// Heading the parameter is prompted under; parameters of a group are asked together.
+    #[serde(default)]
+    pub group: Option<String>,
+    /// Extra explanation shown below the prompt.
+    #[serde(default)]
+    pub help: Option<String>,
+    /// Regex the whole value (each item of a list) has to match.
+    #[serde(default)]
+    pub pattern: Option<ParamPattern>,
+    /// Length bounds in characters; for `multi-select`, the number of selected options.
+    #[serde(default)]
+    pub min_length: Option<usize>,
+    #[serde(default)]
+    pub max_length: Option<usize>,
+    /// Bounds of an `integer` value.
+    #[serde(default)]
+    pub min: Option<i64>,
+    #[serde(default)]
+    pub max: Option<i64>,
+    /// Shown instead of the generated message when a validation rule fails.
+    #[serde(default)]
+    pub error_message: Option<String>,
+}
+
+/// Deserialize `[parameters]`, written either as a table keyed by parameter name or as a
+/// `[[parameters]]` array of tables with a `name`. Array entries without an explicit
+/// `order` are ordered by their position.
+fn deserialize_parameters<'de, D>(deserializer: D) -> Result<HashMap<String, ParamDef>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    use serde::de::Error;
+
+    let entries = match toml::Value::deserialize(deserializer)? {
+        toml::Value::Array(entries) => entries,
+        table => return HashMap::deserialize(table).map_err(D::Error::custom),
+    };
+    let mut parameters = HashMap::new();
+    for (position, entry) in entries.into_iter().enumerate() {
+        let toml::Value::Table(mut entry) = entry else {
+            return Err(D::Error::custom(format!(
+                "parameter #{} must be a table",
+                position + 1
+            )));
+        };
+        let name = match entry.remove("name") {
+            Some(toml::Value::String(name)) => name,
+            _ => {
+                return Err(D::Error::custom(format!(
+                    "parameter #{} needs a string `name`",
+                    position + 1
+                )));
+            }
+        };
+        let mut param = ParamDef::deserialize(entry)
+            .map_err(|e| D::Error::custom(format!("parameter '{name}': {e}")))?;
+        param.order.get_or_insert(position as i64);
+        if parameters.insert(name.clone(), param).is_some() {
+            return Err(D::Error::custom(format!("duplicate parameter '{name}'")));
+        }
+    }
+    Ok(parameters)
+}
+
+impl ParamDef {
+    /// Check `value` against the parameter's validation rules. The error is the message
+    /// to show the user: `error_message` when set, otherwise a description of the rule.
+    pub fn validate(&self, value: &Value) -> Result<(), String> {
+        self.check_rules(value)
+            .map_err(|rule| self.error_message.clone().unwrap_or(rule))
+    }
+
+    fn check_rules(&self, value: &Value) -> Result<(), String> {
+        if matches!(self.param_type, ParamType::Select | ParamType::MultiSelect)
+            && !self.options.is_empty()
+        {
+            let items = match value {
+                Value::List(items) => items.clone(),
+                other => vec![other.to_string()],
+            };
+            if let Some(item) = items.iter().find(|item| !self.options.contains(item)) {
+                return Err(format!(
+                    "'{item}' is not one of the options: {}",
+                    self.options.join(", ")
+                ));
+            }
+        }
+        let length = match value {
+            Value::List(items) => Some((items.len(), "select", "option")),
+            Value::String(s) => Some((s.chars().count(), "must have", "character")),
+            _ => None,
+        };
+        if let Some((length, verb, unit)) = length {
+            let plural = |n: usize| if n == 1 { "" } else { "s" };
+            if let Some(min) = self.min_length
+                && length < min
+            {
+                return Err(format!("{verb} at least {min} {unit}{}", plural(min)));
+            }
+            if let Some(max) = self.max_length
+                && length > max
+            {
+                return Err(format!("{verb} at most {max} {unit}{}", plural(max)));
+            }
+        }
+        if let Value::Integer(n) = value {
+            if let Some(min) = self.min
+                && *n < min
+            {
+                return Err(format!("must be at least {min}"));
+            }
+            if let Some(max) = self.max
+                && *n > max
+            {
+                return Err(format!("must be at most {max}"));
+            }
+        }
+        if let Some(pattern) = &self.pattern {
+            let items = match value {
+                Value::List(items) => items.clone(),
+                other => vec![other.to_string()],
+            };
+            if let Some(item) = items.iter().find(|item| !pattern.is_match(item)) {
+                return Err(format!("'{item}' does not match the pattern {pattern}"));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A parameter `pattern`. It has to match the whole value, as if written `^(?:...)$`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct ParamPattern {
+    source: String,
+    regex: Regex,
+}
+
+impl ParamPattern {
+    pub fn is_match(&self, value: &str) -> bool {
+        self.regex.is_match(value)
+    }
+}
+
+impl TryFrom<String> for ParamPattern {
+    type Error = String;
+
+    fn try_from(source: String) -> Result<Self, Self::Error> {
+        let regex = Regex::new(&format!("^(?:{source})$"))
+            .map_err(|e| format!("invalid pattern '{source}': {e}"))?;
+        Ok(Self { source, regex })
+    }
+}
+
+impl fmt::Display for ParamPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+/// A step's `retry_on` regex, searched for anywhere in the failed attempt's stderr.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct RetryPattern {
+    source: String,
+    regex: Regex,
+}
+
+impl RetryPattern {
+    pub fn is_match(&self, stderr: &str) -> bool {
+        self.regex.is_match(stderr)
+    }
+}
+
+impl TryFrom<String> for RetryPattern {
+    type Error = String;
+
+    fn try_from(source: String) -> Result<Self, Self::Error> {
+        let regex =
+            Regex::new(&source).map_err(|e| format!("invalid retry_on pattern '{source}': {e}"))?;
+        Ok(Self { source, regex })
+    }
+}
+
+impl fmt::Display for RetryPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
 }
 
 #[derive(Debug, Deserialize, Clone, PartialEq)]
@@ -56,17 +348,124 @@ pub enum ParamType {
     Select,
     MultiSelect,
     Bool,
+    Integer,
+}
+
+impl fmt::Display for ParamType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ParamType::String => "string",
+            ParamType::Select => "select",
+            ParamType::MultiSelect => "multi-select",
+            ParamType::Bool => "bool",
+            ParamType::Integer => "integer",
+        })
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct StepDef {
+    /// Stable identifier the step is tracked by in `.tforge-state.json`. Without it the
+    /// step is tracked by a hash of its definition (see [`StepDef::content_hash`]).
+    pub id: Option<String>,
     #[serde(rename = "type")]
     pub step_type: String,
     pub command: Option<String>,
+    /// Program and arguments run directly, without a shell, in place of `command`.
+    pub args: Option<Vec<String>>,
+    /// Script file relative to the template root, run in place of `command`.
+    pub script: Option<String>,
     pub condition: Option<String>,
     pub check: Option<String>,
     pub working_dir: Option<String>,
     pub action: Option<String>,
     pub source: Option<String>,
     pub url: Option<String>,
+    /// Store part of the command's stdout in a variable for later steps.
+    pub capture: Option<CaptureDef>,
+    /// Kill the step's process group after this many seconds.
+    pub timeout: Option<u64>,
+    /// Extra attempts after a failed or timed-out run (default 0).
+    pub retries: Option<u32>,
+    /// Seconds to wait before the first retry (default 2).
+    pub retry_delay: Option<u64>,
+    /// Multiplier applied to the delay after each retry (default 2.0).
+    pub retry_backoff: Option<f64>,
+    /// Only retry when this regex matches the failed attempt's stderr.
+    pub retry_on: Option<RetryPattern>,
+}
+
+impl StepDef {
+    /// Hash of the fields that decide what the step does. Timeouts and retry settings are
+    /// left out, so tuning them does not make a completed step run again.
+    pub fn content_hash(&self) -> String {
+        let capture = self.capture.as_ref();
+        let group = capture.and_then(|c| c.group).map(|g| g.to_string());
+        let args = self.args.as_deref().map(shell_join);
+        let fields = [
+            ("type", Some(self.step_type.as_str())),
+            ("command", self.command.as_deref()),
+            ("args", args.as_deref()),
+            ("script", self.script.as_deref()),
+            ("condition", self.condition.as_deref()),
+            ("check", self.check.as_deref()),
+            ("working_dir", self.working_dir.as_deref()),
+            ("action", self.action.as_deref()),
+            ("source", self.source.as_deref()),
+            ("url", self.url.as_deref()),
+            ("capture.var", capture.map(|c| c.var.as_str())),
+            ("capture.regex", capture.and_then(|c| c.regex.as_deref())),
+            ("capture.group", group.as_deref()),
+            ("capture.json", capture.and_then(|c| c.json.as_deref())),
+        ];
+        fields_hash(fields)
+    }
+}
+
+/// First 16 hex digits of the SHA-256 of named fields. Absent fields contribute nothing,
+/// so adding optional fields keeps existing hashes.
+pub(crate) fn fields_hash<'a>(
+    fields: impl IntoIterator<Item = (&'a str, Option<&'a str>)>,
+) -> String {
+    let mut hasher = Sha256::new();
+    for (name, value) in fields {
+        if let Some(value) = value {
+            hasher.update(format!("{name}:{}:", value.len()));
+            hasher.update(value);
+        }
+    }
+    hasher.finalize()[..8]
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Keys the steps are tracked by in the pipeline state: the step's `id`, or its content
+/// hash. A key that repeats within the template gets a `#2`, `#3`, ... suffix.
+pub fn step_keys(steps: &[StepDef]) -> Vec<String> {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    steps
+        .iter()
+        .map(|step| {
+            let key = step.id.clone().unwrap_or_else(|| step.content_hash());
+            let count = seen.entry(key.clone()).or_default();
+            *count += 1;
+            if *count == 1 {
+                key
+            } else {
+                format!("{key}#{count}")
+            }
+        })
+        .collect()
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct CaptureDef {
+    /// Variable the captured value is stored in.
+    pub var: String,
+    /// Regex matched against stdout; capture group `group` (default 1) is kept.
+    pub regex: Option<String>,
+    pub group: Option<usize>,
+    /// JSON path into stdout (`items[0].name`).
+    pub json: Option<String>,
 }
@@ -0,0 +1,204 @@
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+use crate::types::{ParamType, TemplateManifest};
+
+/// A variable value. Parameters keep the type of their `ParamType`; `--set` values and
+/// captured output start out as strings.
+///
+/// The string form (`Display`) is what shell commands, conditions and state hashes see:
+/// lists are comma-joined (`ios,android`), booleans are `true`/`false`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Value {
+    Bool(bool),
+    Integer(i64),
+    List(Vec<String>),
+    String(String),
+}
+
+impl Value {
+    /// The items of a list. Strings are split on commas, the form multi-select values
+    /// were stored in before variables were typed.
+    pub fn items(&self) -> Vec<Cow<'_, str>> {
+        match self {
+            Value::List(items) => items
+                .iter()
+                .map(|item| Cow::Borrowed(item.as_str()))
+                .collect(),
+            Value::String(s) => s
+                .split(',')
+                .map(|item| Cow::Borrowed(item.trim()))
+                .collect(),
+            other => vec![Cow::Owned(other.to_string())],
+        }
+    }
+
+    /// Convert to the value type of a parameter, e.g. `"true"` for a `bool` parameter.
+    pub fn coerce(self, param_type: &ParamType) -> Result<Value> {
+        Ok(match (param_type, self) {
+            (ParamType::String | ParamType::Select, value @ Value::String(_)) => value,
+            (ParamType::String | ParamType::Select, value) => Value::String(value.to_string()),
+            (ParamType::MultiSelect, value @ Value::List(_)) => value,
+            (ParamType::MultiSelect, Value::String(s)) if s.trim().is_empty() => {
+                Value::List(Vec::new())
+            }
+            (ParamType::MultiSelect, value) => {
+                Value::List(value.items().into_iter().map(Cow::into_owned).collect())
+            }
+            (ParamType::Bool, value @ Value::Bool(_)) => value,
+            (ParamType::Bool, Value::String(s)) => match s.trim() {
+                "true" => Value::Bool(true),
+                "false" => Value::Bool(false),
+                _ => bail!("expected true or false, got '{s}'"),
+            },
+            (ParamType::Integer, value @ Value::Integer(_)) => value,
+            (ParamType::Integer, Value::String(s)) => Value::Integer(
+                s.trim()
+                    .parse()
+                    .map_err(|_| anyhow::anyhow!("expected an integer, got '{s}'"))?,
+            ),
+            (param_type, value) => bail!("expected a {param_type} value, got '{value}'"),
+        })
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Bool(b) => write!(f, "{b}"),
+            Value::Integer(i) => write!(f, "{i}"),
+            Value::List(items) => f.write_str(&items.join(",")),
+            Value::String(s) => f.write_str(s),
+        }
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::String(s)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::String(s.to_string())
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(i: i64) -> Self {
+        Value::Integer(i)
+    }
+}
+
+impl From<Vec<String>> for Value {
+    fn from(items: Vec<String>) -> Self {
+        Value::List(items)
+    }
+}
+
+/// Compares the string form, as conditions do: `Value::Bool(true) == "true"`.
+impl PartialEq<str> for Value {
+    fn eq(&self, other: &str) -> bool {
+        match self {
+            Value::String(s) => s == other,
+            value => value.to_string().as_str() == other,
+        }
+    }
+}
+
+impl PartialEq<&str> for Value {
+    fn eq(&self, other: &&str) -> bool {
+        self == *other
+    }
+}
+
+/// Parse a `--set key=value` argument.
+pub fn parse_set_arg(arg: &str) -> Result<(String, String)> {
+    let (key, value) = arg
+        .split_once('=')
+        .ok_or_else(|| anyhow::anyhow!("invalid --set '{arg}': expected KEY=VALUE"))?;
+    let key = key.trim();
+    if key.is_empty() {
+        bail!("invalid --set '{arg}': key is empty");
+    }
+    Ok((key.to_string(), value.to_string()))
+}
+
+/// Load variables from a TOML file, or a JSON file when the extension is `.json`.
+pub fn load_var_file(path: &Path) -> Result<HashMap<String, Value>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+
+    let is_json = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+    let table: toml::Table = if is_json {
+        serde_json::from_str(&content)
+            .with_context(|| format!("failed to parse {} as JSON", path.display()))?
+    } else {
+        toml::from_str(&content)
+            .with_context(|| format!("failed to parse {} as TOML", path.display()))?
+    };
+
+    let mut vars = HashMap::new();
+    for (key, value) in table {
+        let value = toml_value_to_var(&value)
+            .with_context(|| format!("invalid value for '{key}' in {}", path.display()))?;
+        vars.insert(key, value);
+    }
+    Ok(vars)
+}
+
+/// Convert a TOML value (manifest default or variables file entry) into a variable
+/// value. Floats and datetimes become strings; list items are stored in string form.
+pub fn toml_value_to_var(value: &toml::Value) -> Result<Value> {
+    Ok(match value {
+        toml::Value::String(s) => Value::String(s.clone()),
+        toml::Value::Integer(i) => Value::Integer(*i),
+        toml::Value::Float(f) => Value::String(f.to_string()),
+        toml::Value::Boolean(b) => Value::Bool(*b),
+        toml::Value::Datetime(d) => Value::String(d.to_string()),
+        toml::Value::Array(items) => Value::List(
+            items
+                .iter()
+                .map(|item| toml_value_to_var(item).map(|item| item.to_string()))
+                .collect::<Result<Vec<_>>>()?,
+        ),
+        toml::Value::Table(_) => bail!("tables are not supported as variable values"),
+    })
+}
+
+/// Convert the values of every parameter of `templates` present in `vars` to the
+/// parameter's type. `--set` values and recipes saved before variables were typed hold
+/// strings (`"true"`, `"ios,android"`); this turns them into booleans, lists and integers.
+pub fn coerce_parameter_values(
+    templates: &[TemplateManifest],
+    vars: &mut HashMap<String, Value>,
+) -> Result<()> {
+    for tmpl in templates {
+        for (key, param) in &tmpl.parameters {
+            if let Some(value) = vars.remove(key) {
+                let value = value.coerce(&param.param_type).with_context(|| {
+                    format!(
+                        "invalid value for template '{}' parameter '{key}'",
+                        tmpl.template.name
+                    )
+                })?;
+                vars.insert(key.clone(), value);
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,19 @@
+use axum::{Json, Router, routing::get};
+use serde_json::{Value, json};
+use std::net::SocketAddr;
+
+#[tokio::main]
+async fn main() {
+    let app = Router::new().route("/health", get(health));
+
+    let addr = SocketAddr::from(([0, 0, 0, 0], {{port}}));
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .expect("failed to bind server address");
+    println!("{{project_name}}-server listening on {addr}");
+    axum::serve(listener, app).await.expect("server error");
+}
+
+async fn health() -> Json<Value> {
+    Json(json!({ "status": "ok" }))
+}
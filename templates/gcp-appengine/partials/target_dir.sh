@@ -0,0 +1,9 @@
+{%- if deploy_target == "flutter-app" %}
+TARGET_DIR={{project_name}}
+{%- elif deploy_target == "axum-server" %}
+TARGET_DIR={{project_name}}-server
+{%- elif deploy_target == "custom-path" %}
+TARGET_DIR={{deploy_target_path}}
+{%- else %}
+TARGET_DIR="."
+{%- endif %}
@@ -0,0 +1,95 @@
+use tforge::capture::extract;
+use tforge::types::CaptureDef;
+
+fn capture(toml_str: &str) -> CaptureDef {
+    toml::from_str(toml_str).unwrap()
+}
+
+#[test]
+fn test_capture_whole_stdout_trimmed() {
+    let def = capture(r#"var = "project_number""#);
+    assert_eq!(extract(&def, "123456789\n").unwrap(), "123456789");
+}
+
+#[test]
+fn test_capture_regex_group() {
+    let def = capture(
+        r#"
+var = "sa_email"
+regex = "Created service account \\[(.+?)\\]"
+"#,
+    );
+    let out = "Created service account [deployer@demo.iam.gserviceaccount.com].\n";
+    assert_eq!(
+        extract(&def, out).unwrap(),
+        "deployer@demo.iam.gserviceaccount.com"
+    );
+}
+
+#[test]
+fn test_capture_regex_explicit_group_and_no_groups() {
+    let def = capture(
+        r#"
+var = "minor"
+regex = "v(\\d+)\\.(\\d+)"
+group = 2
+"#,
+    );
+    assert_eq!(extract(&def, "version v1.42").unwrap(), "42");
+
+    let def = capture(
+        r#"
+var = "version"
+regex = "v\\d+\\.\\d+"
+"#,
+    );
+    assert_eq!(extract(&def, "version v1.42").unwrap(), "v1.42");
+}
+
+#[test]
+fn test_capture_regex_no_match_errors() {
+    let def = capture(
+        r#"
+var = "x"
+regex = "id=(\\d+)"
+"#,
+    );
+    assert!(extract(&def, "nothing here").is_err());
+}
+
+#[test]
+fn test_capture_json_path() {
+    let out = r#"{"connectionName": "demo:us-central1:main-db", "ipAddresses": [{"ipAddress": "10.0.0.3"}], "settings": {"tier": "db-f1-micro", "dataDiskSizeGb": 10}}"#;
+
+    let def = capture(
+        r#"
+var = "conn"
+json = "connectionName"
+"#,
+    );
+    assert_eq!(extract(&def, out).unwrap(), "demo:us-central1:main-db");
+
+    let def = capture(
+        r#"
+var = "ip"
+json = "ipAddresses[0].ipAddress"
+"#,
+    );
+    assert_eq!(extract(&def, out).unwrap(), "10.0.0.3");
+
+    let def = capture(
+        r#"
+var = "disk"
+json = "$.settings.dataDiskSizeGb"
+"#,
+    );
+    assert_eq!(extract(&def, out).unwrap(), "10");
+
+    let def = capture(
+        r#"
+var = "missing"
+json = "settings.missing"
+"#,
+    );
+    assert!(extract(&def, out).is_err());
+}
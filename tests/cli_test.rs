@@ -1,10 +1,9 @@
-use assert_cmd::Command;
+use assert_cmd::cargo::cargo_bin_cmd;
 use predicates::prelude::*;
 
 #[test]
 fn test_no_args_shows_help() {
-    Command::cargo_bin("tforge")
-        .unwrap()
+    cargo_bin_cmd!("tforge")
         .assert()
         .failure()
         .stderr(predicate::str::contains("Usage"));
@@ -12,8 +11,7 @@ fn test_no_args_shows_help() {
 
 #[test]
 fn test_version_flag() {
-    Command::cargo_bin("tforge")
-        .unwrap()
+    cargo_bin_cmd!("tforge")
         .arg("--version")
         .assert()
         .success()
@@ -22,9 +20,556 @@ fn test_version_flag() {
 
 #[test]
 fn test_list_subcommand() {
-    Command::cargo_bin("tforge")
+    cargo_bin_cmd!("tforge").arg("list").assert().success();
+}
+
+/// A project directory with a local `templates/` override and an isolated config dir.
+fn ci_project() -> tempfile::TempDir {
+    let tmp = tempfile::TempDir::new().unwrap();
+    let template_dir = tmp.path().join("templates/ci-app");
+    std::fs::create_dir_all(&template_dir).unwrap();
+    std::fs::write(
+        template_dir.join("template.toml"),
+        r#"
+[template]
+name = "ci-app"
+description = "CI test template"
+category = "test"
+provider = "command"
+
+[dependencies]
+
+[parameters]
+greeting = { type = "string", prompt = "Greeting", default = "hello" }
+target = { type = "string", prompt = "Target" }
+
+[[steps]]
+type = "command"
+command = "echo {{greeting}} {{target}} > {{project_name}}.txt"
+
+[[destroy]]
+type = "command"
+command = "rm {{project_name}}.txt"
+"#,
+    )
+    .unwrap();
+    tmp
+}
+
+fn tforge_in(dir: &std::path::Path) -> assert_cmd::Command {
+    let mut cmd = cargo_bin_cmd!("tforge");
+    cmd.current_dir(dir)
+        .env("HOME", dir.join("home"))
+        .env("XDG_CONFIG_HOME", dir.join("home/.config"));
+    cmd
+}
+
+#[test]
+fn test_new_non_interactive_with_set_and_var_file() {
+    let project = ci_project();
+    std::fs::write(project.path().join("vars.toml"), "target = \"file\"\n").unwrap();
+
+    tforge_in(project.path())
+        .args(["new", "demo", "-t", "ci-app", "--var-file", "vars.toml"])
+        .args(["--set", "target=world", "--yes"])
+        .assert()
+        .success();
+
+    let output = std::fs::read_to_string(project.path().join("demo.txt")).unwrap();
+    assert_eq!(output.trim(), "hello world");
+    let recipe = std::fs::read_to_string(project.path().join("tforge.toml")).unwrap();
+    assert!(recipe.contains("greeting = \"hello\""));
+    assert!(project.path().join(".tforge-state.json").exists());
+    let logs: Vec<_> = std::fs::read_dir(project.path().join(".tforge/logs"))
         .unwrap()
+        .collect();
+    assert_eq!(logs.len(), 1);
+}
+
+#[test]
+fn test_new_non_interactive_missing_required_parameter_fails() {
+    let project = ci_project();
+
+    tforge_in(project.path())
+        .args(["new", "demo", "--template", "ci-app", "--yes"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "template 'ci-app' requires parameter 'target'",
+        ));
+    assert!(!project.path().join("tforge.toml").exists());
+}
+
+#[test]
+fn test_new_yes_without_templates_fails() {
+    let project = ci_project();
+
+    tforge_in(project.path())
+        .args(["new", "demo", "--yes"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--template"));
+}
+
+#[test]
+fn test_new_dry_run_json_prints_plan_without_executing() {
+    let project = ci_project();
+
+    let output = tforge_in(project.path())
+        .args(["new", "demo", "-t", "ci-app", "--set", "target=world"])
+        .args(["--yes", "--dry-run", "--json"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let plan: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(plan[0]["template"], "ci-app");
+    assert_eq!(plan[0]["action"], "run");
+    assert_eq!(plan[0]["command"], "echo hello world > demo.txt");
+    assert!(!project.path().join("demo.txt").exists());
+    assert!(!project.path().join("tforge.toml").exists());
+    assert!(!project.path().join(".tforge-state.json").exists());
+}
+
+#[test]
+fn test_plan_reads_recipe() {
+    let project = ci_project();
+    std::fs::write(
+        project.path().join("tforge.toml"),
+        "project_name = \"demo\"\ntemplates = [\"ci-app\"]\n\n[parameters]\ngreeting = \"hi\"\ntarget = \"there\"\n",
+    )
+    .unwrap();
+
+    tforge_in(project.path())
+        .arg("plan")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("$ echo hi there > demo.txt"));
+    assert!(!project.path().join("demo.txt").exists());
+}
+
+#[test]
+fn test_typed_parameters_round_trip_through_recipe() {
+    let project = tempfile::TempDir::new().unwrap();
+    let template_dir = project.path().join("templates/typed-app");
+    std::fs::create_dir_all(&template_dir).unwrap();
+    std::fs::write(
+        template_dir.join("template.toml"),
+        r#"
+[template]
+name = "typed-app"
+description = "Typed parameters"
+category = "test"
+provider = "command"
+
+[parameters]
+services = { type = "multi-select", prompt = "Services", options = ["auth", "db", "queue"], default = ["auth"] }
+replicas = { type = "integer", prompt = "Replicas", default = 1 }
+verbose = { type = "bool", prompt = "Verbose?", default = false }
+
+[[steps]]
+type = "command"
+command = "{% for s in services %}echo {{s}} >> services.txt; {% endfor %}echo {{replicas + 1}}{% if verbose %} verbose{% endif %} > flags.txt"
+"#,
+    )
+    .unwrap();
+
+    tforge_in(project.path())
+        .args(["new", "demo", "-t", "typed-app", "--yes"])
+        .args([
+            "--set",
+            "services=auth,db",
+            "--set",
+            "replicas=2",
+            "--set",
+            "verbose=true",
+        ])
+        .assert()
+        .success();
+
+    let read = |name: &str| std::fs::read_to_string(project.path().join(name)).unwrap();
+    assert_eq!(read("services.txt"), "auth\ndb\n");
+    assert_eq!(read("flags.txt"), "3 verbose\n");
+    let recipe = read("tforge.toml");
+    assert!(recipe.contains("services = [\n    \"auth\",\n    \"db\",\n]"));
+    assert!(recipe.contains("replicas = 2"));
+    assert!(recipe.contains("verbose = true"));
+
+    // Recipes written before variables were typed store strings; they still load.
+    std::fs::write(
+        project.path().join("tforge.toml"),
+        "project_name = \"demo\"\ntemplates = [\"typed-app\"]\n\n[parameters]\nservices = \"db,queue\"\nreplicas = \"4\"\nverbose = \"false\"\n",
+    )
+    .unwrap();
+    tforge_in(project.path())
+        .arg("plan")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "$ echo db >> services.txt; echo queue >> services.txt; echo 5 > flags.txt",
+        ));
+
+    tforge_in(project.path())
+        .args([
+            "new",
+            "other",
+            "-t",
+            "typed-app",
+            "--yes",
+            "--set",
+            "replicas=many",
+        ])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "invalid value for template 'typed-app' parameter 'replicas'",
+        ));
+}
+
+#[test]
+fn test_computed_parameters_are_recomputed_from_recipe() {
+    let project = tempfile::TempDir::new().unwrap();
+    let template_dir = project.path().join("templates/bucket-app");
+    std::fs::create_dir_all(&template_dir).unwrap();
+    std::fs::write(
+        template_dir.join("template.toml"),
+        r#"
+[template]
+name = "bucket-app"
+description = "Computed parameters"
+category = "test"
+provider = "command"
+
+[parameters]
+env = { type = "string", prompt = "Environment", default = "dev" }
+bucket = { type = "string", computed = true, default = "{{project_name}}-{{env}}-assets" }
+
+[[steps]]
+type = "command"
+command = "echo {{bucket}} > bucket.txt"
+"#,
+    )
+    .unwrap();
+
+    tforge_in(project.path())
+        .args(["new", "demo", "-t", "bucket-app", "--yes"])
+        .args(["--set", "bucket=ignored"])
+        .assert()
+        .success();
+    let read = |name: &str| std::fs::read_to_string(project.path().join(name)).unwrap();
+    assert_eq!(read("bucket.txt"), "demo-dev-assets\n");
+    let recipe = read("tforge.toml");
+    assert!(recipe.contains("env = \"dev\""));
+    assert!(!recipe.contains("bucket ="));
+
+    // Editing the variable a computed parameter reads changes it; a value saved by an
+    // older version is ignored.
+    std::fs::write(
+        project.path().join("tforge.toml"),
+        recipe.replace("env = \"dev\"", "env = \"prod\"\nbucket = \"stale\""),
+    )
+    .unwrap();
+    tforge_in(project.path())
+        .arg("plan")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "$ echo demo-prod-assets > bucket.txt",
+        ));
+
+    tforge_in(project.path())
+        .args(["apply", "--yes"])
+        .assert()
+        .success();
+    assert_eq!(read("bucket.txt"), "demo-prod-assets\n");
+    let recipe = read("tforge.toml");
+    assert!(recipe.contains("env = \"prod\""));
+    assert!(!recipe.contains("bucket ="));
+}
+
+#[test]
+fn test_destroy_dry_run_then_destroy() {
+    let project = ci_project();
+    tforge_in(project.path())
+        .args([
+            "new",
+            "demo",
+            "-t",
+            "ci-app",
+            "--set",
+            "target=world",
+            "--yes",
+        ])
+        .assert()
+        .success();
+    assert!(project.path().join("demo.txt").exists());
+
+    tforge_in(project.path())
+        .args(["destroy", "--dry-run"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("$ rm demo.txt"));
+    assert!(project.path().join("demo.txt").exists());
+
+    tforge_in(project.path())
+        .args(["destroy", "--yes"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Destroyed 1 template(s)."));
+    assert!(!project.path().join("demo.txt").exists());
+
+    tforge_in(project.path())
+        .args(["destroy", "--yes"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Nothing to destroy"));
+}
+
+#[test]
+fn test_log_shows_recorded_step() {
+    let project = ci_project();
+    tforge_in(project.path())
+        .args(["new", "demo", "-t", "ci-app", "--set", "target=world"])
+        .arg("--yes")
+        .assert()
+        .success();
+
+    tforge_in(project.path())
+        .args(["log", "ci-app", "1"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("completed"))
+        .stdout(predicate::str::contains(
+            "command:   echo hello world > demo.txt",
+        ))
+        .stdout(predicate::str::contains("exit code: 0"));
+
+    tforge_in(project.path())
+        .args(["log", "ci-app", "2"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("has no step '2'"));
+}
+
+#[test]
+fn test_apply_converges_to_edited_recipe() {
+    let project = ci_project();
+    tforge_in(project.path())
+        .args(["new", "demo", "-t", "ci-app", "--set", "target=world"])
+        .arg("--yes")
+        .assert()
+        .success();
+
+    tforge_in(project.path())
+        .args(["apply", "--yes"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Nothing to apply"));
+
+    let recipe_path = project.path().join("tforge.toml");
+    let recipe = std::fs::read_to_string(&recipe_path).unwrap();
+    std::fs::write(
+        &recipe_path,
+        recipe.replace("greeting = \"hello\"", "greeting = \"bonjour\""),
+    )
+    .unwrap();
+
+    tforge_in(project.path())
+        .args(["apply", "--dry-run"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "inputs changed since the last run",
+        ))
+        .stdout(predicate::str::contains("$ echo bonjour world > demo.txt"));
+    let output = std::fs::read_to_string(project.path().join("demo.txt")).unwrap();
+    assert_eq!(output.trim(), "hello world");
+
+    tforge_in(project.path())
+        .args(["apply", "--yes"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("up to date"));
+    let output = std::fs::read_to_string(project.path().join("demo.txt")).unwrap();
+    assert_eq!(output.trim(), "bonjour world");
+}
+
+#[test]
+fn test_add_template_runs_only_new_template() {
+    let project = ci_project();
+    let extra_dir = project.path().join("templates/ci-extra");
+    std::fs::create_dir_all(&extra_dir).unwrap();
+    std::fs::write(
+        extra_dir.join("template.toml"),
+        r#"
+[template]
+name = "ci-extra"
+description = "CI test template that builds on ci-app"
+category = "test"
+provider = "command"
+
+[dependencies]
+requires_templates = ["ci-app"]
+
+[parameters]
+suffix = { type = "string", prompt = "Suffix", default = "extra" }
+
+[[steps]]
+type = "command"
+command = "echo {{target}} {{suffix}} > {{project_name}}-extra.txt"
+"#,
+    )
+    .unwrap();
+
+    tforge_in(project.path())
+        .args(["new", "demo", "-t", "ci-app", "--set", "target=world"])
+        .arg("--yes")
+        .assert()
+        .success();
+    std::fs::remove_file(project.path().join("demo.txt")).unwrap();
+
+    tforge_in(project.path())
+        .args(["add-template", "ci-extra", "--set", "suffix=more", "--yes"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Added ci-extra"));
+
+    let output = std::fs::read_to_string(project.path().join("demo-extra.txt")).unwrap();
+    assert_eq!(output.trim(), "world more");
+    assert!(!project.path().join("demo.txt").exists());
+
+    let recipe = std::fs::read_to_string(project.path().join("tforge.toml")).unwrap();
+    assert!(recipe.contains("\"ci-extra\""));
+    assert!(recipe.contains("suffix = \"more\""));
+
+    tforge_in(project.path())
+        .args(["add-template", "ci-extra", "--yes"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("already part of the project"))
+        .stdout(predicate::str::contains("Nothing to add"));
+}
+
+#[test]
+fn test_resume_refuses_when_template_no_longer_matches_lock() {
+    let project = ci_project();
+    tforge_in(project.path())
+        .args(["new", "demo", "-t", "ci-app", "--set", "target=world"])
+        .arg("--yes")
+        .assert()
+        .success();
+    let lock = std::fs::read_to_string(project.path().join("tforge.lock")).unwrap();
+    assert!(lock.contains("name = \"ci-app\""));
+    assert!(lock.contains("source = \"local\""));
+
+    let manifest = project.path().join("templates/ci-app/template.toml");
+    let content = std::fs::read_to_string(&manifest).unwrap();
+    std::fs::write(
+        &manifest,
+        content.replace(
+            "name = \"ci-app\"",
+            "name = \"ci-app\"\nversion = \"2.0.0\"",
+        ),
+    )
+    .unwrap();
+
+    tforge_in(project.path())
+        .arg("resume")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("no longer match tforge.lock"))
+        .stderr(predicate::str::contains("ci-app: locked unversioned ("));
+
+    tforge_in(project.path())
+        .args(["resume", "--ignore-lock"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("continuing with --ignore-lock"));
+    let lock = std::fs::read_to_string(project.path().join("tforge.lock")).unwrap();
+    assert!(lock.contains("version = \"2.0.0\""));
+
+    tforge_in(project.path()).arg("resume").assert().success();
+}
+
+#[test]
+fn test_install_from_file_registry_index() {
+    let project = ci_project();
+    let template_dir = project.path().join("registry/cloudrun");
+    std::fs::create_dir_all(&template_dir).unwrap();
+    std::fs::write(
+        template_dir.join("template.toml"),
+        r#"
+[template]
+name = "cloudrun"
+version = "1.1.0"
+description = "Cloud Run service"
+category = "cloud"
+provider = "command"
+
+[[steps]]
+type = "command"
+command = "echo cloudrun"
+"#,
+    )
+    .unwrap();
+    let status = std::process::Command::new("tar")
+        .args(["-czf", "cloudrun.tar.gz", "cloudrun"])
+        .current_dir(project.path().join("registry"))
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let data = std::fs::read(project.path().join("registry/cloudrun.tar.gz")).unwrap();
+    let sha256 = {
+        use sha2::Digest;
+        format!("{:x}", sha2::Sha256::digest(&data))
+    };
+    std::fs::write(
+        project.path().join("registry/index.toml"),
+        format!(
+            "[[template]]\nname = \"cloudrun\"\nversion = \"1.1.0\"\ndescription = \"Cloud Run service\"\ncategory = \"cloud\"\ntarball = \"cloudrun.tar.gz\"\nsha256 = \"{sha256}\"\n"
+        ),
+    )
+    .unwrap();
+    let config_dir = project.path().join("home/.config/tforge");
+    std::fs::create_dir_all(&config_dir).unwrap();
+    std::fs::write(
+        config_dir.join("config.toml"),
+        format!(
+            "registries = [\"file://{}\"]\n",
+            project.path().join("registry/index.toml").display()
+        ),
+    )
+    .unwrap();
+
+    tforge_in(project.path())
         .arg("list")
         .assert()
+        .success()
+        .stdout(predicate::str::contains("Available from registries"))
+        .stdout(predicate::str::contains("cloudrun"));
+    tforge_in(project.path())
+        .args(["search", "cloud run"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("[not installed]"));
+
+    tforge_in(project.path())
+        .args(["install", "cloudrun"])
+        .assert()
         .success();
+    assert!(config_dir.join("templates/cloudrun/template.toml").exists());
+
+    tforge_in(project.path())
+        .arg("list")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("cloudrun             1.1.0"))
+        .stdout(predicate::str::contains("Available from registries").not());
+    tforge_in(project.path())
+        .args(["install", "missing"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "not listed in any configured registry index",
+        ));
 }
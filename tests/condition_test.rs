@@ -1,5 +1,6 @@
 use std::collections::HashMap;
-use tforge::condition::evaluate_condition;
+use tforge::condition::{evaluate_condition, parse_condition};
+use tforge::vars::Value;
 
 #[test]
 fn test_contains_true() {
@@ -42,3 +43,167 @@ fn test_missing_variable() {
     let result = evaluate_condition("missing contains 'x'", &vars);
     assert!(result.is_err());
 }
+
+fn vars(pairs: &[(&str, &str)]) -> HashMap<String, Value> {
+    pairs
+        .iter()
+        .map(|(k, v)| (k.to_string(), (*v).into()))
+        .collect()
+}
+
+#[test]
+fn test_and_with_both_comparisons() {
+    let v = vars(&[("a", "x"), ("b", "z")]);
+    assert!(evaluate_condition("a == 'x' and b != 'y'", &v).unwrap());
+    assert!(!evaluate_condition("a == 'x' and b != 'z'", &v).unwrap());
+}
+
+#[test]
+fn test_or_not_and_parentheses() {
+    let v = vars(&[("env", "flexible"), ("deploy_now", "true")]);
+    assert!(evaluate_condition("env == 'standard' or deploy_now == 'true'", &v).unwrap());
+    assert!(!evaluate_condition("not (env == 'flexible' and deploy_now)", &v).unwrap());
+    assert!(evaluate_condition("not env == 'standard'", &v).unwrap());
+    assert!(
+        evaluate_condition(
+            "(env == 'standard' or env == 'flexible') and deploy_now",
+            &v
+        )
+        .unwrap()
+    );
+}
+
+#[test]
+fn test_and_binds_tighter_than_or() {
+    let v = vars(&[("a", "1"), ("b", "0"), ("c", "0")]);
+    assert!(evaluate_condition("a == 1 or b == 1 and c == 1", &v).unwrap());
+    assert!(!evaluate_condition("(a == 1 or b == 1) and c == 1", &v).unwrap());
+}
+
+#[test]
+fn test_in_list() {
+    let v = vars(&[("deploy_target", "axum-server")]);
+    assert!(evaluate_condition("deploy_target in ['flutter-app', 'axum-server']", &v).unwrap());
+    assert!(!evaluate_condition("deploy_target in ['project-root']", &v).unwrap());
+    assert!(
+        evaluate_condition("deploy_target not in ['project-root', 'custom-path']", &v).unwrap()
+    );
+}
+
+#[test]
+fn test_in_multi_select_variable() {
+    let v = vars(&[("services", "auth,analytics")]);
+    assert!(evaluate_condition("'auth' in services", &v).unwrap());
+    assert!(!evaluate_condition("'crashlytics' in services", &v).unwrap());
+}
+
+#[test]
+fn test_bool_truthiness() {
+    let v = vars(&[
+        ("deploy_now", "false"),
+        ("promote", "true"),
+        ("billing", ""),
+    ]);
+    assert!(!evaluate_condition("deploy_now", &v).unwrap());
+    assert!(evaluate_condition("promote", &v).unwrap());
+    assert!(!evaluate_condition("billing", &v).unwrap());
+    assert!(evaluate_condition("not deploy_now and promote", &v).unwrap());
+    assert!(evaluate_condition("deploy_now == false", &v).unwrap());
+}
+
+#[test]
+fn test_short_circuit_skips_missing_variables() {
+    let v = vars(&[("deploy_now", "false")]);
+    assert!(!evaluate_condition("deploy_now and promote_traffic", &v).unwrap());
+    assert!(evaluate_condition("not deploy_now or promote_traffic", &v).unwrap());
+}
+
+#[test]
+fn test_syntax_errors_report_column() {
+    let v = vars(&[("a", "x")]);
+    let err = evaluate_condition("a == 'x' and", &v)
+        .unwrap_err()
+        .to_string();
+    assert!(
+        err.contains("expected a value at end of condition"),
+        "{err}"
+    );
+
+    let err = evaluate_condition("a == 'x' b", &v)
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("unexpected 'b' at column 10"), "{err}");
+
+    let err = evaluate_condition("a = 'x'", &v).unwrap_err().to_string();
+    assert!(err.contains("column 3"), "{err}");
+
+    let err = evaluate_condition("a == 'x", &v).unwrap_err().to_string();
+    assert!(
+        err.contains("unterminated string starting at column 6"),
+        "{err}"
+    );
+
+    let err = evaluate_condition("(a == 'x'", &v).unwrap_err().to_string();
+    assert!(err.contains("expected ')'"), "{err}");
+}
+
+#[test]
+fn test_condition_variables() {
+    let condition = parse_condition("a == 'x' and (b or 'y' in c) and d not in [e, 'f']").unwrap();
+    let names: Vec<String> = condition.variables().into_iter().collect();
+    assert_eq!(names, vec!["a", "b", "c", "d", "e"]);
+}
+
+#[test]
+fn test_typed_values() {
+    let mut vars = HashMap::new();
+    vars.insert(
+        "services".to_string(),
+        Value::List(vec!["auth".into(), "crashlytics".into()]),
+    );
+    vars.insert("deploy_now".to_string(), Value::Bool(false));
+    vars.insert("replicas".to_string(), Value::Integer(3));
+    assert!(evaluate_condition("services contains 'crashlytics'", &vars).unwrap());
+    assert!(evaluate_condition("'auth' in services", &vars).unwrap());
+    assert!(!evaluate_condition("deploy_now", &vars).unwrap());
+    assert!(evaluate_condition("deploy_now == 'false'", &vars).unwrap());
+    assert!(evaluate_condition("replicas == 3", &vars).unwrap());
+
+    vars.insert("services".to_string(), Value::List(Vec::new()));
+    assert!(!evaluate_condition("services", &vars).unwrap());
+}
+
+#[test]
+fn test_unquoted_right_hand_identifier_falls_back_to_literal() {
+    let mut v = vars(&[("db", "postgres"), ("services", "auth,analytics")]);
+    assert!(evaluate_condition("db == postgres", &v).unwrap());
+    assert!(!evaluate_condition("db != postgres", &v).unwrap());
+    assert!(evaluate_condition("services contains auth", &v).unwrap());
+
+    // A variable of that name still wins.
+    v.insert("postgres".into(), "mysql".into());
+    assert!(!evaluate_condition("db == postgres", &v).unwrap());
+
+    // The left-hand side is always a variable.
+    let err = evaluate_condition("engine == postgres", &v)
+        .unwrap_err()
+        .to_string();
+    assert_eq!(err, "variable 'engine' not found");
+}
+
+#[test]
+fn test_unquoted_words_with_dashes_and_dots() {
+    let v = vars(&[
+        ("deploy_target", "flutter-app"),
+        ("db", "mysql-8.0"),
+        ("services", "auth"),
+    ]);
+    assert!(evaluate_condition("deploy_target == flutter-app", &v).unwrap());
+    assert!(evaluate_condition("db == mysql-8.0", &v).unwrap());
+    assert!(!evaluate_condition("db == mysql-9.0", &v).unwrap());
+    assert!(evaluate_condition("services in [auth, billing]", &v).unwrap());
+    assert!(!evaluate_condition("services not in [auth, billing]", &v).unwrap());
+
+    let v = vars(&[("replicas", "-1")]);
+    assert!(evaluate_condition("replicas == -1", &v).unwrap());
+}
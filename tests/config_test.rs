@@ -19,6 +19,7 @@ fn test_save_and_load_config() {
             api_key_env: Some("ANTHROPIC_API_KEY".into()),
             endpoint: None,
         }),
+        ..Default::default()
     };
     config.save(&config_path).unwrap();
 
@@ -39,3 +39,36 @@ fn test_registry_merge_deduplicates() {
         "merge should deduplicate"
     );
 }
+
+#[test]
+fn test_embedded_axum_server_ships_overlay_files() {
+    let registry = Registry::from_embedded().unwrap();
+    let template = registry.find("axum-server").expect("missing axum-server");
+    let source = template
+        .source
+        .as_ref()
+        .expect("embedded template has a source");
+    let files = source.list_files("files/").unwrap();
+    assert!(files.contains(&"src/main.rs".to_string()));
+    assert!(
+        template
+            .steps
+            .iter()
+            .any(|s| s.step_type == "bundled" && s.source.as_deref() == Some("files/"))
+    );
+}
+
+#[test]
+fn test_embedded_retry_patterns_compile() {
+    for template in load_embedded_templates().unwrap() {
+        let steps = template.steps.iter().chain(&template.destroy);
+        let patterns = steps.filter_map(|step| step.retry_on.as_ref());
+        for pattern in patterns.chain(&template.defaults.retry_on) {
+            assert!(
+                regex::Regex::new(&pattern.to_string()).is_ok(),
+                "{}: invalid retry_on '{pattern}'",
+                template.template.name
+            );
+        }
+    }
+}
@@ -1,7 +1,15 @@
 use std::collections::HashMap;
 use tempfile::TempDir;
-use tforge::engine::Engine;
-use tforge::types::TemplateManifest;
+use tforge::engine::{Engine, PlanAction};
+use tforge::source::TemplateSource;
+use tforge::state::{PipelineState, StepRecord, StepState, StepStatus};
+use tforge::types::{TemplateManifest, step_keys};
+
+fn failed(error: &str) -> StepRecord {
+    let mut record = StepRecord::new(StepStatus::Failed);
+    record.error = Some(error.to_string());
+    record
+}
 
 #[test]
 fn test_engine_runs_single_template() {
@@ -178,7 +186,7 @@ command = "touch deployed.txt"
     let mut vars_false = HashMap::new();
     vars_false.insert("deploy_now".into(), "false".into());
     Engine::new(tmp_false.path().to_path_buf())
-        .run(&[manifest.clone()], &vars_false)
+        .run(std::slice::from_ref(&manifest), &vars_false)
         .unwrap();
     assert!(!tmp_false.path().join("deployed.txt").exists());
 
@@ -190,3 +198,849 @@ command = "touch deployed.txt"
         .unwrap();
     assert!(tmp_true.path().join("deployed.txt").exists());
 }
+
+#[test]
+fn test_engine_runs_template_script_with_partials() {
+    let fixtures =
+        std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/templates");
+    let mut manifest: TemplateManifest = toml::from_str(
+        r#"
+[template]
+name = "scripted"
+description = "test"
+category = "test"
+provider = "bundled"
+[dependencies]
+
+[[steps]]
+type = "command"
+script = "scripts/setup.sh"
+
+[[steps]]
+type = "command"
+command = "echo '{% include \"partials/greeting.txt\" %}' > greeting.txt"
+"#,
+    )
+    .unwrap();
+    manifest.source = Some(TemplateSource::Local(fixtures.join("test-app")));
+
+    let tmp = TempDir::new().unwrap();
+    let mut vars = HashMap::new();
+    vars.insert("project_name".into(), "demo".into());
+    Engine::new(tmp.path().to_path_buf())
+        .run(&[manifest], &vars)
+        .unwrap();
+
+    let setup = std::fs::read_to_string(tmp.path().join("setup.txt")).unwrap();
+    assert_eq!(setup.trim(), "demo");
+    let greeting = std::fs::read_to_string(tmp.path().join("greeting.txt")).unwrap();
+    assert_eq!(greeting.trim(), "Hello demo!");
+}
+
+#[test]
+fn test_engine_captures_output_for_later_steps_and_resume() {
+    let tmp = TempDir::new().unwrap();
+    let state_path = tmp.path().join(".tforge-state.json");
+    let producer: TemplateManifest = toml::from_str(
+        r#"
+[template]
+name = "producer"
+description = "test"
+category = "test"
+provider = "command"
+[dependencies]
+
+[[steps]]
+type = "command"
+command = "echo 'project number: 4242'"
+capture = { var = "project_number", regex = "number: (\\d+)" }
+"#,
+    )
+    .unwrap();
+    let consumer: TemplateManifest = toml::from_str(
+        r#"
+[template]
+name = "consumer"
+description = "test"
+category = "test"
+provider = "command"
+[dependencies]
+requires_templates = ["producer"]
+
+[[steps]]
+type = "command"
+command = "echo {{project_number}} > number.txt"
+
+[[steps]]
+type = "command"
+command = "echo {{project_number}} > resumed.txt"
+condition = "resume_now == 'true'"
+"#,
+    )
+    .unwrap();
+
+    let mut vars = HashMap::new();
+    vars.insert("resume_now".into(), "false".into());
+    let engine = Engine::new(tmp.path().to_path_buf());
+    engine
+        .run_with_state(
+            &[producer.clone(), consumer.clone()],
+            &vars,
+            &state_path,
+            false,
+        )
+        .unwrap();
+    let number = std::fs::read_to_string(tmp.path().join("number.txt")).unwrap();
+    assert_eq!(number.trim(), "4242");
+
+    let state = PipelineState::load(&state_path).unwrap();
+    assert_eq!(state.vars().get("project_number").unwrap(), "4242");
+
+    // Resume with a new pending step: the captured value comes from the saved state.
+    let mut state = PipelineState::load(&state_path).unwrap();
+    state.record(
+        "consumer",
+        &step_keys(&consumer.steps)[1],
+        failed("interrupted"),
+    );
+    state.save(&state_path).unwrap();
+    vars.insert("resume_now".into(), "true".into());
+    engine
+        .run_with_state(&[producer, consumer], &vars, &state_path, true)
+        .unwrap();
+    let resumed = std::fs::read_to_string(tmp.path().join("resumed.txt")).unwrap();
+    assert_eq!(resumed.trim(), "4242");
+}
+
+#[test]
+fn test_engine_plan_renders_steps_without_executing() {
+    let tmp = TempDir::new().unwrap();
+    let producer: TemplateManifest = toml::from_str(
+        r#"
+[template]
+name = "producer"
+description = "test"
+category = "test"
+provider = "command"
+[dependencies]
+
+[[steps]]
+type = "command"
+command = "echo 4242 > produced.txt"
+capture = { var = "project_number" }
+"#,
+    )
+    .unwrap();
+    let consumer: TemplateManifest = toml::from_str(
+        r#"
+[template]
+name = "consumer"
+description = "test"
+category = "test"
+provider = "command"
+[dependencies]
+requires_templates = ["producer"]
+
+[[steps]]
+type = "command"
+command = "echo {{project_number}} > {{name}}.txt"
+working_dir = "{{name}}-dir"
+check = "test -f {{name}}.txt"
+
+[[steps]]
+type = "command"
+command = "touch skipped.txt"
+condition = "mode == 'full'"
+"#,
+    )
+    .unwrap();
+
+    let mut vars = HashMap::new();
+    vars.insert("name".into(), "demo".into());
+    vars.insert("mode".into(), "lite".into());
+
+    let engine = Engine::new(tmp.path().to_path_buf());
+    let plan = engine.plan(&[consumer, producer], &vars).unwrap();
+
+    assert_eq!(plan.len(), 3);
+    assert_eq!(plan[0].template, "producer");
+    assert_eq!(plan[0].action, PlanAction::Run);
+    assert_eq!(plan[1].template, "consumer");
+    assert_eq!(
+        plan[1].command.as_deref(),
+        Some("echo '<project_number from [producer] step 1>' > demo.txt")
+    );
+    assert_eq!(plan[1].working_dir.as_deref(), Some("demo-dir"));
+    assert_eq!(plan[1].check.as_deref(), Some("test -f demo.txt"));
+    assert_eq!(plan[2].action, PlanAction::Skip);
+    assert_eq!(
+        plan[2].reason.as_deref(),
+        Some("condition `mode == 'full'` is false")
+    );
+    assert!(plan[2].command.is_none());
+    assert!(std::fs::read_dir(tmp.path()).unwrap().next().is_none());
+}
+
+#[test]
+fn test_engine_plan_with_state_skips_completed_steps() {
+    let tmp = TempDir::new().unwrap();
+    let state_path = tmp.path().join(".tforge-state.json");
+    let manifest: TemplateManifest = toml::from_str(
+        r#"
+[template]
+name = "app"
+description = "test"
+category = "test"
+provider = "command"
+[dependencies]
+
+[[steps]]
+type = "command"
+command = "touch first.txt"
+
+[[steps]]
+type = "command"
+command = "touch second.txt"
+"#,
+    )
+    .unwrap();
+
+    let keys = step_keys(&manifest.steps);
+    let mut state = PipelineState::new();
+    state.record("app", &keys[0], StepRecord::new(StepStatus::Completed));
+    state.record("app", &keys[1], failed("boom"));
+    state.save(&state_path).unwrap();
+
+    let engine = Engine::new(tmp.path().to_path_buf());
+    let plan = engine
+        .plan_with_state(&[manifest], &HashMap::new(), &state_path)
+        .unwrap();
+
+    assert_eq!(plan[0].action, PlanAction::Skip);
+    assert_eq!(plan[0].reason.as_deref(), Some("already completed"));
+    assert_eq!(plan[1].action, PlanAction::Run);
+    assert_eq!(plan[1].command.as_deref(), Some("touch second.txt"));
+}
+
+#[test]
+fn test_engine_records_attempts_for_retried_steps() {
+    let tmp = TempDir::new().unwrap();
+    let state_path = tmp.path().join(".tforge-state.json");
+    let manifest: TemplateManifest = toml::from_str(
+        r#"
+[template]
+name = "flaky"
+description = "test"
+category = "test"
+provider = "command"
+[dependencies]
+
+[[steps]]
+type = "command"
+command = "echo ok"
+
+[[steps]]
+type = "command"
+command = "test -f marker || { touch marker; exit 1; }"
+retries = 1
+retry_delay = 0
+"#,
+    )
+    .unwrap();
+
+    let keys = step_keys(&manifest.steps);
+    Engine::new(tmp.path().to_path_buf())
+        .run_with_state(&[manifest], &HashMap::new(), &state_path, false)
+        .unwrap();
+
+    let state = PipelineState::load(&state_path).unwrap();
+    assert_eq!(state.step_record("flaky", &keys[0]).unwrap().attempts, 1);
+    assert_eq!(state.step_record("flaky", &keys[1]).unwrap().attempts, 2);
+}
+
+#[test]
+fn test_engine_applies_template_default_retry_on() {
+    let tmp = TempDir::new().unwrap();
+    let state_path = tmp.path().join(".tforge-state.json");
+    let manifest: TemplateManifest = toml::from_str(
+        r#"
+[template]
+name = "flaky"
+description = "test"
+category = "test"
+provider = "command"
+[dependencies]
+
+[defaults]
+retry_on = "unavailable"
+
+[[steps]]
+type = "command"
+command = "test -f first || { touch first; echo unavailable >&2; exit 1; }"
+retries = 1
+retry_delay = 0
+
+[[steps]]
+type = "command"
+command = "test -f second || { touch second; echo unavailable >&2; exit 1; }"
+retries = 1
+retry_delay = 0
+retry_on = "quota"
+"#,
+    )
+    .unwrap();
+
+    let keys = step_keys(&manifest.steps);
+    let result = Engine::new(tmp.path().to_path_buf()).run_with_state(
+        &[manifest],
+        &HashMap::new(),
+        &state_path,
+        false,
+    );
+    assert!(
+        result.is_err(),
+        "the step's own retry_on wins over the default"
+    );
+
+    let state = PipelineState::load(&state_path).unwrap();
+    assert_eq!(state.step_record("flaky", &keys[0]).unwrap().attempts, 2);
+    assert_eq!(state.step_record("flaky", &keys[1]).unwrap().attempts, 1);
+}
+
+fn destroyable(name: &str, requires: &[&str]) -> TemplateManifest {
+    let requires = requires
+        .iter()
+        .map(|r| format!("\"{r}\""))
+        .collect::<Vec<_>>()
+        .join(", ");
+    toml::from_str(&format!(
+        r#"
+[template]
+name = "{name}"
+description = "test"
+category = "test"
+provider = "command"
+[dependencies]
+requires_templates = [{requires}]
+
+[[steps]]
+type = "command"
+command = "echo {name} >> created.txt"
+
+[[destroy]]
+type = "command"
+command = "echo {name} >> destroyed.txt"
+"#
+    ))
+    .unwrap()
+}
+
+#[test]
+fn test_engine_destroy_runs_in_reverse_order_and_clears_state() {
+    let tmp = TempDir::new().unwrap();
+    let state_path = tmp.path().join(".tforge-state.json");
+    let templates = vec![
+        destroyable("db", &["project"]),
+        destroyable("project", &[]),
+        destroyable("unused", &[]),
+    ];
+
+    let mut state = PipelineState::new();
+    for tmpl in &templates {
+        if tmpl.template.name != "unused" {
+            state.record(
+                &tmpl.template.name,
+                &step_keys(&tmpl.steps)[0],
+                StepRecord::new(StepStatus::Completed),
+            );
+        }
+    }
+    state.save(&state_path).unwrap();
+
+    let engine = Engine::new(tmp.path().to_path_buf());
+    let destroyed = engine
+        .destroy(&templates, &HashMap::new(), &state_path, &[])
+        .unwrap();
+
+    assert_eq!(destroyed, vec!["db", "project"]);
+    let log = std::fs::read_to_string(tmp.path().join("destroyed.txt")).unwrap();
+    assert_eq!(log, "db\nproject\n");
+    let state = PipelineState::load(&state_path).unwrap();
+    assert!(!state.has_completed("db"));
+    assert!(!state.has_completed("project"));
+}
+
+#[test]
+fn test_engine_destroy_filter_refuses_to_orphan_dependents() {
+    let tmp = TempDir::new().unwrap();
+    let state_path = tmp.path().join(".tforge-state.json");
+    let templates = vec![destroyable("db", &["project"]), destroyable("project", &[])];
+
+    let mut state = PipelineState::new();
+    for tmpl in &templates {
+        if tmpl.template.name != "unused" {
+            state.record(
+                &tmpl.template.name,
+                &step_keys(&tmpl.steps)[0],
+                StepRecord::new(StepStatus::Completed),
+            );
+        }
+    }
+    state.save(&state_path).unwrap();
+
+    let engine = Engine::new(tmp.path().to_path_buf());
+    let err = engine
+        .destroy(
+            &templates,
+            &HashMap::new(),
+            &state_path,
+            &["project".into()],
+        )
+        .unwrap_err();
+    assert!(err.to_string().contains("template 'db' requires 'project'"));
+
+    let plan = engine
+        .plan_destroy(&templates, &HashMap::new(), &state_path, &["db".into()])
+        .unwrap();
+    assert_eq!(plan.len(), 1);
+    assert_eq!(plan[0].template, "db");
+    assert_eq!(plan[0].command.as_deref(), Some("echo db >> destroyed.txt"));
+    assert!(!tmp.path().join("destroyed.txt").exists());
+}
+
+fn rendezvous(name: &str, other: &str) -> TemplateManifest {
+    toml::from_str(&format!(
+        r#"
+[template]
+name = "{name}"
+description = "test"
+category = "test"
+provider = "command"
+[dependencies]
+
+[[steps]]
+type = "command"
+command = "touch {name}.ready; for i in $(seq 100); do test -f {other}.ready && exit 0; sleep 0.05; done; exit 1"
+
+[[steps]]
+type = "command"
+command = "echo {name}-value"
+capture = {{ var = "{name}_value" }}
+"#
+    ))
+    .unwrap()
+}
+
+#[test]
+fn test_engine_runs_independent_templates_concurrently() {
+    let tmp = TempDir::new().unwrap();
+    let state_path = tmp.path().join(".tforge-state.json");
+    // Each template waits for the other to start, so this only passes when they overlap.
+    let consumer: TemplateManifest = toml::from_str(
+        r#"
+[template]
+name = "consumer"
+description = "test"
+category = "test"
+provider = "command"
+[dependencies]
+
+[[steps]]
+type = "command"
+command = "echo {{left_value}} {{right_value}} > both.txt"
+"#,
+    )
+    .unwrap();
+    let templates = vec![
+        rendezvous("left", "right"),
+        rendezvous("right", "left"),
+        consumer,
+    ];
+
+    Engine::new(tmp.path().to_path_buf())
+        .with_jobs(2)
+        .run_with_state(&templates, &HashMap::new(), &state_path, false)
+        .unwrap();
+
+    let both = std::fs::read_to_string(tmp.path().join("both.txt")).unwrap();
+    assert_eq!(both.trim(), "left-value right-value");
+    let state = PipelineState::load(&state_path).unwrap();
+    for tmpl in &templates {
+        for key in step_keys(&tmpl.steps) {
+            let name = &tmpl.template.name;
+            assert_eq!(state.get(name, &key), StepState::Completed, "{name} {key}");
+        }
+    }
+    assert_eq!(state.vars().get("right_value").unwrap(), "right-value");
+}
+
+#[test]
+fn test_engine_parallel_failure_stops_later_layers() {
+    let tmp = TempDir::new().unwrap();
+    let state_path = tmp.path().join(".tforge-state.json");
+    let templates: Vec<TemplateManifest> = [
+        ("broken", "exit 3", ""),
+        ("fine", "touch fine.txt", ""),
+        (
+            "after",
+            "touch after.txt",
+            "requires_templates = [\"fine\"]",
+        ),
+    ]
+    .iter()
+    .map(|(name, cmd, deps)| {
+        toml::from_str(&format!(
+            r#"
+[template]
+name = "{name}"
+description = "test"
+category = "test"
+provider = "command"
+[dependencies]
+{deps}
+
+[[steps]]
+type = "command"
+command = "{cmd}"
+"#
+        ))
+        .unwrap()
+    })
+    .collect();
+
+    let first_key = |i: usize| step_keys(&templates[i].steps).remove(0);
+    let err = Engine::new(tmp.path().to_path_buf())
+        .with_jobs(4)
+        .run_with_state(&templates, &HashMap::new(), &state_path, false)
+        .unwrap_err();
+    assert!(err.to_string().contains("[broken] step 1"));
+    assert!(!tmp.path().join("after.txt").exists());
+    let state = PipelineState::load(&state_path).unwrap();
+    assert!(matches!(
+        state.get("broken", &first_key(0)),
+        StepState::Failed(_)
+    ));
+    assert_eq!(state.get("after", &first_key(2)), StepState::Pending);
+}
+
+#[test]
+fn test_engine_resume_after_template_update_runs_only_new_steps() {
+    let tmp = TempDir::new().unwrap();
+    let state_path = tmp.path().join(".tforge-state.json");
+    let manifest = |steps: &[&str]| -> TemplateManifest {
+        let steps: String = steps
+            .iter()
+            .map(|s| {
+                format!("\n[[steps]]\ntype = \"command\"\ncommand = \"echo {s} >> log.txt\"\n")
+            })
+            .collect();
+        toml::from_str(&format!(
+            "[template]\nname = \"app\"\ndescription = \"test\"\ncategory = \"test\"\nprovider = \"command\"\n{steps}"
+        ))
+        .unwrap()
+    };
+
+    let engine = Engine::new(tmp.path().to_path_buf());
+    engine
+        .run_with_state(
+            &[manifest(&["a", "b"])],
+            &HashMap::new(),
+            &state_path,
+            false,
+        )
+        .unwrap();
+
+    // An update inserts a step in front: positions shift, but completed steps stay completed.
+    let updated = [manifest(&["setup", "a", "b"])];
+    let changes = engine
+        .resume_changes(&updated, &HashMap::new(), &state_path)
+        .unwrap();
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].to_string(), "[app] step 1 is new and will run");
+
+    engine
+        .run_with_state(&updated, &HashMap::new(), &state_path, true)
+        .unwrap();
+    let log = std::fs::read_to_string(tmp.path().join("log.txt")).unwrap();
+    assert_eq!(log, "a\nb\nsetup\n");
+    assert!(
+        engine
+            .resume_changes(&updated, &HashMap::new(), &state_path)
+            .unwrap()
+            .is_empty()
+    );
+}
+
+#[test]
+fn test_engine_records_run_details() {
+    let tmp = TempDir::new().unwrap();
+    let state_path = tmp.path().join(".tforge-state.json");
+    let manifest: TemplateManifest = toml::from_str(
+        r#"
+[template]
+name = "app"
+description = "test"
+category = "test"
+provider = "command"
+[dependencies]
+
+[[steps]]
+type = "command"
+command = "echo created {{project_name}}"
+
+[[steps]]
+type = "command"
+command = "echo about to fail; echo quota exceeded >&2; exit 7"
+"#,
+    )
+    .unwrap();
+    let keys = step_keys(&manifest.steps);
+    let mut vars = HashMap::new();
+    vars.insert("project_name".into(), "demo".into());
+
+    Engine::new(tmp.path().to_path_buf())
+        .run_with_state(&[manifest], &vars, &state_path, false)
+        .unwrap_err();
+
+    let state = PipelineState::load(&state_path).unwrap();
+    let done = state.step_record("app", &keys[0]).unwrap();
+    assert_eq!(done.command.as_deref(), Some("echo created demo"));
+    assert_eq!(done.exit_code, Some(0));
+    assert_eq!(done.stdout_tail.as_deref(), Some("created demo"));
+    assert_eq!(
+        done.working_dir.as_deref(),
+        Some(tmp.path().display().to_string().as_str())
+    );
+    assert!(done.started_at.unwrap() <= done.finished_at.unwrap());
+    assert!(done.duration_ms.is_some());
+    assert_eq!(
+        done.tforge_version.as_deref(),
+        Some(env!("CARGO_PKG_VERSION"))
+    );
+
+    let failed = state.step_record("app", &keys[1]).unwrap();
+    assert_eq!(failed.status, StepStatus::Failed);
+    assert_eq!(failed.exit_code, Some(7));
+    assert_eq!(failed.attempts, 1);
+    assert_eq!(failed.stdout_tail.as_deref(), Some("about to fail"));
+    assert_eq!(failed.stderr_tail.as_deref(), Some("quota exceeded"));
+    assert!(failed.error.as_deref().unwrap().contains("command failed"));
+}
+
+#[test]
+fn test_engine_resume_reruns_steps_whose_inputs_drifted() {
+    let tmp = TempDir::new().unwrap();
+    let state_path = tmp.path().join(".tforge-state.json");
+    let manifest: TemplateManifest = toml::from_str(
+        r#"
+[template]
+name = "app"
+description = "test"
+category = "test"
+provider = "command"
+[dependencies]
+
+[[steps]]
+type = "command"
+command = "echo {{greeting}} >> log.txt"
+
+[[steps]]
+type = "command"
+command = "echo static >> log.txt"
+
+[[steps]]
+type = "command"
+command = "echo feature >> log.txt"
+condition = "feature == 'on'"
+"#,
+    )
+    .unwrap();
+    let templates = [manifest];
+    let mut vars = HashMap::new();
+    vars.insert("greeting".to_string(), "hello".into());
+    vars.insert("feature".to_string(), "off".into());
+
+    let engine = Engine::new(tmp.path().to_path_buf());
+    engine
+        .run_with_state(&templates, &vars, &state_path, false)
+        .unwrap();
+    assert!(
+        engine
+            .resume_changes(&templates, &vars, &state_path)
+            .unwrap()
+            .is_empty()
+    );
+
+    vars.insert("greeting".to_string(), "hi".into());
+    vars.insert("feature".to_string(), "on".into());
+    let changes: Vec<String> = engine
+        .resume_changes(&templates, &vars, &state_path)
+        .unwrap()
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+    assert_eq!(
+        changes,
+        vec![
+            "[app] step 1 inputs changed since the last run and will run again",
+            "[app] step 3 inputs changed since the last run and will run again",
+        ]
+    );
+    let plan = engine
+        .plan_with_state(&templates, &vars, &state_path)
+        .unwrap();
+    assert_eq!(plan[0].action, PlanAction::Run);
+    assert_eq!(
+        plan[0].reason.as_deref(),
+        Some("inputs changed since the last run")
+    );
+    assert_eq!(plan[1].action, PlanAction::Skip);
+
+    // Ignoring drift keeps completed steps completed.
+    Engine::new(tmp.path().to_path_buf())
+        .with_rerun_drifted(false)
+        .run_with_state(&templates, &vars, &state_path, true)
+        .unwrap();
+    let log = std::fs::read_to_string(tmp.path().join("log.txt")).unwrap();
+    assert_eq!(log, "hello\nstatic\n");
+
+    engine
+        .run_with_state(&templates, &vars, &state_path, true)
+        .unwrap();
+    let log = std::fs::read_to_string(tmp.path().join("log.txt")).unwrap();
+    assert_eq!(log, "hello\nstatic\nhi\nfeature\n");
+    assert!(
+        engine
+            .resume_changes(&templates, &vars, &state_path)
+            .unwrap()
+            .is_empty()
+    );
+}
+
+#[test]
+fn test_engine_only_runs_selected_templates() {
+    let tmp = TempDir::new().unwrap();
+    let state_path = tmp.path().join(".tforge-state.json");
+    let templates = vec![destroyable("base", &[]), destroyable("addon", &["base"])];
+    let vars = HashMap::new();
+
+    let engine = Engine::new(tmp.path().to_path_buf()).with_only(vec!["addon".to_string()]);
+    let plan = engine
+        .plan_with_state(&templates, &vars, &state_path)
+        .unwrap();
+    assert_eq!(plan.len(), 1);
+    assert_eq!(plan[0].template, "addon");
+
+    engine
+        .run_with_state(&templates, &vars, &state_path, true)
+        .unwrap();
+    let created = std::fs::read_to_string(tmp.path().join("created.txt")).unwrap();
+    assert_eq!(created, "addon\n");
+
+    let state = PipelineState::load(&state_path).unwrap();
+    assert_eq!(state.templates(), vec!["addon"]);
+}
+
+#[test]
+fn test_engine_does_not_run_interpolated_shell_syntax() {
+    let tmp = TempDir::new().unwrap();
+    let manifest: TemplateManifest = toml::from_str(
+        r#"
+[template]
+name = "quoting"
+description = "test"
+category = "test"
+provider = "command"
+[dependencies]
+
+[[steps]]
+type = "command"
+command = "echo {{name}} > shell.txt"
+
+[[steps]]
+type = "command"
+args = ["touch", "{{name}}"]
+"#,
+    )
+    .unwrap();
+
+    let mut vars = HashMap::new();
+    vars.insert("name".into(), "x; touch injected".into());
+
+    let engine = Engine::new(tmp.path().to_path_buf());
+    engine.run(&[manifest], &vars).unwrap();
+
+    assert_eq!(
+        std::fs::read_to_string(tmp.path().join("shell.txt")).unwrap(),
+        "x; touch injected\n"
+    );
+    assert!(tmp.path().join("x; touch injected").exists());
+    assert!(!tmp.path().join("injected").exists());
+}
+
+#[test]
+fn test_engine_resume_reruns_overlays_whose_partial_inputs_drifted() {
+    let template_dir = TempDir::new().unwrap();
+    std::fs::create_dir_all(template_dir.path().join("files")).unwrap();
+    std::fs::create_dir_all(template_dir.path().join("partials")).unwrap();
+    std::fs::write(
+        template_dir.path().join("files/config.txt"),
+        "{% include \"partials/port.txt\" %}",
+    )
+    .unwrap();
+    std::fs::write(
+        template_dir.path().join("partials/port.txt"),
+        "port={{port}}\n",
+    )
+    .unwrap();
+
+    let tmp = TempDir::new().unwrap();
+    let state_path = tmp.path().join(".tforge-state.json");
+    let mut manifest: TemplateManifest = toml::from_str(
+        r#"
+[template]
+name = "app"
+description = "test"
+category = "test"
+provider = "bundled"
+[dependencies]
+
+[[steps]]
+type = "bundled"
+source = "files/"
+"#,
+    )
+    .unwrap();
+    manifest.source = Some(TemplateSource::Local(template_dir.path().to_path_buf()));
+    let templates = [manifest];
+    let mut vars = HashMap::new();
+    vars.insert("port".to_string(), "3000".into());
+
+    let engine = Engine::new(tmp.path().to_path_buf());
+    engine
+        .run_with_state(&templates, &vars, &state_path, false)
+        .unwrap();
+    assert!(
+        engine
+            .resume_changes(&templates, &vars, &state_path)
+            .unwrap()
+            .is_empty()
+    );
+
+    vars.insert("port".to_string(), "8080".into());
+    let changes: Vec<String> = engine
+        .resume_changes(&templates, &vars, &state_path)
+        .unwrap()
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+    assert_eq!(
+        changes,
+        vec!["[app] step 1 inputs changed since the last run and will run again"]
+    );
+    engine
+        .run_with_state(&templates, &vars, &state_path, true)
+        .unwrap();
+    let config = std::fs::read_to_string(tmp.path().join("config.txt")).unwrap();
+    assert_eq!(config, "port=8080\n");
+}
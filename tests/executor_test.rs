@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 use tempfile::TempDir;
-use tforge::executor::{StepContext, execute_step};
+use tforge::executor::{CommandError, StepContext, execute_step, execute_with_retries};
+use tforge::source::TemplateSource;
 use tforge::types::StepDef;
 
 fn make_command_step(cmd: &str) -> StepDef {
@@ -19,6 +20,8 @@ fn test_execute_simple_command() {
     let ctx = StepContext {
         project_dir: tmp.path().to_path_buf(),
         vars: HashMap::new(),
+        source: None,
+        output: None,
     };
     let step = make_command_step("echo hello");
     let result = execute_step(&step, &ctx);
@@ -34,6 +37,8 @@ fn test_execute_command_with_working_dir() {
     let ctx = StepContext {
         project_dir: tmp.path().to_path_buf(),
         vars: HashMap::new(),
+        source: None,
+        output: None,
     };
     let step: StepDef = toml::from_str(
         r#"
@@ -53,6 +58,8 @@ fn test_execute_failing_command() {
     let ctx = StepContext {
         project_dir: tmp.path().to_path_buf(),
         vars: HashMap::new(),
+        source: None,
+        output: None,
     };
     let step = make_command_step("false");
     let result = execute_step(&step, &ctx);
@@ -67,6 +74,8 @@ fn test_execute_with_check_skips() {
     let ctx = StepContext {
         project_dir: tmp.path().to_path_buf(),
         vars: HashMap::new(),
+        source: None,
+        output: None,
     };
     let step: StepDef = toml::from_str(
         r#"
@@ -79,3 +88,228 @@ command = "echo should-be-skipped"
     let result = execute_step(&step, &ctx);
     assert!(result.is_ok());
 }
+
+#[test]
+fn test_bundled_overlay_renders_paths_and_contents() {
+    let template_dir = TempDir::new().unwrap();
+    let files = template_dir
+        .path()
+        .join("files/{{project_name}}-server/src");
+    std::fs::create_dir_all(&files).unwrap();
+    std::fs::write(files.join("main.rs"), "// {{project_name}} on {{port}}\n").unwrap();
+    std::fs::write(
+        template_dir.path().join("files/logo.bin"),
+        [0xff, 0xfe, 0x00],
+    )
+    .unwrap();
+
+    let tmp = TempDir::new().unwrap();
+    let mut vars = HashMap::new();
+    vars.insert("project_name".into(), "demo".into());
+    vars.insert("port".into(), "3000".into());
+    let ctx = StepContext {
+        project_dir: tmp.path().to_path_buf(),
+        vars,
+        source: Some(TemplateSource::Local(template_dir.path().to_path_buf())),
+        output: None,
+    };
+    let step: StepDef = toml::from_str(
+        r#"
+type = "bundled"
+action = "overlay"
+source = "files/"
+"#,
+    )
+    .unwrap();
+    execute_step(&step, &ctx).unwrap();
+
+    let main_rs = std::fs::read_to_string(tmp.path().join("demo-server/src/main.rs")).unwrap();
+    assert_eq!(main_rs, "// demo on 3000\n");
+    let logo = std::fs::read(tmp.path().join("logo.bin")).unwrap();
+    assert_eq!(logo, vec![0xff, 0xfe, 0x00]);
+}
+
+#[test]
+fn test_bundled_overlay_rejects_escaping_paths() {
+    let template_dir = TempDir::new().unwrap();
+    let files = template_dir.path().join("files");
+    std::fs::create_dir_all(&files).unwrap();
+    std::fs::write(files.join("{{target}}"), "x").unwrap();
+
+    let tmp = TempDir::new().unwrap();
+    let mut vars = HashMap::new();
+    vars.insert("target".into(), "../escape.txt".into());
+    let ctx = StepContext {
+        project_dir: tmp.path().to_path_buf(),
+        vars,
+        source: Some(TemplateSource::Local(template_dir.path().to_path_buf())),
+        output: None,
+    };
+    let step: StepDef = toml::from_str(
+        r#"
+type = "bundled"
+source = "files"
+"#,
+    )
+    .unwrap();
+    assert!(execute_step(&step, &ctx).is_err());
+}
+
+#[test]
+fn test_bundled_overlay_without_source_errors() {
+    let tmp = TempDir::new().unwrap();
+    let ctx = StepContext {
+        project_dir: tmp.path().to_path_buf(),
+        vars: HashMap::new(),
+        source: None,
+        output: None,
+    };
+    let step: StepDef = toml::from_str(
+        r#"
+type = "bundled"
+source = "files/"
+"#,
+    )
+    .unwrap();
+    assert!(execute_step(&step, &ctx).is_err());
+}
+
+fn plain_ctx(dir: &std::path::Path) -> StepContext {
+    StepContext {
+        project_dir: dir.to_path_buf(),
+        vars: HashMap::new(),
+        source: None,
+        output: None,
+    }
+}
+
+#[test]
+fn test_timeout_kills_process_group() {
+    let tmp = TempDir::new().unwrap();
+    let step: StepDef = toml::from_str(
+        r#"
+type = "command"
+command = "sleep 30 & echo started; wait"
+timeout = 1
+"#,
+    )
+    .unwrap();
+
+    let started = std::time::Instant::now();
+    let err = execute_step(&step, &plain_ctx(tmp.path()))
+        .err()
+        .expect("step should time out");
+    assert!(started.elapsed() < std::time::Duration::from_secs(10));
+    assert!(matches!(
+        err.downcast_ref::<CommandError>(),
+        Some(CommandError::TimedOut { .. })
+    ));
+    assert!(err.to_string().contains("timed out after 1s"));
+}
+
+#[test]
+fn test_retries_until_success() {
+    let tmp = TempDir::new().unwrap();
+    let step: StepDef = toml::from_str(
+        r#"
+type = "command"
+command = "n=$(cat n 2>/dev/null || echo 0); n=$((n+1)); echo $n > n; [ $n -ge 3 ]"
+retries = 3
+retry_delay = 0
+"#,
+    )
+    .unwrap();
+
+    let (_, attempts) = execute_with_retries(&step, &plain_ctx(tmp.path())).unwrap();
+    assert_eq!(attempts, 3);
+}
+
+#[test]
+fn test_retry_on_pattern_limits_retries() {
+    let tmp = TempDir::new().unwrap();
+    let step: StepDef = toml::from_str(
+        r#"
+type = "command"
+command = "echo x >> runs; echo 'permission denied' >&2; exit 1"
+retries = 3
+retry_delay = 0
+retry_on = "(?i)unavailable|deadline exceeded"
+"#,
+    )
+    .unwrap();
+
+    let failure = execute_with_retries(&step, &plain_ctx(tmp.path()))
+        .err()
+        .expect("step should fail");
+    assert_eq!(failure.attempts, 1);
+    let runs = std::fs::read_to_string(tmp.path().join("runs")).unwrap();
+    assert_eq!(runs.lines().count(), 1);
+    assert!(
+        failure
+            .into_error()
+            .to_string()
+            .contains("permission denied")
+    );
+}
+
+#[test]
+fn test_retries_exhausted_reports_attempts() {
+    let tmp = TempDir::new().unwrap();
+    let step: StepDef = toml::from_str(
+        r#"
+type = "command"
+command = "echo 'service unavailable' >&2; exit 1"
+retries = 2
+retry_delay = 0
+retry_on = "unavailable"
+"#,
+    )
+    .unwrap();
+
+    let failure = execute_with_retries(&step, &plain_ctx(tmp.path()))
+        .err()
+        .expect("step should fail");
+    assert_eq!(failure.attempts, 3);
+    let err = failure.into_error();
+    assert_eq!(err.to_string(), "gave up after 3 attempts");
+    assert!(format!("{err:#}").contains("service unavailable"));
+}
+
+#[test]
+fn test_timeout_applies_to_check() {
+    let tmp = TempDir::new().unwrap();
+    let step: StepDef = toml::from_str(
+        r#"
+type = "command"
+check = "sleep 30"
+command = "touch ran"
+timeout = 1
+"#,
+    )
+    .unwrap();
+
+    let started = std::time::Instant::now();
+    let err = execute_step(&step, &plain_ctx(tmp.path()))
+        .err()
+        .expect("check should time out");
+    assert!(started.elapsed() < std::time::Duration::from_secs(10));
+    assert!(matches!(
+        err.downcast_ref::<CommandError>(),
+        Some(CommandError::TimedOut { .. })
+    ));
+    assert_eq!(err.to_string(), "check timed out after 1s: sleep 30");
+    assert!(!tmp.path().join("ran").exists());
+}
+
+#[test]
+fn test_invalid_retry_on_is_rejected_when_parsed() {
+    let err = toml::from_str::<StepDef>(
+        "type = \"command\"\ncommand = \"true\"\nretry_on = \"(unclosed\"\n",
+    )
+    .unwrap_err()
+    .to_string();
+    assert!(
+        err.contains("invalid retry_on pattern '(unclosed'"),
+        "{err}"
+    );
+}
@@ -0,0 +1 @@
+echo "{{project_name}}" > setup.txt
@@ -0,0 +1,233 @@
+use sha2::{Digest, Sha256};
+use std::io::{Read, Write};
+use std::path::Path;
+use tempfile::TempDir;
+use tforge::index::{IndexSource, RegistryIndex, fetch_index, install_in};
+use tforge::remote::{RefKind, list_cached_templates_in, load_pin};
+
+const MANIFEST: &str = r#"
+[template]
+name = "cloudrun"
+version = "1.1.0"
+description = "Cloud Run service"
+category = "cloud"
+provider = "command"
+
+[[steps]]
+type = "command"
+command = "echo cloudrun"
+"#;
+
+/// A `.tar.gz` with the template under a `cloudrun-1.1.0/` top-level directory.
+fn tarball(manifest: &str) -> Vec<u8> {
+    let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+        Vec::new(),
+        flate2::Compression::default(),
+    ));
+    for (path, data) in [
+        ("cloudrun-1.1.0/template.toml", manifest),
+        ("cloudrun-1.1.0/files/main.py", "print('hi')\n"),
+    ] {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, path, data.as_bytes())
+            .unwrap();
+    }
+    builder.into_inner().unwrap().finish().unwrap()
+}
+
+fn sha256(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+fn file_url(path: &Path) -> String {
+    format!("file://{}", path.display())
+}
+
+#[test]
+fn test_parse_toml_and_json_indexes() {
+    let toml_index = RegistryIndex::parse(
+        r#"
+[[template]]
+name = "cloudrun"
+version = "1.1.0"
+description = "Cloud Run service"
+category = "cloud"
+tarball = "cloudrun-1.1.0.tar.gz"
+sha256 = "abc"
+
+[[template]]
+name = "cloudrun"
+version = "1.0.0"
+git = "https://example.com/templates.git"
+ref = "v1.0.0"
+path = "gcp/cloudrun"
+"#,
+    )
+    .unwrap();
+    assert_eq!(toml_index.templates.len(), 2);
+    assert_eq!(
+        toml_index.templates[1].source,
+        IndexSource::Git {
+            git: "https://example.com/templates.git".into(),
+            git_ref: Some("v1.0.0".into()),
+            path: Some("gcp/cloudrun".into()),
+        }
+    );
+    assert_eq!(
+        toml_index.find("cloudrun").unwrap().version,
+        Some(semver::Version::new(1, 1, 0))
+    );
+
+    let json_index = RegistryIndex::parse(
+        r#"{"templates": [{"name": "cloudrun", "description": "Cloud Run service",
+            "category": "cloud", "version": "1.1.0",
+            "tarball": "cloudrun-1.1.0.tar.gz", "sha256": "abc"}]}"#,
+    )
+    .unwrap();
+    assert_eq!(json_index.templates[0], toml_index.templates[0]);
+
+    assert!(RegistryIndex::parse("[[template]]\nname = \"no-source\"\n").is_err());
+}
+
+#[tokio::test]
+async fn test_install_tarball_from_file_index() {
+    let tmp = TempDir::new().unwrap();
+    let data = tarball(MANIFEST);
+    std::fs::write(tmp.path().join("cloudrun-1.1.0.tar.gz"), &data).unwrap();
+    std::fs::write(
+        tmp.path().join("index.toml"),
+        format!(
+            "[[template]]\nname = \"cloudrun\"\nversion = \"1.1.0\"\ntarball = \"cloudrun-1.1.0.tar.gz\"\nsha256 = \"{}\"\n",
+            sha256(&data)
+        ),
+    )
+    .unwrap();
+
+    let index = fetch_index(&file_url(&tmp.path().join("index.toml")))
+        .await
+        .unwrap();
+    let entry = index.find("cloudrun").unwrap();
+    let IndexSource::Tarball { tarball, .. } = &entry.source else {
+        panic!("expected a tarball entry");
+    };
+    assert_eq!(
+        tarball,
+        &file_url(&tmp.path().join("cloudrun-1.1.0.tar.gz"))
+    );
+
+    let cache = tmp.path().join("cache");
+    install_in(&cache, entry).await.unwrap();
+    assert!(cache.join("cloudrun/files/main.py").exists());
+    let pin = load_pin(&cache.join("cloudrun")).unwrap().unwrap();
+    assert_eq!(pin.ref_kind, RefKind::Tarball);
+    assert_eq!(pin.sha256, Some(sha256(&data)));
+    let templates = list_cached_templates_in(&cache).unwrap();
+    assert_eq!(templates.len(), 1);
+    assert_eq!(templates[0].template.name, "cloudrun");
+    assert!(!cache.join(".cloudrun.download").exists());
+
+    let err = install_in(&cache, entry).await.unwrap_err();
+    assert!(err.to_string().contains("already installed"));
+}
+
+#[tokio::test]
+async fn test_install_rejects_checksum_mismatch_and_wrong_name() {
+    let tmp = TempDir::new().unwrap();
+    let data = tarball(MANIFEST);
+    std::fs::write(tmp.path().join("cloudrun.tar.gz"), &data).unwrap();
+    let cache = tmp.path().join("cache");
+
+    let mut index = RegistryIndex::parse(&format!(
+        "[[template]]\nname = \"cloudrun\"\ntarball = \"{}\"\nsha256 = \"{}\"\n",
+        file_url(&tmp.path().join("cloudrun.tar.gz")),
+        "0".repeat(64)
+    ))
+    .unwrap();
+    let err = install_in(&cache, &index.templates[0]).await.unwrap_err();
+    assert!(err.to_string().starts_with("checksum mismatch for file://"));
+    assert!(!cache.join("cloudrun").exists());
+
+    index.templates[0].name = "other".into();
+    if let IndexSource::Tarball { sha256: sum, .. } = &mut index.templates[0].source {
+        *sum = sha256(&data);
+    }
+    let err = install_in(&cache, &index.templates[0]).await.unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "index entry 'other' points to a template named 'cloudrun'"
+    );
+    assert!(!cache.join("other").exists());
+}
+
+#[tokio::test]
+async fn test_rejects_entry_names_outside_the_cache() {
+    for name in [
+        "../evil",
+        "/tmp/evil",
+        "nested/evil",
+        "..",
+        "evil\\..\\x",
+        "",
+    ] {
+        let err = RegistryIndex::parse(&format!(
+            "[[template]]\nname = {name:?}\ngit = \"https://example.com/t.git\"\n"
+        ))
+        .unwrap_err();
+        assert!(
+            err.to_string().starts_with("invalid template name"),
+            "{name}: {err}"
+        );
+    }
+
+    let tmp = TempDir::new().unwrap();
+    let data = tarball(MANIFEST);
+    std::fs::write(tmp.path().join("cloudrun.tar.gz"), &data).unwrap();
+    let mut index = RegistryIndex::parse(&format!(
+        "[[template]]\nname = \"cloudrun\"\ntarball = \"{}\"\nsha256 = \"{}\"\n",
+        file_url(&tmp.path().join("cloudrun.tar.gz")),
+        sha256(&data)
+    ))
+    .unwrap();
+    index.templates[0].name = "../cloudrun".into();
+    let cache = tmp.path().join("cache");
+    let err = install_in(&cache, &index.templates[0]).await.unwrap_err();
+    assert!(
+        err.to_string()
+            .starts_with("invalid template name '../cloudrun'")
+    );
+    assert!(!tmp.path().join("cloudrun").exists());
+}
+
+#[tokio::test]
+async fn test_fetch_index_over_http() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let body = r#"{"templates": [{"name": "cloudrun", "tarball": "pkg/cloudrun.tar.gz", "sha256": "abc"}]}"#;
+    let server = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = [0u8; 1024];
+        let _ = stream.read(&mut request).unwrap();
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )
+        .unwrap();
+    });
+
+    let index = fetch_index(&format!("http://{addr}/registry/index.json"))
+        .await
+        .unwrap();
+    server.join().unwrap();
+    let IndexSource::Tarball { tarball, .. } = &index.templates[0].source else {
+        panic!("expected a tarball entry");
+    };
+    assert_eq!(
+        tarball,
+        &format!("http://{addr}/registry/pkg/cloudrun.tar.gz")
+    );
+}
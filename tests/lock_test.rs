@@ -0,0 +1,95 @@
+use tempfile::TempDir;
+use tforge::lock::Lockfile;
+use tforge::registry::Registry;
+
+fn write_template(root: &std::path::Path, name: &str, version: &str, command: &str) {
+    let dir = root.join(name);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("template.toml"),
+        format!(
+            r#"
+[template]
+name = "{name}"
+version = "{version}"
+description = "test"
+category = "test"
+provider = "command"
+
+[[steps]]
+type = "command"
+command = "{command}"
+"#
+        ),
+    )
+    .unwrap();
+}
+
+fn lock_of(root: &std::path::Path) -> Lockfile {
+    let registry = Registry::from_directory(root).unwrap();
+    Lockfile::from_templates(registry.templates()).unwrap()
+}
+
+#[test]
+fn test_lockfile_records_version_source_and_hash() {
+    let tmp = TempDir::new().unwrap();
+    write_template(tmp.path(), "app", "1.2.0", "echo app");
+
+    let lock = lock_of(tmp.path());
+    let app = lock.get("app").unwrap();
+    assert_eq!(app.version, Some(semver::Version::new(1, 2, 0)));
+    assert_eq!(app.source.as_deref(), Some("local"));
+    assert_eq!(app.commit, None);
+    assert_eq!(app.hash.as_ref().unwrap().len(), 64);
+
+    let path = tmp.path().join("tforge.lock");
+    lock.save(&path).unwrap();
+    assert_eq!(Lockfile::load(&path).unwrap(), Some(lock));
+    assert_eq!(
+        Lockfile::load(&tmp.path().join("missing.lock")).unwrap(),
+        None
+    );
+}
+
+#[test]
+fn test_lockfile_mismatches_report_changed_and_unlocked_templates() {
+    let tmp = TempDir::new().unwrap();
+    write_template(tmp.path(), "app", "1.0.0", "echo app");
+    write_template(tmp.path(), "db", "1.0.0", "echo db");
+    let mut locked = lock_of(tmp.path());
+    assert!(locked.mismatches(&lock_of(tmp.path())).is_empty());
+
+    // Same version, different files.
+    write_template(tmp.path(), "app", "1.0.0", "echo app v2");
+    let mismatches = locked.mismatches(&lock_of(tmp.path()));
+    assert_eq!(mismatches.len(), 1);
+    assert_eq!(mismatches[0].name, "app");
+    assert!(mismatches[0].to_string().starts_with("app: locked 1.0.0 ("));
+
+    // Companion files count too.
+    write_template(tmp.path(), "app", "1.0.0", "echo app");
+    std::fs::write(tmp.path().join("app/setup.sh"), "echo hi").unwrap();
+    assert_eq!(locked.mismatches(&lock_of(tmp.path())).len(), 1);
+    std::fs::remove_file(tmp.path().join("app/setup.sh")).unwrap();
+
+    locked.templates.retain(|t| t.name != "db");
+    let mismatches = locked.mismatches(&lock_of(tmp.path()));
+    assert_eq!(mismatches.len(), 1);
+    assert_eq!(mismatches[0].locked, None);
+    assert!(
+        mismatches[0]
+            .to_string()
+            .starts_with("db: not in tforge.lock, now 1.0.0 (")
+    );
+}
+
+#[test]
+fn test_embedded_templates_are_versioned() {
+    let registry = Registry::from_embedded().unwrap();
+    let lock = Lockfile::from_templates(registry.templates()).unwrap();
+    for locked in &lock.templates {
+        assert!(locked.version.is_some(), "{} has no version", locked.name);
+        assert_eq!(locked.source.as_deref(), Some("embedded"));
+        assert!(locked.hash.is_some());
+    }
+}
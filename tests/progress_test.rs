@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tempfile::TempDir;
+use tforge::engine::Engine;
+use tforge::executor::{StepContext, StepResult, execute_step};
+use tforge::progress::{OutputStream, Reporter, RunLog, StepInfo, StepOutcome, StepOutput};
+use tforge::types::{StepDef, TemplateManifest};
+
+#[derive(Default)]
+struct Recorder {
+    events: Mutex<Vec<String>>,
+}
+
+impl Reporter for Recorder {
+    fn step_started(&self, step: &StepInfo) {
+        self.events.lock().unwrap().push(format!("start {step}"));
+    }
+
+    fn output_line(&self, step: &StepInfo, stream: OutputStream, line: &str) {
+        self.events
+            .lock()
+            .unwrap()
+            .push(format!("{} {stream:?} {line}", step.prefix()));
+    }
+
+    fn step_finished(&self, step: &StepInfo, outcome: &StepOutcome, _elapsed: Duration) {
+        self.events
+            .lock()
+            .unwrap()
+            .push(format!("finish {} {outcome:?}", step.prefix()));
+    }
+}
+
+fn step_info() -> StepInfo {
+    StepInfo {
+        template: "app".into(),
+        step: 1,
+        total: 1,
+        step_type: "command".into(),
+        summary: String::new(),
+    }
+}
+
+#[test]
+fn test_command_output_is_streamed_and_captured() {
+    let tmp = TempDir::new().unwrap();
+    let recorder = Arc::new(Recorder::default());
+    let ctx = StepContext {
+        project_dir: tmp.path().to_path_buf(),
+        vars: HashMap::new(),
+        source: None,
+        output: Some(StepOutput {
+            reporter: recorder.clone(),
+            step: step_info(),
+        }),
+    };
+    let step: StepDef = toml::from_str(
+        r#"
+type = "command"
+command = "echo one; echo two; echo oops >&2"
+"#,
+    )
+    .unwrap();
+
+    let result = execute_step(&step, &ctx).unwrap();
+    let StepResult::Executed { stdout } = result else {
+        panic!("expected the step to run");
+    };
+    assert_eq!(stdout, "one\ntwo\n");
+
+    let events = recorder.events.lock().unwrap();
+    assert!(events.contains(&"[app:1] Stdout one".to_string()));
+    assert!(events.contains(&"[app:1] Stdout two".to_string()));
+    assert!(events.contains(&"[app:1] Stderr oops".to_string()));
+}
+
+#[test]
+fn test_engine_reports_step_progress() {
+    let tmp = TempDir::new().unwrap();
+    let manifest: TemplateManifest = toml::from_str(
+        r#"
+[template]
+name = "app"
+description = "test"
+category = "test"
+provider = "command"
+[dependencies]
+
+[[steps]]
+type = "command"
+command = "echo built"
+
+[[steps]]
+type = "command"
+command = "echo never"
+condition = "enabled"
+
+[[steps]]
+type = "command"
+command = "echo unused"
+check = "true"
+"#,
+    )
+    .unwrap();
+    let mut vars = HashMap::new();
+    vars.insert("enabled".into(), "false".into());
+
+    let recorder = Arc::new(Recorder::default());
+    Engine::new(tmp.path().to_path_buf())
+        .with_reporter(recorder.clone())
+        .run(&[manifest], &vars)
+        .unwrap();
+
+    let events = recorder.events.lock().unwrap();
+    assert_eq!(
+        *events,
+        vec![
+            "start [app] step 1/3 (command): echo built",
+            "[app:1] Stdout built",
+            "finish [app:1] Done",
+            "finish [app:2] Skipped(\"condition false\")",
+            "start [app] step 3/3 (command): echo unused",
+            "finish [app:3] Skipped(\"check passed\")",
+        ]
+    );
+}
+
+#[test]
+fn test_run_log_records_output() {
+    let tmp = TempDir::new().unwrap();
+    let log = RunLog::create(tmp.path()).unwrap();
+    let step = step_info();
+    log.step_started(&step);
+    log.output_line(&step, OutputStream::Stdout, "hello");
+    log.output_line(&step, OutputStream::Stderr, "warning");
+    log.step_finished(&step, &StepOutcome::Done, Duration::from_millis(1500));
+
+    assert!(log.path().starts_with(tmp.path().join(".tforge/logs")));
+    let content = std::fs::read_to_string(log.path()).unwrap();
+    assert_eq!(
+        content,
+        "==> [app] step 1/1 (command)\n\
+         [app:1] hello\n\
+         [app:1] stderr: warning\n\
+         <== [app] step 1/1 (command): done in 1.5s\n"
+    );
+}
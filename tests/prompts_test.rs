@@ -1,5 +1,8 @@
 use std::collections::HashMap;
-use tforge::prompts::{parameter_keys_in_prompt_order, should_prompt_parameter};
+use tforge::prompts::{
+    apply_parameter_defaults, parameter_keys_in_prompt_order, parameter_prompt_order,
+    should_prompt_parameter,
+};
 use tforge::types::TemplateManifest;
 
 #[test]
@@ -27,11 +30,113 @@ command = "echo ok"
     .unwrap();
 
     assert_eq!(
-        parameter_keys_in_prompt_order(&manifest),
+        parameter_keys_in_prompt_order(&manifest).unwrap(),
         vec!["alpha", "beta", "zeta"]
     );
 }
 
+#[test]
+fn test_parameter_prompt_order_follows_dependencies() {
+    let project: TemplateManifest = toml::from_str(
+        r#"
+[template]
+name = "project"
+description = "project"
+category = "test"
+provider = "command"
+
+[parameters]
+alpha = { type = "string", prompt = "a", default = "{{zeta}}-a" }
+beta = { type = "bool", prompt = "b", when = "gamma == 'yes'" }
+gamma = { type = "string", prompt = "g", default = "{{region}}" }
+zeta = { type = "string", prompt = "z" }
+"#,
+    )
+    .unwrap();
+    let network: TemplateManifest = toml::from_str(
+        r#"
+[template]
+name = "network"
+description = "network"
+category = "test"
+provider = "command"
+
+[parameters]
+region = { type = "string", prompt = "r", default = "us-central1" }
+"#,
+    )
+    .unwrap();
+
+    let templates = [project, network];
+    let order: Vec<String> = parameter_prompt_order(&templates)
+        .unwrap()
+        .into_iter()
+        .map(|(tmpl, key)| format!("{}.{key}", tmpl.template.name))
+        .collect();
+    assert_eq!(
+        order,
+        vec![
+            "project.zeta",
+            "project.alpha",
+            "network.region",
+            "project.gamma",
+            "project.beta"
+        ]
+    );
+}
+
+#[test]
+fn test_parameter_prompt_order_uses_order_and_groups() {
+    let manifest: TemplateManifest = toml::from_str(
+        r#"
+[template]
+name = "wizard"
+description = "wizard"
+category = "test"
+provider = "command"
+
+[parameters]
+alpha = { type = "string", prompt = "a" }
+cpu = { type = "string", prompt = "c", group = "Sizing" }
+deploy = { type = "bool", prompt = "d", order = 3 }
+memory = { type = "string", prompt = "m", group = "Sizing", order = 2 }
+region = { type = "string", prompt = "r", order = 1 }
+zone = { type = "string", prompt = "z", default = "{{region}}-a", order = 0 }
+"#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        parameter_keys_in_prompt_order(&manifest).unwrap(),
+        vec!["region", "zone", "memory", "cpu", "deploy", "alpha"]
+    );
+}
+
+#[test]
+fn test_parameter_prompt_order_rejects_cycles() {
+    let manifest: TemplateManifest = toml::from_str(
+        r#"
+[template]
+name = "cyclic"
+description = "cyclic"
+category = "test"
+provider = "command"
+
+[parameters]
+first = { type = "string", prompt = "1", default = "{{second}}" }
+second = { type = "string", prompt = "2", when = "first == 'x'" }
+third = { type = "string", prompt = "3" }
+"#,
+    )
+    .unwrap();
+
+    let err = parameter_keys_in_prompt_order(&manifest).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "parameter defaults and `when` conditions depend on each other in a cycle: cyclic.first, cyclic.second"
+    );
+}
+
 #[test]
 fn test_prompt_condition_respects_current_vars() {
     let manifest: TemplateManifest = toml::from_str(
@@ -58,7 +163,7 @@ command = "echo ok"
     let promote_traffic = manifest.parameters.get("promote_traffic").unwrap();
 
     let mut vars = HashMap::new();
-    vars.insert("deploy_now".to_string(), "false".to_string());
+    vars.insert("deploy_now".to_string(), "false".into());
     assert!(
         !should_prompt_parameter(
             &manifest.template.name,
@@ -69,7 +174,7 @@ command = "echo ok"
         .unwrap()
     );
 
-    vars.insert("deploy_now".to_string(), "true".to_string());
+    vars.insert("deploy_now".to_string(), "true".into());
     assert!(
         should_prompt_parameter(
             &manifest.template.name,
@@ -116,3 +221,142 @@ command = "echo ok"
     assert!(err_msg.contains("template 'gcp-appengine' parameter 'runtime_standard'"));
     assert!(err_msg.contains("variable 'appengine_environment' not found"));
 }
+
+#[test]
+fn test_apply_parameter_defaults_fills_gaps_and_honours_when() {
+    let manifest: TemplateManifest = toml::from_str(
+        r#"
+[template]
+name = "gcp-appengine"
+description = "App Engine"
+category = "cloud"
+provider = "command"
+
+[dependencies]
+
+[parameters]
+appengine_environment = { type = "select", prompt = "Environment", options = ["standard", "flexible"], default = "standard" }
+runtime_standard = { type = "select", prompt = "Runtime", options = ["python312"], default = "python312", when = "appengine_environment == 'standard'" }
+flex_cpu = { type = "string", prompt = "CPU", when = "appengine_environment == 'flexible'" }
+deploy_now = { type = "bool", prompt = "Deploy now?", default = false }
+platforms = { type = "multi-select", prompt = "Platforms", options = ["ios", "android"], default = ["ios", "android"] }
+service = { type = "string", prompt = "Service", default = "default" }
+"#,
+    )
+    .unwrap();
+
+    let mut vars = HashMap::new();
+    vars.insert("service".to_string(), "api".into());
+    apply_parameter_defaults(&[manifest], &mut vars).unwrap();
+
+    assert_eq!(vars.get("appengine_environment").unwrap(), "standard");
+    assert_eq!(vars.get("runtime_standard").unwrap(), "python312");
+    assert_eq!(vars.get("deploy_now").unwrap(), "false");
+    assert_eq!(vars.get("platforms").unwrap(), "ios,android");
+    assert_eq!(vars.get("service").unwrap(), "api");
+    assert!(!vars.contains_key("flex_cpu"));
+}
+
+#[test]
+fn test_apply_parameter_defaults_errors_on_missing_required_value() {
+    let manifest: TemplateManifest = toml::from_str(
+        r#"
+[template]
+name = "gcp-project"
+description = "GCP"
+category = "cloud"
+provider = "command"
+
+[dependencies]
+
+[parameters]
+gcp_project_id = { type = "string", prompt = "GCP Project ID" }
+"#,
+    )
+    .unwrap();
+
+    let err = apply_parameter_defaults(std::slice::from_ref(&manifest), &mut HashMap::new())
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("template 'gcp-project' requires parameter 'gcp_project_id'"));
+    assert!(err.contains("--set gcp_project_id=<value>"));
+
+    let mut vars = HashMap::new();
+    vars.insert("gcp_project_id".to_string(), "demo-dev".into());
+    apply_parameter_defaults(&[manifest], &mut vars).unwrap();
+}
+
+#[test]
+fn test_apply_parameter_defaults_validates_preset_values() {
+    let manifest: TemplateManifest = toml::from_str(
+        r#"
+[template]
+name = "gcp-project"
+description = "GCP"
+category = "cloud"
+provider = "command"
+
+[parameters]
+gcp_project_id = { type = "string", prompt = "GCP Project ID", pattern = "[a-z][a-z0-9-]*", min_length = 6, error_message = "use 6+ lowercase letters, digits or hyphens" }
+"#,
+    )
+    .unwrap();
+
+    let mut vars = HashMap::new();
+    vars.insert("gcp_project_id".to_string(), "Demo".into());
+    let err = apply_parameter_defaults(std::slice::from_ref(&manifest), &mut vars).unwrap_err();
+    assert_eq!(
+        format!("{err:#}"),
+        "invalid value for template 'gcp-project' parameter 'gcp_project_id': use 6+ lowercase letters, digits or hyphens"
+    );
+
+    vars.insert("gcp_project_id".to_string(), "demo-dev".into());
+    apply_parameter_defaults(&[manifest], &mut vars).unwrap();
+}
+
+#[test]
+fn test_apply_parameter_defaults_renders_templated_and_computed_defaults() {
+    let manifest: TemplateManifest = toml::from_str(
+        r#"
+[template]
+name = "gcp-cloudsql"
+description = "Cloud SQL"
+category = "database"
+provider = "command"
+
+[parameters]
+db_instance_name = { type = "string", prompt = "Instance name", default = "{{project_name}}-db", max_length = 20 }
+connection_name = { type = "string", computed = true, default = "{{gcp_project_id}}:{{region}}:{{db_instance_name}}" }
+region = { type = "string", prompt = "Region", default = "us-central1" }
+"#,
+    )
+    .unwrap();
+
+    let mut vars = HashMap::new();
+    vars.insert("project_name".to_string(), "demo".into());
+    vars.insert("gcp_project_id".to_string(), "demo-dev".into());
+    vars.insert("connection_name".to_string(), "stale".into());
+    apply_parameter_defaults(std::slice::from_ref(&manifest), &mut vars).unwrap();
+    assert_eq!(vars.get("db_instance_name").unwrap(), "demo-db");
+    assert_eq!(
+        vars.get("connection_name").unwrap(),
+        "demo-dev:us-central1:demo-db"
+    );
+
+    let mut vars = HashMap::new();
+    vars.insert("project_name".to_string(), "a-much-longer-project".into());
+    vars.insert("gcp_project_id".to_string(), "demo-dev".into());
+    let err = apply_parameter_defaults(std::slice::from_ref(&manifest), &mut vars).unwrap_err();
+    assert_eq!(
+        format!("{err:#}"),
+        "invalid default for template 'gcp-cloudsql' parameter 'db_instance_name': must have at most 20 characters"
+    );
+
+    let mut vars = HashMap::new();
+    vars.insert("project_name".to_string(), "demo".into());
+    let err = apply_parameter_defaults(&[manifest], &mut vars).unwrap_err();
+    assert!(
+        format!("{err:#}")
+            .starts_with("invalid default for template 'gcp-cloudsql' parameter 'connection_name'")
+    );
+}
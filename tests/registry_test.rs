@@ -1,11 +1,15 @@
+use std::collections::HashMap;
 use std::path::PathBuf;
+use tempfile::TempDir;
 use tforge::registry::Registry;
+use tforge::renderer::Renderer;
+use tforge::source::TemplateSource;
 
 #[test]
 fn test_load_templates_from_directory() {
     let fixtures = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/templates");
     let registry = Registry::from_directory(&fixtures).unwrap();
-    assert!(registry.templates().len() >= 1);
+    assert!(!registry.templates().is_empty());
 }
 
 #[test]
@@ -43,7 +47,8 @@ fn test_embedded_gcp_appengine_template_contract() {
         template
             .dependencies
             .requires_templates
-            .contains(&"gcp-project".to_string())
+            .iter()
+            .any(|req| req.name == "gcp-project")
     );
 
     for key in [
@@ -89,6 +94,19 @@ fn test_embedded_gcp_appengine_template_contract() {
         Some("appengine_environment == 'flexible'")
     );
 
+    let order = tforge::prompts::parameter_keys_in_prompt_order(template).unwrap();
+    assert_eq!(
+        &order[..5],
+        [
+            "deploy_target",
+            "deploy_target_path",
+            "appengine_environment",
+            "service",
+            "version"
+        ]
+    );
+    assert_eq!(&order[order.len() - 2..], ["deploy_now", "promote_traffic"]);
+
     assert!(
         template
             .steps
@@ -105,6 +123,79 @@ fn test_embedded_gcp_appengine_template_contract() {
         template
             .steps
             .iter()
-            .any(|s| s.condition.as_deref() == Some("deploy_now == 'true'"))
+            .any(|s| s.condition.as_deref() == Some("deploy_now"))
+    );
+}
+
+#[test]
+fn test_directory_templates_expose_companion_files() {
+    let fixtures = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/templates");
+    let registry = Registry::from_directory(&fixtures).unwrap();
+
+    assert_eq!(
+        registry.source("test-app"),
+        Some(&TemplateSource::Local(fixtures.join("test-app")))
+    );
+    assert_eq!(
+        registry.list_files("test-app", "scripts").unwrap(),
+        vec!["setup.sh"]
     );
+    let greeting = registry
+        .read_file("test-app", "partials/greeting.txt")
+        .unwrap();
+    assert_eq!(greeting, b"Hello {{project_name}}!\n");
+    assert!(registry.read_file("test-app", "../escape.txt").is_err());
+}
+
+#[test]
+fn test_embedded_gcp_appengine_partial_resolves() {
+    let registry = Registry::from_embedded().unwrap();
+    let source = registry.source("gcp-appengine").unwrap().clone();
+    assert!(matches!(source, TemplateSource::Embedded { .. }));
+    assert!(source.exists("partials/target_dir.sh"));
+
+    let mut vars = HashMap::new();
+    vars.insert("project_name".to_string(), "my demo".into());
+    vars.insert("deploy_target".to_string(), "axum-server".into());
+    let renderer = Renderer::with_source(source);
+    let rendered = renderer
+        .render_shell("{% include \"partials/target_dir.sh\" %}", &vars)
+        .unwrap();
+    assert!(rendered.contains("TARGET_DIR='my demo'-server"));
+
+    // `deploy_target_path` is only set for `custom-path`.
+    vars.insert("deploy_target".to_string(), "project-root".into());
+    let rendered = renderer
+        .render_shell("{% include \"partials/target_dir.sh\" %}", &vars)
+        .unwrap();
+    assert_eq!(rendered.trim(), "TARGET_DIR=\".\"");
+}
+
+#[test]
+fn test_cache_skips_entries_that_fail_to_load() {
+    let cache = TempDir::new().unwrap();
+    let write = |path: &str, content: &str| {
+        let path = cache.path().join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    };
+    write(
+        "good/template.toml",
+        "[template]\nname = \"good\"\ndescription = \"d\"\ncategory = \"c\"\nprovider = \"git\"\n",
+    );
+    write("broken/template.toml", "[template\n");
+    write("bad-pin/.tforge-pin.toml", "not a pin");
+    write(
+        "missing-path/.tforge-pin.toml",
+        "url = \"https://example.com/repo.git\"\npath = \"templates/api\"\nref_kind = \"default-branch\"\n",
+    );
+    std::fs::create_dir_all(cache.path().join("not-a-template")).unwrap();
+
+    let registry = Registry::from_cache_dir_in(cache.path()).unwrap();
+    let names: Vec<&str> = registry
+        .templates()
+        .iter()
+        .map(|t| t.template.name.as_str())
+        .collect();
+    assert_eq!(names, vec!["good"]);
 }
@@ -1,7 +1,11 @@
 use std::fs;
+use std::path::Path;
 use tempfile::TempDir;
 use tforge::registry::Registry;
-use tforge::remote::{cache_dir, list_cached_templates_in, search_templates};
+use tforge::remote::{
+    PIN_FILE, RefKind, add_template_in, cache_dir, head_commit, head_commit_in,
+    list_cached_templates_in, load_pin, search_templates, update_templates_in,
+};
 
 #[test]
 fn test_cache_dir_path() {
@@ -74,3 +78,143 @@ fn test_search_templates_no_match() {
     let results = search_templates(&registry, "zzz_nonexistent_zzz");
     assert!(results.is_empty());
 }
+
+fn git(dir: &Path, args: &[&str]) {
+    let status = std::process::Command::new("git")
+        .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .unwrap();
+    assert!(status.status.success(), "git {args:?} failed: {status:?}");
+}
+
+fn write_manifest(dir: &Path, name: &str, description: &str) {
+    fs::create_dir_all(dir).unwrap();
+    fs::write(
+        dir.join("template.toml"),
+        format!(
+            r#"
+[template]
+name = "{name}"
+description = "{description}"
+category = "test"
+provider = "command"
+
+[[steps]]
+type = "command"
+command = "echo {name}"
+"#
+        ),
+    )
+    .unwrap();
+}
+
+/// A repository hosting two templates under `templates/`, tagged `v1` before a second
+/// commit on `main`.
+fn monorepo(root: &Path) -> String {
+    fs::create_dir_all(root).unwrap();
+    git(root, &["init", "-q", "-b", "main"]);
+    write_manifest(&root.join("templates/api"), "api", "v1");
+    write_manifest(&root.join("templates/web"), "web", "v1");
+    git(root, &["add", "-A"]);
+    git(root, &["commit", "-q", "-m", "v1"]);
+    git(root, &["tag", "v1"]);
+    format!("file://{}", root.display())
+}
+
+fn description(cache: &Path, name: &str) -> String {
+    list_cached_templates_in(cache)
+        .unwrap()
+        .into_iter()
+        .find(|t| t.template.name == name)
+        .unwrap()
+        .template
+        .description
+}
+
+#[test]
+fn test_add_template_from_subdirectory_at_ref() {
+    let tmp = TempDir::new().unwrap();
+    let url = monorepo(&tmp.path().join("repo"));
+    let cache = tmp.path().join("cache");
+
+    let name = add_template_in(&cache, &url, Some("v1"), Some("templates/api")).unwrap();
+    assert_eq!(name, "api");
+    let pin = load_pin(&cache.join("api")).unwrap().unwrap();
+    assert_eq!(pin.git_ref.as_deref(), Some("v1"));
+    assert_eq!(pin.path.as_deref(), Some("templates/api"));
+    assert_eq!(pin.ref_kind, RefKind::Tag);
+    assert!(!pin.is_floating());
+
+    let templates = list_cached_templates_in(&cache).unwrap();
+    assert_eq!(templates.len(), 1);
+    assert_eq!(templates[0].template.name, "api");
+    let root = cache.join("api/templates/api");
+    assert_eq!(
+        templates[0].source,
+        Some(tforge::source::TemplateSource::Cached(root.clone()))
+    );
+    assert_eq!(head_commit_in(&cache, &root).unwrap().len(), 40);
+    assert!(cache.join("api").join(PIN_FILE).exists());
+
+    let err = add_template_in(&cache, &url, None, Some("templates/missing")).unwrap_err();
+    assert!(err.to_string().contains("does not contain a template.toml"));
+    assert!(!cache.join("missing").exists());
+    assert!(add_template_in(&cache, &url, None, Some("../escape")).is_err());
+    assert!(add_template_in(&cache, &url, Some("no-such-ref"), Some("templates/web")).is_err());
+    assert!(!cache.join("web").exists());
+
+    let commit = head_commit(&tmp.path().join("repo")).unwrap();
+    add_template_in(&cache, &url, Some(&commit), Some("templates/web")).unwrap();
+    let pin = load_pin(&cache.join("web")).unwrap().unwrap();
+    assert_eq!(pin.ref_kind, RefKind::Commit);
+    assert_eq!(head_commit_in(&cache, &cache.join("web")), Some(commit));
+}
+
+#[test]
+fn test_head_commit_stays_inside_the_cache_entry() {
+    let tmp = TempDir::new().unwrap();
+    let repo = tmp.path().join("repo");
+    let url = monorepo(&repo);
+    // A cache inside an unrelated repository, like a dotfiles checkout of ~/.config.
+    let cache = repo.join("cache");
+    add_template_in(&cache, &url, None, Some("templates/api")).unwrap();
+    assert!(head_commit_in(&cache, &cache.join("api/templates/api")).is_some());
+
+    write_manifest(&cache.join("plain"), "plain", "v1");
+    assert_eq!(head_commit_in(&cache, &cache.join("plain")), None);
+
+    write_manifest(&cache.join("packed/cloudrun"), "cloudrun", "v1");
+    fs::write(
+        cache.join("packed").join(PIN_FILE),
+        "url = \"file:///cloudrun.tar.gz\"\nref_kind = \"tarball\"\n",
+    )
+    .unwrap();
+    assert_eq!(head_commit_in(&cache, &cache.join("packed/cloudrun")), None);
+}
+
+#[test]
+fn test_update_moves_floating_refs_only() {
+    let tmp = TempDir::new().unwrap();
+    let repo = tmp.path().join("repo");
+    let url = monorepo(&repo);
+    let cache = tmp.path().join("cache");
+
+    add_template_in(&cache, &url, Some("v1"), Some("templates/api")).unwrap();
+    add_template_in(&cache, &url, Some("main"), Some("templates/web")).unwrap();
+    assert_eq!(
+        load_pin(&cache.join("web")).unwrap().unwrap().ref_kind,
+        RefKind::Branch
+    );
+
+    write_manifest(&repo.join("templates/api"), "api", "v2");
+    write_manifest(&repo.join("templates/web"), "web", "v2");
+    git(&repo, &["commit", "-q", "-am", "v2"]);
+
+    let report = update_templates_in(&cache).unwrap();
+    assert_eq!(report.updated, vec!["web"]);
+    assert_eq!(report.pinned, vec![("api".to_string(), "v1".to_string())]);
+    assert_eq!(description(&cache, "api"), "v1");
+    assert_eq!(description(&cache, "web"), "v2");
+}
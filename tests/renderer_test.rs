@@ -1,5 +1,6 @@
 use std::collections::HashMap;
-use tforge::renderer::Renderer;
+use tforge::renderer::{Renderer, shell_join, shell_quote};
+use tforge::vars::Value;
 
 #[test]
 fn test_render_simple_variable() {
@@ -42,3 +43,90 @@ fn test_render_missing_variable_errors() {
     let result = renderer.render_string("hello {{missing}}", &vars);
     assert!(result.is_err());
 }
+
+#[test]
+fn test_render_shell_quotes_values() {
+    let renderer = Renderer::new();
+    let mut vars = HashMap::new();
+    vars.insert("name".into(), "my-app".into());
+    vars.insert("title".into(), "it's; rm -rf ~".into());
+    vars.insert("flags".into(), "--quiet --verbose".into());
+    let result = renderer
+        .render_shell("echo {{name}} {{title}} {{flags|raw}}", &vars)
+        .unwrap();
+    assert_eq!(result, "echo my-app 'it'\\''s; rm -rf ~' --quiet --verbose");
+
+    // Plain rendering is unchanged, and `raw` is accepted there too.
+    let result = renderer
+        .render_string("{{title}} {{flags|raw}}", &vars)
+        .unwrap();
+    assert_eq!(result, "it's; rm -rf ~ --quiet --verbose");
+}
+
+#[test]
+fn test_render_shell_escapes_values_inside_existing_quotes() {
+    let renderer = Renderer::new();
+    let mut vars = HashMap::new();
+    vars.insert("dir".into(), "my dir".into());
+    vars.insert("title".into(), "say \"hi\" for $5".into());
+    vars.insert("name".into(), "it's".into());
+
+    let result = renderer
+        .render_shell("mkdir -p \"{{dir}}\" && cd \"$HOME/{{dir}}\"", &vars)
+        .unwrap();
+    assert_eq!(result, "mkdir -p \"my dir\" && cd \"$HOME/my dir\"");
+
+    let result = renderer
+        .render_shell("echo \"{{title}}\" '{{name}}' {{dir}}", &vars)
+        .unwrap();
+    assert_eq!(result, r#"echo "say \"hi\" for \$5" 'it'\''s' 'my dir'"#);
+
+    // `raw` still inserts the value untouched.
+    let result = renderer
+        .render_shell("echo \"{{dir|raw}}\"", &vars)
+        .unwrap();
+    assert_eq!(result, "echo \"my dir\"");
+
+    // Heredoc bodies: nothing expands in a quoted one, `$` does in an unquoted one.
+    let result = renderer
+        .render_shell(
+            "cat <<'EOF'\n{{title}}\nEOF\ncat <<EOF\n{{title}}\nEOF\necho {{dir}}",
+            &vars,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        "cat <<'EOF'\nsay \"hi\" for $5\nEOF\ncat <<EOF\nsay \"hi\" for \\$5\nEOF\necho 'my dir'"
+    );
+}
+
+#[test]
+fn test_shell_quote_and_join() {
+    assert_eq!(shell_quote("gs://bucket/path"), "gs://bucket/path");
+    assert_eq!(shell_quote(""), "''");
+    assert_eq!(shell_quote("$HOME"), "'$HOME'");
+    assert_eq!(shell_join(&["echo", "a b", "c"]), "echo 'a b' c");
+}
+
+#[test]
+fn test_render_typed_values() {
+    let renderer = Renderer::new();
+    let mut vars = HashMap::new();
+    vars.insert(
+        "services".into(),
+        Value::List(vec!["auth".into(), "my db".into()]),
+    );
+    vars.insert("verbose".into(), Value::Bool(false));
+    vars.insert("replicas".into(), Value::Integer(2));
+    let result = renderer
+        .render_string(
+            "{% for s in services %}[{{s}}]{% endfor %} {{services}} {% if not verbose %}quiet{% endif %} {{replicas * 2}}",
+            &vars,
+        )
+        .unwrap();
+    assert_eq!(result, "[auth][my db] auth,my db quiet 4");
+
+    // A list prints comma-joined and is quoted as one word.
+    let result = renderer.render_shell("echo {{services}}", &vars).unwrap();
+    assert_eq!(result, "echo 'auth,my db'");
+}
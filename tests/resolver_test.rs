@@ -1,4 +1,6 @@
-use tforge::resolver::resolve_order;
+use tempfile::TempDir;
+use tforge::resolver::{ResolveError, check_versions, resolve_layers, resolve_order};
+use tforge::source::TemplateSource;
 use tforge::types::TemplateManifest;
 
 fn make_manifest(name: &str, requires: Vec<&str>) -> TemplateManifest {
@@ -65,3 +67,201 @@ fn test_circular_dependency_errors() {
     let result = resolve_order(&templates);
     assert!(result.is_err());
 }
+
+#[test]
+fn test_order_follows_selection_then_alphabetical_dependencies() {
+    let templates = vec![
+        make_manifest("web", vec![]),
+        make_manifest("app", vec!["zeta", "beta"]),
+        make_manifest("zeta", vec![]),
+        make_manifest("beta", vec![]),
+        make_manifest("alpha", vec![]),
+    ];
+    for _ in 0..10 {
+        let order = resolve_order(&templates).unwrap();
+        assert_eq!(order, vec!["web", "beta", "zeta", "app", "alpha"]);
+    }
+}
+
+#[test]
+fn test_cycle_error_names_full_path() {
+    let templates = vec![
+        make_manifest("root", vec!["a"]),
+        make_manifest("a", vec!["b"]),
+        make_manifest("b", vec!["c"]),
+        make_manifest("c", vec!["a"]),
+    ];
+    let err = resolve_order(&templates).unwrap_err();
+    assert_eq!(
+        err,
+        ResolveError::Cycle {
+            path: vec!["a".into(), "b".into(), "c".into(), "a".into()]
+        }
+    );
+    assert_eq!(err.to_string(), "circular dependency: a -> b -> c -> a");
+}
+
+#[test]
+fn test_missing_dependency_is_reported() {
+    let templates = vec![make_manifest("gcp-cloudsql", vec!["gcp-project"])];
+    let err = resolve_order(&templates).unwrap_err();
+    assert_eq!(
+        err,
+        ResolveError::MissingDependency {
+            template: "gcp-cloudsql".into(),
+            dependency: "gcp-project".into(),
+        }
+    );
+}
+
+fn versioned(name: &str, version: &str, requires: Vec<&str>) -> TemplateManifest {
+    let mut manifest = make_manifest(name, requires);
+    manifest.template.version = Some(semver::Version::parse(version).unwrap());
+    manifest
+}
+
+#[test]
+fn test_version_constraints_are_satisfied() {
+    let templates = vec![
+        versioned("gcp-cloudsql", "1.0.0", vec!["gcp-project >=1.2, <2"]),
+        versioned("gcp-project", "1.4.1", vec![]),
+    ];
+    assert_eq!(
+        resolve_order(&templates).unwrap(),
+        vec!["gcp-project", "gcp-cloudsql"]
+    );
+}
+
+#[test]
+fn test_version_conflicts_are_all_reported() {
+    let templates = vec![
+        versioned("gcp-cloudsql", "1.0.0", vec!["gcp-project >=1.2, <2"]),
+        versioned("gcp-appengine", "1.0.0", vec!["gcp-project ^1.4"]),
+        make_manifest("firebase-flutter", vec!["flutter-app >=1", "gcp-project"]),
+        make_manifest("flutter-app", vec![]),
+        versioned("gcp-project", "2.0.0", vec![]),
+    ];
+    let err = check_versions(&templates).unwrap_err();
+    let ResolveError::VersionConflicts(conflicts) = &err else {
+        panic!("unexpected error: {err}");
+    };
+    assert_eq!(conflicts.len(), 3);
+    assert_eq!(
+        err.to_string(),
+        "template version conflicts:\n  \
+         - 'gcp-cloudsql' requires 'gcp-project >=1.2, <2', but version 2.0.0 is available\n  \
+         - 'gcp-appengine' requires 'gcp-project ^1.4', but version 2.0.0 is available\n  \
+         - 'firebase-flutter' requires 'flutter-app >=1', but the available template has no version"
+    );
+    assert_eq!(resolve_order(&templates).unwrap_err(), err);
+}
+
+#[test]
+fn test_layers_group_independent_templates() {
+    let templates = vec![
+        make_manifest("flutter-app", vec![]),
+        make_manifest("axum-server", vec![]),
+        make_manifest("gcp-cloudsql", vec!["gcp-project"]),
+        make_manifest("gcp-project", vec![]),
+        make_manifest("firebase-flutter", vec!["flutter-app", "gcp-project"]),
+    ];
+    let layers = resolve_layers(&templates).unwrap();
+    assert_eq!(
+        layers,
+        vec![
+            vec!["flutter-app", "axum-server", "gcp-project"],
+            vec!["gcp-cloudsql", "firebase-flutter"],
+        ]
+    );
+}
+
+#[test]
+fn test_layers_order_templates_sharing_captured_variables() {
+    let producer: TemplateManifest = toml::from_str(
+        r#"
+[template]
+name = "producer"
+description = "test"
+category = "test"
+provider = "command"
+
+[[steps]]
+type = "command"
+command = "echo 42"
+capture = { var = "answer" }
+"#,
+    )
+    .unwrap();
+    let consumer: TemplateManifest = toml::from_str(
+        r#"
+[template]
+name = "consumer"
+description = "test"
+category = "test"
+provider = "command"
+
+[[steps]]
+type = "command"
+command = "echo done"
+condition = "answer == '42'"
+"#,
+    )
+    .unwrap();
+    let layers = resolve_layers(&[producer, consumer, make_manifest("other", vec![])]).unwrap();
+    assert_eq!(layers, vec![vec!["producer", "other"], vec!["consumer"]]);
+}
+
+#[test]
+fn test_layers_see_variables_read_through_partials() {
+    let producer: TemplateManifest = toml::from_str(
+        r#"
+[template]
+name = "producer"
+description = "test"
+category = "test"
+provider = "command"
+
+[[steps]]
+type = "command"
+command = "echo 42"
+capture = { var = "answer" }
+"#,
+    )
+    .unwrap();
+    let consumer_toml = |name: &str, command: &str| {
+        format!(
+            r#"
+[template]
+name = "{name}"
+description = "test"
+category = "test"
+provider = "command"
+
+[[steps]]
+type = "command"
+command = '{command}'
+"#
+        )
+    };
+    let dir = TempDir::new().unwrap();
+    std::fs::create_dir_all(dir.path().join("partials")).unwrap();
+    std::fs::write(dir.path().join("partials/use.sh"), "echo {{answer}}\n").unwrap();
+
+    let mut consumer: TemplateManifest = toml::from_str(&consumer_toml(
+        "consumer",
+        "{% include \"partials/use.sh\" %}",
+    ))
+    .unwrap();
+    consumer.source = Some(TemplateSource::Local(dir.path().to_path_buf()));
+    // A partial named by an expression cannot be scanned, so it waits for every capture.
+    let mut dynamic: TemplateManifest =
+        toml::from_str(&consumer_toml("dynamic", "{% include script %}")).unwrap();
+    dynamic.source = Some(TemplateSource::Local(dir.path().to_path_buf()));
+
+    let layers =
+        resolve_layers(&[producer, consumer, dynamic, make_manifest("other", vec![])]).unwrap();
+    assert_eq!(
+        layers,
+        vec![vec!["producer", "other"], vec!["consumer", "dynamic"]]
+    );
+}
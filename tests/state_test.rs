@@ -1,5 +1,12 @@
 use tempfile::TempDir;
-use tforge::state::{PipelineState, StepState};
+use tforge::state::{PipelineState, StepChange, StepChangeKind, StepRecord, StepState, StepStatus};
+use tforge::types::{TemplateManifest, step_keys};
+
+fn failed(error: &str) -> StepRecord {
+    let mut record = StepRecord::new(StepStatus::Failed);
+    record.error = Some(error.to_string());
+    record
+}
 
 #[test]
 fn test_save_and_load_state() {
@@ -7,16 +14,27 @@ fn test_save_and_load_state() {
     let state_file = tmp.path().join(".tforge-state.json");
 
     let mut state = PipelineState::new();
-    state.mark_completed("flutter-app", 0);
-    state.mark_completed("flutter-app", 1);
-    state.mark_failed("gcp-project", 0, "quota exceeded");
+    state.record(
+        "flutter-app",
+        "step-0",
+        StepRecord::new(StepStatus::Completed),
+    );
+    state.record(
+        "flutter-app",
+        "step-1",
+        StepRecord::new(StepStatus::Completed),
+    );
+    state.record("gcp-project", "step-0", failed("quota exceeded"));
     state.save(&state_file).unwrap();
 
     let loaded = PipelineState::load(&state_file).unwrap();
-    assert_eq!(loaded.get("flutter-app", 0), StepState::Completed);
-    assert_eq!(loaded.get("flutter-app", 1), StepState::Completed);
-    assert!(matches!(loaded.get("gcp-project", 0), StepState::Failed(_)));
-    assert_eq!(loaded.get("gcp-project", 1), StepState::Pending);
+    assert_eq!(loaded.get("flutter-app", "step-0"), StepState::Completed);
+    assert_eq!(loaded.get("flutter-app", "step-1"), StepState::Completed);
+    assert!(matches!(
+        loaded.get("gcp-project", "step-0"),
+        StepState::Failed(_)
+    ));
+    assert_eq!(loaded.get("gcp-project", "step-1"), StepState::Pending);
 }
 
 #[test]
@@ -24,5 +42,247 @@ fn test_load_nonexistent_returns_empty() {
     let tmp = TempDir::new().unwrap();
     let state_file = tmp.path().join(".tforge-state.json");
     let state = PipelineState::load(&state_file).unwrap();
-    assert_eq!(state.get("anything", 0), StepState::Pending);
+    assert_eq!(state.get("anything", "step-0"), StepState::Pending);
+}
+
+#[test]
+fn test_captured_vars_round_trip() {
+    let tmp = TempDir::new().unwrap();
+    let state_file = tmp.path().join(".tforge-state.json");
+
+    let mut state = PipelineState::new();
+    state.set_var("gcp_project_number", "123456");
+    state.save(&state_file).unwrap();
+
+    let loaded = PipelineState::load(&state_file).unwrap();
+    assert_eq!(loaded.vars().get("gcp_project_number").unwrap(), "123456");
+}
+
+fn manifest(name: &str, steps: &[&str]) -> TemplateManifest {
+    let steps: String = steps
+        .iter()
+        .map(|step| format!("\n[[steps]]\ntype = \"command\"\n{step}\n"))
+        .collect();
+    toml::from_str(&format!(
+        r#"
+[template]
+name = "{name}"
+description = "test"
+category = "test"
+provider = "command"
+{steps}"#
+    ))
+    .unwrap()
+}
+
+#[test]
+fn test_load_state_without_vars_field() {
+    let tmp = TempDir::new().unwrap();
+    let state_file = tmp.path().join(".tforge-state.json");
+    std::fs::write(
+        &state_file,
+        r#"{"steps": {"flutter-app": {"0": "Completed"}}}"#,
+    )
+    .unwrap();
+
+    let tmpl = manifest("flutter-app", &["command = \"flutter create app\""]);
+    let loaded = PipelineState::load_for(&state_file, std::slice::from_ref(&tmpl)).unwrap();
+    assert_eq!(
+        loaded.get("flutter-app", &step_keys(&tmpl.steps)[0]),
+        StepState::Completed
+    );
+    assert!(loaded.vars().is_empty());
+}
+
+#[test]
+fn test_load_for_migrates_positional_keys() {
+    let tmp = TempDir::new().unwrap();
+    let state_file = tmp.path().join(".tforge-state.json");
+    std::fs::write(
+        &state_file,
+        r#"{
+  "steps": {
+    "app": {"0": "Completed", "1": {"Failed": "boom"}, "7": "Completed"},
+    "gone": {"0": "Completed"}
+  },
+  "attempts": {"app": {"1": 3}}
+}"#,
+    )
+    .unwrap();
+
+    let tmpl = manifest(
+        "app",
+        &[
+            "id = \"create\"\ncommand = \"touch a\"",
+            "command = \"touch b\"",
+        ],
+    );
+    let keys = step_keys(&tmpl.steps);
+    let state = PipelineState::load_for(&state_file, std::slice::from_ref(&tmpl)).unwrap();
+    assert_eq!(state.get("app", "create"), StepState::Completed);
+    assert!(matches!(state.get("app", &keys[1]), StepState::Failed(_)));
+    assert_eq!(state.step_record("app", &keys[1]).unwrap().attempts, 3);
+    assert!(!state.has_completed("gone"));
+    assert!(state.step_changes(&tmpl).is_empty());
+
+    // Saved files use step keys from now on and are not migrated again.
+    state.save(&state_file).unwrap();
+    let reloaded = PipelineState::load_for(&state_file, &[tmpl]).unwrap();
+    assert_eq!(reloaded.get("app", "create"), StepState::Completed);
+}
+
+#[test]
+fn test_step_keys_use_id_or_content_hash() {
+    let tmpl = manifest(
+        "app",
+        &[
+            "id = \"create\"\ncommand = \"touch a\"",
+            "command = \"touch b\"",
+            "command = \"touch b\"",
+        ],
+    );
+    let keys = step_keys(&tmpl.steps);
+    assert_eq!(keys[0], "create");
+    assert_eq!(keys[1], tmpl.steps[1].content_hash());
+    assert_eq!(keys[2], format!("{}#2", keys[1]));
+
+    let retried = manifest("app", &["command = \"touch b\"\nretries = 3\ntimeout = 60"]);
+    assert_eq!(retried.steps[0].content_hash(), keys[1]);
+}
+
+#[test]
+fn test_step_changes_after_template_update() {
+    let before = manifest(
+        "app",
+        &[
+            "id = \"create\"\ncommand = \"touch a\"",
+            "command = \"touch b\"",
+            "command = \"touch c\"",
+            "command = \"touch d\"",
+        ],
+    );
+    let mut state = PipelineState::new();
+    state.record_layout(&before);
+    for key in step_keys(&before.steps) {
+        state.record("app", &key, StepRecord::new(StepStatus::Completed));
+    }
+
+    let after = manifest(
+        "app",
+        &[
+            "id = \"create\"\ncommand = \"touch a2\"",
+            "command = \"touch new\"",
+            "command = \"touch b\"",
+            "command = \"touch c2\"",
+        ],
+    );
+    let keys = step_keys(&after.steps);
+    let kinds: Vec<(usize, StepChangeKind)> = state
+        .step_changes(&after)
+        .iter()
+        .map(|c| (c.step, c.kind))
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            (1, StepChangeKind::Changed),
+            (2, StepChangeKind::Added),
+            (4, StepChangeKind::Changed),
+            (3, StepChangeKind::Removed),
+        ]
+    );
+
+    state.forget_stale_steps(&after);
+    assert_eq!(state.get("app", "create"), StepState::Pending);
+    assert_eq!(state.get("app", &keys[1]), StepState::Pending);
+    assert_eq!(state.get("app", &keys[2]), StepState::Completed);
+    assert_eq!(state.get("app", &keys[3]), StepState::Pending);
+    assert!(state.step_changes(&after).is_empty());
+}
+
+#[test]
+fn test_step_change_explains_itself() {
+    let change = StepChange {
+        template: "gcp-project".into(),
+        step: 3,
+        key: "enable-apis".into(),
+        kind: StepChangeKind::Changed,
+    };
+    assert_eq!(
+        change.to_string(),
+        "[gcp-project] step 3 changed since the last run and will run again"
+    );
+}
+
+#[test]
+fn test_step_record_attempts_round_trip() {
+    let tmp = TempDir::new().unwrap();
+    let state_file = tmp.path().join(".tforge-state.json");
+
+    let mut state = PipelineState::new();
+    let mut record = StepRecord::new(StepStatus::Completed);
+    record.attempts = 3;
+    state.record("gcp-project", "step-0", record);
+    state.save(&state_file).unwrap();
+
+    let loaded = PipelineState::load(&state_file).unwrap();
+    assert_eq!(
+        loaded
+            .step_record("gcp-project", "step-0")
+            .unwrap()
+            .attempts,
+        3
+    );
+    assert!(loaded.step_record("gcp-project", "step-1").is_none());
+}
+
+#[test]
+fn test_clear_template_forgets_progress() {
+    let mut state = PipelineState::new();
+    state.record("gcp-cloudsql", "step-1", failed("boom"));
+    assert!(!state.has_completed("gcp-cloudsql"));
+    state.record(
+        "gcp-cloudsql",
+        "step-0",
+        StepRecord::new(StepStatus::Completed),
+    );
+    assert!(state.has_completed("gcp-cloudsql"));
+
+    state.clear_template("gcp-cloudsql");
+    assert!(!state.has_completed("gcp-cloudsql"));
+    assert_eq!(state.get("gcp-cloudsql", "step-1"), StepState::Pending);
+    assert!(state.step_record("gcp-cloudsql", "step-0").is_none());
+}
+
+#[test]
+fn test_load_migrates_status_entries_to_records() {
+    let tmp = TempDir::new().unwrap();
+    let state_file = tmp.path().join(".tforge-state.json");
+    std::fs::write(
+        &state_file,
+        r#"{
+  "schema_version": 1,
+  "steps": {"app": {"create": "Completed", "deploy": {"Failed": "quota exceeded"}}},
+  "attempts": {"app": {"deploy": 3}}
+}"#,
+    )
+    .unwrap();
+
+    let state = PipelineState::load(&state_file).unwrap();
+    assert_eq!(state.get("app", "create"), StepState::Completed);
+    assert_eq!(
+        state.get("app", "deploy"),
+        StepState::Failed("quota exceeded".into())
+    );
+    let record = state.step_record("app", "deploy").unwrap();
+    assert_eq!(record.attempts, 3);
+    assert_eq!(record.started_at, None);
+    assert_eq!(record.tforge_version, None);
+
+    state.save(&state_file).unwrap();
+    let saved: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&state_file).unwrap()).unwrap();
+    assert_eq!(saved["schema_version"], 2);
+    assert_eq!(saved["steps"]["app"]["deploy"]["status"], "failed");
+    assert!(saved.get("attempts").is_none());
 }
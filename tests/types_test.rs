@@ -1,4 +1,5 @@
-use tforge::types::{Provider, TemplateManifest};
+use tforge::types::{ParamDef, Provider, TemplateManifest, TemplateRequirement};
+use tforge::vars::Value;
 
 #[test]
 fn test_deserialize_command_template() {
@@ -27,6 +28,39 @@ command = "flutter create --org {{org}} {{project_name}}"
     assert!(manifest.parameters.contains_key("org"));
 }
 
+#[test]
+fn test_parse_template_requirement() {
+    let req: TemplateRequirement = "gcp-project >=1.2, <2".parse().unwrap();
+    assert_eq!(req.name, "gcp-project");
+    assert_eq!(req.to_string(), "gcp-project >=1.2, <2");
+    assert!(req.matches(Some(&semver::Version::new(1, 3, 0))));
+    assert!(!req.matches(Some(&semver::Version::new(2, 0, 0))));
+    assert!(!req.matches(None));
+
+    let bare: TemplateRequirement = "flutter-app".parse().unwrap();
+    assert_eq!(bare.version, None);
+    assert!(bare.matches(None));
+
+    let err = "gcp-project >=one"
+        .parse::<TemplateRequirement>()
+        .unwrap_err();
+    assert!(err.starts_with("invalid version requirement '>=one' for 'gcp-project'"));
+
+    let toml_err = toml::from_str::<TemplateManifest>(
+        r#"
+[template]
+name = "x"
+description = "x"
+category = "x"
+provider = "command"
+[dependencies]
+requires_templates = ["gcp-project >=one"]
+"#,
+    )
+    .unwrap_err();
+    assert!(toml_err.to_string().contains("invalid version requirement"));
+}
+
 #[test]
 fn test_deserialize_template_with_conditions() {
     let toml_str = r#"
@@ -49,9 +83,10 @@ command = "flutter pub add firebase_crashlytics"
 condition = "services contains 'crashlytics'"
 "#;
     let manifest: TemplateManifest = toml::from_str(toml_str).unwrap();
+    assert_eq!(manifest.dependencies.requires_templates.len(), 1);
     assert_eq!(
-        manifest.dependencies.requires_templates,
-        vec!["flutter-app"]
+        manifest.dependencies.requires_templates[0].name,
+        "flutter-app"
     );
     assert!(manifest.steps[0].condition.is_some());
 }
@@ -104,3 +139,152 @@ source = "files/"
     assert_eq!(manifest.steps[0].step_type, "bundled");
     assert_eq!(manifest.steps[0].action.as_deref(), Some("overlay"));
 }
+
+#[test]
+fn test_parameter_validation_rules() {
+    let param: ParamDef = toml::from_str(
+        r#"
+type = "string"
+prompt = "Project ID"
+pattern = "[a-z][a-z0-9-]*"
+min_length = 6
+max_length = 12
+"#,
+    )
+    .unwrap();
+    assert!(param.validate(&Value::from("my-app-dev")).is_ok());
+    assert_eq!(
+        param.validate(&Value::from("app")).unwrap_err(),
+        "must have at least 6 characters"
+    );
+    assert_eq!(
+        param
+            .validate(&Value::from("my-app-development"))
+            .unwrap_err(),
+        "must have at most 12 characters"
+    );
+    // The pattern has to match the whole value.
+    assert_eq!(
+        param.validate(&Value::from("My-App-Dev")).unwrap_err(),
+        "'My-App-Dev' does not match the pattern [a-z][a-z0-9-]*"
+    );
+
+    let param: ParamDef = toml::from_str(
+        r#"
+type = "integer"
+prompt = "Replicas"
+min = 1
+max = 10
+error_message = "Pick between 1 and 10 replicas"
+"#,
+    )
+    .unwrap();
+    assert!(param.validate(&Value::Integer(10)).is_ok());
+    assert_eq!(
+        param.validate(&Value::Integer(0)).unwrap_err(),
+        "Pick between 1 and 10 replicas"
+    );
+
+    let param: ParamDef = toml::from_str(
+        r#"
+type = "multi-select"
+prompt = "Platforms"
+options = ["ios", "android", "web"]
+min_length = 1
+"#,
+    )
+    .unwrap();
+    assert_eq!(
+        param.validate(&Value::List(Vec::new())).unwrap_err(),
+        "select at least 1 option"
+    );
+    assert!(
+        param
+            .validate(&Value::List(vec!["ios".into(), "web".into()]))
+            .is_ok()
+    );
+    assert_eq!(
+        param
+            .validate(&Value::List(vec!["ios".into(), "windows".into()]))
+            .unwrap_err(),
+        "'windows' is not one of the options: ios, android, web"
+    );
+
+    let param: ParamDef = toml::from_str(
+        r#"
+type = "select"
+prompt = "Database"
+options = ["postgres", "sqlite"]
+"#,
+    )
+    .unwrap();
+    assert!(param.validate(&Value::from("sqlite")).is_ok());
+    assert_eq!(
+        param.validate(&Value::from("mysql")).unwrap_err(),
+        "'mysql' is not one of the options: postgres, sqlite"
+    );
+
+    let err = toml::from_str::<ParamDef>("type = \"string\"\nprompt = \"x\"\npattern = \"[a-\"\n")
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("invalid pattern '[a-'"), "{err}");
+}
+
+#[test]
+fn test_parameters_array_form() {
+    let manifest: TemplateManifest = toml::from_str(
+        r#"
+[template]
+name = "wizard"
+description = "wizard"
+category = "test"
+provider = "command"
+
+[[parameters]]
+name = "environment"
+type = "select"
+prompt = "Environment"
+options = ["dev", "prod"]
+group = "Basics"
+help = "Where the service runs"
+
+[[parameters]]
+name = "replicas"
+type = "integer"
+prompt = "Replicas"
+order = 10
+"#,
+    )
+    .unwrap();
+
+    let environment = &manifest.parameters["environment"];
+    assert_eq!(environment.order, Some(0));
+    assert_eq!(environment.group.as_deref(), Some("Basics"));
+    assert_eq!(environment.help.as_deref(), Some("Where the service runs"));
+    assert_eq!(manifest.parameters["replicas"].order, Some(10));
+
+    let header =
+        "[template]\nname = \"w\"\ndescription = \"w\"\ncategory = \"t\"\nprovider = \"command\"\n";
+    let err = toml::from_str::<TemplateManifest>(&format!(
+        "{header}[[parameters]]\nname = \"a\"\ntype = \"string\"\n[[parameters]]\nname = \"a\"\ntype = \"bool\"\n"
+    ))
+    .unwrap_err()
+    .to_string();
+    assert!(err.contains("duplicate parameter 'a'"), "{err}");
+
+    let err =
+        toml::from_str::<TemplateManifest>(&format!("{header}[[parameters]]\ntype = \"string\"\n"))
+            .unwrap_err()
+            .to_string();
+    assert!(err.contains("parameter #1 needs a string `name`"), "{err}");
+
+    let err = toml::from_str::<TemplateManifest>(&format!(
+        "{header}[[parameters]]\nname = \"id\"\ntype = \"string\"\npattern = \"[a-\"\n"
+    ))
+    .unwrap_err()
+    .to_string();
+    assert!(
+        err.contains("parameter 'id': invalid pattern '[a-'"),
+        "{err}"
+    );
+}
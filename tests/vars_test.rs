@@ -0,0 +1,106 @@
+use tempfile::TempDir;
+use tforge::types::ParamType;
+use tforge::vars::{Value, load_var_file, parse_set_arg};
+
+#[test]
+fn test_parse_set_arg() {
+    assert_eq!(
+        parse_set_arg("gcp_project_id=my-app-dev").unwrap(),
+        ("gcp_project_id".to_string(), "my-app-dev".to_string())
+    );
+    assert_eq!(
+        parse_set_arg("filter=a=b").unwrap(),
+        ("filter".to_string(), "a=b".to_string())
+    );
+    assert_eq!(
+        parse_set_arg("billing_account=").unwrap(),
+        ("billing_account".to_string(), String::new())
+    );
+    assert!(parse_set_arg("no-equals").is_err());
+    assert!(parse_set_arg("=value").is_err());
+}
+
+#[test]
+fn test_load_toml_var_file() {
+    let tmp = TempDir::new().unwrap();
+    let path = tmp.path().join("vars.toml");
+    std::fs::write(
+        &path,
+        r#"
+gcp_project_id = "my-app-dev"
+deploy_now = true
+standard_max_instances = 5
+platforms = ["ios", "android"]
+"#,
+    )
+    .unwrap();
+
+    let vars = load_var_file(&path).unwrap();
+    assert_eq!(vars["gcp_project_id"], Value::String("my-app-dev".into()));
+    assert_eq!(vars["deploy_now"], Value::Bool(true));
+    assert_eq!(vars["standard_max_instances"], Value::Integer(5));
+    assert_eq!(
+        vars["platforms"],
+        Value::List(vec!["ios".into(), "android".into()])
+    );
+    // The string form is what shell commands and conditions see.
+    assert_eq!(vars["platforms"].to_string(), "ios,android");
+}
+
+#[test]
+fn test_load_json_var_file() {
+    let tmp = TempDir::new().unwrap();
+    let path = tmp.path().join("vars.json");
+    std::fs::write(
+        &path,
+        r#"{"region": "europe-west1", "services": ["auth", "firestore"], "deploy_now": false}"#,
+    )
+    .unwrap();
+
+    let vars = load_var_file(&path).unwrap();
+    assert_eq!(vars["region"], Value::String("europe-west1".into()));
+    assert_eq!(
+        vars["services"],
+        Value::List(vec!["auth".into(), "firestore".into()])
+    );
+    assert_eq!(vars["deploy_now"], Value::Bool(false));
+}
+
+#[test]
+fn test_load_var_file_rejects_tables() {
+    let tmp = TempDir::new().unwrap();
+    let path = tmp.path().join("vars.toml");
+    std::fs::write(&path, "[nested]\nkey = \"value\"\n").unwrap();
+    assert!(load_var_file(&path).is_err());
+}
+
+#[test]
+fn test_coerce_string_values_to_parameter_types() {
+    let coerce = |value: &str, param_type| Value::from(value).coerce(&param_type);
+    assert_eq!(
+        coerce("ios, android", ParamType::MultiSelect).unwrap(),
+        Value::List(vec!["ios".into(), "android".into()])
+    );
+    assert_eq!(
+        coerce("", ParamType::MultiSelect).unwrap(),
+        Value::List(Vec::new())
+    );
+    assert_eq!(coerce("true", ParamType::Bool).unwrap(), Value::Bool(true));
+    assert_eq!(
+        coerce("12", ParamType::Integer).unwrap(),
+        Value::Integer(12)
+    );
+    assert_eq!(
+        Value::Integer(3).coerce(&ParamType::String).unwrap(),
+        Value::String("3".into())
+    );
+
+    let err = coerce("yes", ParamType::Bool).unwrap_err();
+    assert_eq!(err.to_string(), "expected true or false, got 'yes'");
+    let err = coerce("1.5", ParamType::Integer).unwrap_err();
+    assert_eq!(err.to_string(), "expected an integer, got '1.5'");
+    let err = Value::List(vec!["a".into()])
+        .coerce(&ParamType::Bool)
+        .unwrap_err();
+    assert_eq!(err.to_string(), "expected a bool value, got 'a'");
+}